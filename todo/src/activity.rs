@@ -0,0 +1,143 @@
+use hyperware_process_lib::hyperapp;
+
+use crate::{
+    ActivityChange, ActivityEvent, ActivityPage, ActivitySource, DependencyChange, DueDateChange,
+    Entry, ListChange, PriorityChange, StatusChange, TodoState, SPIDER_PROCESS_ID,
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
+
+impl TodoState {
+    /// Appends an activity event for each tracked field that differs between the two images
+    /// of `entry_id`. `None` stands for the entry not existing on that side.
+    pub(crate) fn record_entry_activity(
+        &mut self,
+        entry_id: u64,
+        before: Option<&Entry>,
+        after: Option<&Entry>,
+    ) {
+        let changes = match (before, after) {
+            (None, Some(_)) if self.activity.iter().any(|a| a.entry_id == entry_id) => {
+                vec![ActivityChange::Restored]
+            }
+            (None, Some(_)) => vec![ActivityChange::Created],
+            (Some(_), None) => vec![ActivityChange::Deleted],
+            (Some(before), Some(after)) => entry_changes(before, after),
+            (None, None) => Vec::new(),
+        };
+        if changes.is_empty() {
+            return;
+        }
+
+        let source = request_source();
+        let ts = self.calendar().now_ts();
+        for change in changes {
+            let id = self.next_activity_id();
+            self.activity.push(ActivityEvent {
+                id,
+                entry_id,
+                ts,
+                source: source.clone(),
+                change,
+            });
+        }
+    }
+
+    /// Newest events first, for one entry or the whole workspace, starting below `before_id`.
+    pub(crate) fn activity_page(
+        &self,
+        entry_id: Option<u64>,
+        before_id: Option<u64>,
+        limit: Option<u32>,
+    ) -> ActivityPage {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize;
+        let mut matching = self.activity.iter().rev().filter(|event| {
+            entry_id.is_none_or(|id| event.entry_id == id)
+                && before_id.is_none_or(|before| event.id < before)
+        });
+        let events: Vec<ActivityEvent> = matching.by_ref().take(limit).cloned().collect();
+        let next_before_id = match matching.next() {
+            Some(_) => events.last().map(|event| event.id),
+            None => None,
+        };
+        ActivityPage {
+            events,
+            next_before_id,
+        }
+    }
+
+    fn next_activity_id(&mut self) -> u64 {
+        // State saved before the activity log existed deserializes this as 0
+        let id = self.next_activity_id.max(1);
+        self.next_activity_id = id + 1;
+        id
+    }
+}
+
+fn entry_changes(before: &Entry, after: &Entry) -> Vec<ActivityChange> {
+    let mut changes = Vec::new();
+    if before.status != after.status {
+        changes.push(ActivityChange::Status(StatusChange {
+            from: before.status.clone(),
+            to: after.status.clone(),
+        }));
+    }
+    if before.priority != after.priority {
+        changes.push(ActivityChange::Priority(PriorityChange {
+            from: before.priority.clone(),
+            to: after.priority.clone(),
+        }));
+    }
+    if before.assignees != after.assignees {
+        changes.push(ActivityChange::Assignees(ListChange {
+            added: added(&before.assignees, &after.assignees),
+            removed: added(&after.assignees, &before.assignees),
+        }));
+    }
+    if before.due_ts != after.due_ts {
+        changes.push(ActivityChange::DueDate(DueDateChange {
+            from: before.due_ts,
+            to: after.due_ts,
+        }));
+    }
+    if before.dependencies != after.dependencies {
+        changes.push(ActivityChange::Dependencies(DependencyChange {
+            added: added(&before.dependencies, &after.dependencies),
+            removed: added(&after.dependencies, &before.dependencies),
+        }));
+    }
+    if before.is_completed != after.is_completed {
+        changes.push(if after.is_completed {
+            ActivityChange::Completed
+        } else {
+            ActivityChange::Reopened
+        });
+    }
+    changes
+}
+
+/// Items of `new` that are not in `old`.
+fn added<T: PartialEq + Clone>(old: &[T], new: &[T]) -> Vec<T> {
+    new.iter()
+        .filter(|item| !old.contains(item))
+        .cloned()
+        .collect()
+}
+
+/// Who sent the request being handled: the browser UI (through the HTTP server), the
+/// Spider agent, or some other local process.
+pub(crate) fn request_source() -> ActivitySource {
+    let source = hyperapp::source();
+    let process = &source.process;
+    if process.to_string() == "http-server:distro:sys" {
+        return ActivitySource::Http;
+    }
+    let (name, package, publisher) = SPIDER_PROCESS_ID;
+    if process.process() == name && process.package() == package && process.publisher() == publisher
+    {
+        ActivitySource::Agent(source.to_string())
+    } else {
+        ActivitySource::Local(source.to_string())
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveTime};
+
+use crate::{
+    clock::Calendar, ActivityChange, ActivityEvent, DurationStats, Entry, EntryStatus,
+    FlowAnalytics, FlowPoint, ProjectFlow, StatusCount, StatusTime, ThroughputWeek, TodoState,
+};
+
+/// Window used when the caller gives no start.
+const DEFAULT_WINDOW_DAYS: i64 = 12 * 7;
+/// Longest window daily series are built for.
+const MAX_WINDOW_DAYS: i64 = 366;
+
+const STATUSES: [EntryStatus; 7] = [
+    EntryStatus::Backlog,
+    EntryStatus::UpNext,
+    EntryStatus::InProgress,
+    EntryStatus::Blocked,
+    EntryStatus::Review,
+    EntryStatus::Done,
+    EntryStatus::Archived,
+];
+
+/// Time spent in one status. `end` is `None` while the entry is still in it.
+pub(crate) struct Segment {
+    pub(crate) status: EntryStatus,
+    start: i64,
+    end: Option<i64>,
+}
+
+/// An entry's status history rebuilt from the activity log.
+pub(crate) struct Timeline<'a> {
+    pub(crate) entry: &'a Entry,
+    created_ts: Option<i64>,
+    started_ts: Option<i64>,
+    done_ts: Vec<i64>,
+    pub(crate) segments: Vec<Segment>,
+}
+
+impl TodoState {
+    /// Flow metrics for `[since_ts, until_ts]`, optionally limited to project `project_id`.
+    ///
+    /// Entries only have a history from the point the activity log started recording them;
+    /// older entries contribute what is known after that.
+    pub(crate) fn flow_analytics(
+        &self,
+        project_id: Option<u64>,
+        since_ts: Option<i64>,
+        until_ts: Option<i64>,
+    ) -> Result<FlowAnalytics, String> {
+        let calendar = self.calendar();
+        let (since_ts, until_ts) =
+            resolve_window(&calendar, since_ts, until_ts, DEFAULT_WINDOW_DAYS)?;
+        let timelines = self.timelines(project_id);
+
+        let mut lead_times = Vec::new();
+        let mut cycle_times = Vec::new();
+        for timeline in &timelines {
+            let is_done = timeline.entry.is_completed || timeline.entry.status == EntryStatus::Done;
+            let Some(done_ts) = timeline.done_ts.last().copied() else {
+                continue;
+            };
+            if !is_done || done_ts < since_ts || done_ts > until_ts {
+                continue;
+            }
+            if let Some(created_ts) = timeline.created_ts {
+                lead_times.push(done_ts - created_ts);
+            }
+            if let Some(started_ts) = timeline.started_ts.filter(|ts| *ts <= done_ts) {
+                cycle_times.push(done_ts - started_ts);
+            }
+        }
+
+        Ok(FlowAnalytics {
+            since_ts,
+            until_ts,
+            lead_time: duration_stats(lead_times),
+            cycle_time: duration_stats(cycle_times),
+            time_in_status: time_in_status(&timelines, since_ts, until_ts),
+            weekly_throughput: weekly_throughput(&timelines, &calendar, since_ts, until_ts),
+            cumulative_flow: cumulative_flow(&timelines, &calendar, since_ts, until_ts),
+        })
+    }
+}
+
+impl TodoState {
+    /// Status timelines of the live entries, optionally limited to project `project_id`.
+    pub(crate) fn timelines(&self, project_id: Option<u64>) -> Vec<Timeline<'_>> {
+        let mut events: HashMap<u64, Vec<&ActivityEvent>> = HashMap::new();
+        for event in &self.activity {
+            events.entry(event.entry_id).or_default().push(event);
+        }
+        self.entries
+            .iter()
+            .filter(|e| project_id.is_none_or(|id| e.project_id == Some(id)))
+            .map(|e| {
+                let history = events.get(&e.id).map(Vec::as_slice).unwrap_or_default();
+                build_timeline(e, history)
+            })
+            .collect()
+    }
+}
+
+/// Fills in a missing window (ending now, `default_days` long) and checks its bounds.
+pub(crate) fn resolve_window(
+    calendar: &Calendar,
+    since_ts: Option<i64>,
+    until_ts: Option<i64>,
+    default_days: i64,
+) -> Result<(i64, i64), String> {
+    let until_ts = until_ts.unwrap_or(calendar.now_ts()).min(calendar.now_ts());
+    let since_ts = since_ts.unwrap_or(until_ts - Duration::days(default_days).num_milliseconds());
+    if since_ts >= until_ts {
+        return Err("The reporting window must end after it starts.".to_string());
+    }
+    if until_ts - since_ts > Duration::days(MAX_WINDOW_DAYS).num_milliseconds() {
+        return Err(format!(
+            "Reporting windows are limited to {MAX_WINDOW_DAYS} days."
+        ));
+    }
+    Ok((since_ts, until_ts))
+}
+
+/// The last instant of each local day from `since_ts` through `until_ts`; the final day
+/// ends at `until_ts`.
+pub(crate) fn day_ends(calendar: &Calendar, since_ts: i64, until_ts: i64) -> Vec<i64> {
+    let (Some(first), Some(last)) = (calendar.date_of(since_ts), calendar.date_of(until_ts)) else {
+        return Vec::new();
+    };
+    let mut ends = Vec::new();
+    let mut day = first;
+    while day <= last {
+        let end_of_day = day
+            .succ_opt()
+            .and_then(|next| calendar.timestamp(next, NaiveTime::MIN))
+            .map_or(until_ts, |ts| (ts - 1).min(until_ts));
+        ends.push(end_of_day);
+        day += Duration::days(1);
+    }
+    ends
+}
+
+fn build_timeline<'a>(entry: &'a Entry, history: &[&ActivityEvent]) -> Timeline<'a> {
+    let mut timeline = Timeline {
+        entry,
+        created_ts: None,
+        started_ts: None,
+        done_ts: Vec::new(),
+        segments: Vec::new(),
+    };
+    // Until the first transition the status is unknown; it is then the transition's `from`,
+    // or the entry's current status if it never changed
+    let mut status: Option<EntryStatus> = None;
+    let mut open_since: Option<i64> = None;
+    for event in history {
+        match &event.change {
+            ActivityChange::Created => {
+                timeline.created_ts.get_or_insert(event.ts);
+                open_since = Some(event.ts);
+            }
+            ActivityChange::Restored => open_since = Some(event.ts),
+            ActivityChange::Status(change) => {
+                if let Some(start) = open_since {
+                    timeline.segments.push(Segment {
+                        status: change.from.clone(),
+                        start,
+                        end: Some(event.ts),
+                    });
+                }
+                if change.to == EntryStatus::InProgress {
+                    timeline.started_ts.get_or_insert(event.ts);
+                }
+                if change.to == EntryStatus::Done {
+                    timeline.done_ts.push(event.ts);
+                }
+                status = Some(change.to.clone());
+                open_since = Some(event.ts);
+            }
+            ActivityChange::Deleted => {
+                if let Some(start) = open_since.take() {
+                    timeline.segments.push(Segment {
+                        status: status.clone().unwrap_or_else(|| entry.status.clone()),
+                        start,
+                        end: Some(event.ts),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = open_since {
+        timeline.segments.push(Segment {
+            status: status.unwrap_or_else(|| entry.status.clone()),
+            start,
+            end: None,
+        });
+    }
+    timeline
+}
+
+fn duration_stats(mut durations: Vec<i64>) -> DurationStats {
+    durations.sort_unstable();
+    let percentile = |q: f64| {
+        let rank = ((q * durations.len() as f64).ceil() as usize).max(1);
+        durations.get(rank - 1).copied()
+    };
+    DurationStats {
+        count: durations.len() as u32,
+        mean_ms: (!durations.is_empty())
+            .then(|| durations.iter().sum::<i64>() / durations.len() as i64),
+        median_ms: percentile(0.5),
+        p85_ms: percentile(0.85),
+    }
+}
+
+fn time_in_status(timelines: &[Timeline], since_ts: i64, until_ts: i64) -> Vec<StatusTime> {
+    STATUSES
+        .iter()
+        .map(|status| {
+            let mut total_ms = 0;
+            let mut entries = 0;
+            for timeline in timelines {
+                let spent: i64 = timeline
+                    .segments
+                    .iter()
+                    .filter(|s| s.status == *status)
+                    .map(|s| {
+                        let end = s.end.unwrap_or(until_ts).min(until_ts);
+                        (end - s.start.max(since_ts)).max(0)
+                    })
+                    .sum();
+                if spent > 0 {
+                    total_ms += spent;
+                    entries += 1;
+                }
+            }
+            StatusTime {
+                status: status.clone(),
+                total_ms,
+                entries,
+            }
+        })
+        .collect()
+}
+
+/// Distinct entries that reached Done in each week of the window, including empty weeks.
+fn weekly_throughput(
+    timelines: &[Timeline],
+    calendar: &Calendar,
+    since_ts: i64,
+    until_ts: i64,
+) -> Vec<ThroughputWeek> {
+    let (Some(first), Some(last)) = (calendar.date_of(since_ts), calendar.date_of(until_ts)) else {
+        return Vec::new();
+    };
+    let mut weeks = Vec::new();
+    let mut week = calendar.week_start_of(first);
+    while week <= last {
+        let completed = timelines
+            .iter()
+            .filter(|timeline| {
+                timeline.done_ts.iter().any(|ts| {
+                    (since_ts..=until_ts).contains(ts)
+                        && calendar
+                            .date_of(*ts)
+                            .is_some_and(|date| calendar.week_start_of(date) == week)
+                })
+            })
+            .count() as u32;
+        weeks.push(ThroughputWeek {
+            week_start_ts: calendar.timestamp(week, NaiveTime::MIN).unwrap_or_default(),
+            completed,
+        });
+        week += Duration::weeks(1);
+    }
+    weeks
+}
+
+/// Per project, how many entries sat in each status at the end of every day in the window.
+fn cumulative_flow(
+    timelines: &[Timeline],
+    calendar: &Calendar,
+    since_ts: i64,
+    until_ts: i64,
+) -> Vec<ProjectFlow> {
+    let ends = day_ends(calendar, since_ts, until_ts);
+    let mut projects: Vec<(Option<String>, Option<u64>)> = Vec::new();
+    for timeline in timelines {
+        if !projects
+            .iter()
+            .any(|(_, id)| *id == timeline.entry.project_id)
+        {
+            projects.push((timeline.entry.project.clone(), timeline.entry.project_id));
+        }
+    }
+    projects.sort();
+
+    projects
+        .into_iter()
+        .map(|(project, project_id)| {
+            let members: Vec<&Timeline> = timelines
+                .iter()
+                .filter(|t| t.entry.project_id == project_id)
+                .collect();
+            let points = ends
+                .iter()
+                .map(|&end_of_day| FlowPoint {
+                    ts: end_of_day,
+                    counts: STATUSES
+                        .iter()
+                        .map(|status| StatusCount {
+                            status: status.clone(),
+                            count: members
+                                .iter()
+                                .filter(|t| status_at(t, end_of_day) == Some(status))
+                                .count() as u32,
+                        })
+                        .collect(),
+                })
+                .collect();
+            ProjectFlow {
+                project,
+                project_id,
+                points,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn status_at<'a>(timeline: &'a Timeline, ts: i64) -> Option<&'a EntryStatus> {
+    timeline
+        .segments
+        .iter()
+        .find(|s| s.start <= ts && s.end.is_none_or(|end| ts < end))
+        .map(|s| &s.status)
+}
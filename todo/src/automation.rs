@@ -0,0 +1,411 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    query::parse_query, search_index::DocKey, AutomationPreview, AutomationRule,
+    AutomationRuleDraft, AutomationRun, Entry, EntryDraft, EntryPatch, EntrySaveError, RuleAction,
+    RuleCondition, RuleEntryTemplate, RuleFiring, RuleTrigger, TodoState, WsServerMessage,
+};
+
+/// Firings allowed in response to one change. A rule acts on an entry at most once per
+/// change already, so this only cuts off long chains of rules setting each other off.
+const MAX_RULE_FIRINGS: usize = 50;
+
+/// A change the rules react to.
+#[allow(clippy::large_enum_variant)]
+enum RuleEvent {
+    /// `before` is `None` when the entry was just created.
+    Entry {
+        before: Option<Entry>,
+        entry_id: u64,
+    },
+    Note(u64),
+}
+
+impl TodoState {
+    fn next_rule_id(&mut self) -> u64 {
+        // State saved before automation rules existed deserializes the counter as 0
+        let id = self.next_rule_id.max(1);
+        self.next_rule_id = id + 1;
+        id
+    }
+
+    pub(crate) fn save_rule_draft(
+        &mut self,
+        draft: AutomationRuleDraft,
+    ) -> Result<AutomationRule, String> {
+        self.validate_rule(&draft)?;
+        let rule = if let Some(id) = draft.id {
+            let rule = self
+                .automation_rules
+                .iter_mut()
+                .find(|r| r.id == id)
+                .ok_or_else(|| "Automation rule not found".to_string())?;
+            *rule = rule_from_draft(id, draft);
+            rule.clone()
+        } else {
+            let rule = rule_from_draft(self.next_rule_id(), draft);
+            self.automation_rules.push(rule.clone());
+            rule
+        };
+        self.publish_change(|seq| WsServerMessage::AutomationRuleUpdated {
+            rule: rule.clone(),
+            seq,
+        });
+        Ok(rule)
+    }
+
+    pub(crate) fn remove_rule(&mut self, rule_id: u64) -> Result<(), String> {
+        if !self.automation_rules.iter().any(|r| r.id == rule_id) {
+            return Err("Automation rule not found".to_string());
+        }
+        self.automation_rules.retain(|r| r.id != rule_id);
+        self.publish_change(|seq| WsServerMessage::AutomationRuleRemoved { rule_id, seq });
+        Ok(())
+    }
+
+    /// Runs the rules after `entry_id` was saved; `before` is its copy from before the save.
+    pub(crate) fn automate_entry_change(
+        &mut self,
+        before: Option<Entry>,
+        entry_id: u64,
+    ) -> AutomationRun {
+        self.run_rules(RuleEvent::Entry { before, entry_id })
+    }
+
+    pub(crate) fn automate_note_change(&mut self, note_id: u64) -> AutomationRun {
+        self.run_rules(RuleEvent::Note(note_id))
+    }
+
+    /// Saves `draft` and runs the rules inside a batch that is then rolled back, so the
+    /// preview goes through exactly the code a real save would.
+    pub(crate) fn preview_automation(
+        &mut self,
+        draft: EntryDraft,
+        rule: Option<AutomationRuleDraft>,
+    ) -> Result<AutomationPreview, String> {
+        let rules = self.automation_rules.clone();
+        if let Some(rule) = rule {
+            self.validate_rule(&rule)?;
+            let id = rule.id.unwrap_or(self.next_rule_id.max(1));
+            // A rule being tried out runs even while it is switched off
+            let rule = AutomationRule {
+                enabled: true,
+                ..rule_from_draft(id, rule)
+            };
+            match self.automation_rules.iter_mut().find(|r| r.id == id) {
+                Some(saved) => *saved = rule,
+                None => self.automation_rules.push(rule),
+            }
+        }
+
+        let checkpoint = self.checkpoint();
+        self.begin_batch();
+        let before = draft
+            .id
+            .and_then(|id| self.entries.iter().find(|e| e.id == id))
+            .cloned();
+        let preview = self
+            .save_entry_draft(draft)
+            .map_err(EntrySaveError::into_message)
+            .map(|entry| {
+                let run = self.automate_entry_change(before, entry.id);
+                let entry = run
+                    .entries
+                    .iter()
+                    .find(|e| e.id == entry.id)
+                    .cloned()
+                    .unwrap_or(entry);
+                AutomationPreview { entry, run }
+            });
+        self.roll_back(checkpoint);
+        self.automation_rules = rules;
+        preview
+    }
+
+    /// Fires every enabled rule that `event` sets off, then the rules set off by what those
+    /// firings changed, and so on. A rule acts on an entry at most once per run, so rules
+    /// undoing each other's changes can't loop.
+    fn run_rules(&mut self, event: RuleEvent) -> AutomationRun {
+        let mut run = AutomationRun {
+            firings: Vec::new(),
+            entries: Vec::new(),
+            stopped_early: false,
+        };
+        let rules: Vec<AutomationRule> = self
+            .automation_rules
+            .iter()
+            .filter(|r| r.enabled)
+            .cloned()
+            .collect();
+        if rules.is_empty() {
+            return run;
+        }
+
+        let mut fired: HashSet<(u64, u64)> = HashSet::new();
+        let mut touched: Vec<u64> = Vec::new();
+        let mut events = VecDeque::from([event]);
+        'events: while let Some(event) = events.pop_front() {
+            for rule in &rules {
+                for entry_id in self.rule_subjects(&rule.trigger, &event) {
+                    if fired.contains(&(rule.id, entry_id))
+                        || !self.conditions_hold(&rule.conditions, entry_id)
+                    {
+                        continue;
+                    }
+                    if run.firings.len() == MAX_RULE_FIRINGS {
+                        run.stopped_early = true;
+                        break 'events;
+                    }
+                    fired.insert((rule.id, entry_id));
+
+                    let before = self.entries.iter().find(|e| e.id == entry_id).cloned();
+                    let firing = self.fire_rule(rule, entry_id);
+                    let revision = self
+                        .entries
+                        .iter()
+                        .find(|e| e.id == entry_id)
+                        .map(|e| e.revision);
+                    if before.as_ref().map(|e| e.revision) != revision {
+                        touched.push(entry_id);
+                        events.push_back(RuleEvent::Entry { before, entry_id });
+                    }
+                    for id in &firing.created_entry_ids {
+                        touched.push(*id);
+                        events.push_back(RuleEvent::Entry {
+                            before: None,
+                            entry_id: *id,
+                        });
+                    }
+                    run.firings.push(firing);
+                }
+            }
+        }
+
+        for entry_id in touched {
+            if run.entries.iter().any(|e| e.id == entry_id) {
+                continue;
+            }
+            if let Some(entry) = self.entries.iter().find(|e| e.id == entry_id) {
+                run.entries.push(entry.clone());
+            }
+        }
+        run
+    }
+
+    /// The entries a rule with `trigger` acts on in response to `event`.
+    fn rule_subjects(&self, trigger: &RuleTrigger, event: &RuleEvent) -> Vec<u64> {
+        let (before, entry_id) = match event {
+            RuleEvent::Note(note_id) => {
+                if *trigger != RuleTrigger::NoteSaved {
+                    return Vec::new();
+                }
+                return self
+                    .entries
+                    .iter()
+                    .filter(|e| e.note_ids.contains(note_id))
+                    .map(|e| e.id)
+                    .collect();
+            }
+            RuleEvent::Entry { before, entry_id } => (before.as_ref(), *entry_id),
+        };
+        let Some(entry) = self.entries.iter().find(|e| e.id == entry_id) else {
+            return Vec::new();
+        };
+        let completed_now = entry.is_completed && !before.is_some_and(|b| b.is_completed);
+
+        let applies = match trigger {
+            RuleTrigger::EntrySaved => true,
+            RuleTrigger::EntryCreated => before.is_none(),
+            RuleTrigger::StatusChanged(status) => {
+                entry.status == *status && before.is_none_or(|b| b.status != *status)
+            }
+            RuleTrigger::EntryCompleted => completed_now,
+            RuleTrigger::SubtasksCompleted => {
+                return match entry.parent_id {
+                    Some(parent_id)
+                        if completed_now
+                            && self
+                                .entries
+                                .iter()
+                                .filter(|e| e.parent_id == Some(parent_id))
+                                .all(|e| e.is_completed) =>
+                    {
+                        vec![parent_id]
+                    }
+                    _ => Vec::new(),
+                };
+            }
+            RuleTrigger::NoteSaved => false,
+        };
+        if applies {
+            vec![entry_id]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn conditions_hold(&self, conditions: &[RuleCondition], entry_id: u64) -> bool {
+        let Some(entry) = self.entries.iter().find(|e| e.id == entry_id) else {
+            return false;
+        };
+        let calendar = self.calendar();
+        conditions.iter().all(|condition| match condition {
+            RuleCondition::StatusIs(status) => entry.status == *status,
+            RuleCondition::PriorityIs(priority) => entry.priority == *priority,
+            RuleCondition::TimescaleIs(timescale) => entry.timescale == *timescale,
+            RuleCondition::HasTag(tag) => entry
+                .tags
+                .iter()
+                .any(|t| t.to_lowercase() == tag.to_lowercase()),
+            RuleCondition::AssignedTo(assignee) => entry
+                .assignees
+                .iter()
+                .any(|a| a.to_lowercase() == assignee.to_lowercase()),
+            RuleCondition::Unassigned => entry.assignees.is_empty(),
+            RuleCondition::InProject(project_id) => entry.project_id == Some(*project_id),
+            RuleCondition::Matches(query) => parse_query(query, &calendar).is_ok_and(|query| {
+                query.matches_entry(entry, &calendar, &|text| {
+                    self.text_matches(text).contains(&DocKey::Entry(entry.id))
+                })
+            }),
+        })
+    }
+
+    /// Applies the actions of `rule` to `entry_id`: the field changes as one patch, then the
+    /// entries to create. Stops at the first action that fails.
+    fn fire_rule(&mut self, rule: &AutomationRule, entry_id: u64) -> RuleFiring {
+        let mut firing = RuleFiring {
+            rule_id: rule.id,
+            rule_name: rule.name.clone(),
+            entry_id,
+            created_entry_ids: Vec::new(),
+            error: None,
+        };
+        let mut patch = EntryPatch::default();
+        let mut changes_entry = false;
+        let mut templates = Vec::new();
+        for action in &rule.actions {
+            changes_entry |= !matches!(action, RuleAction::CreateEntry(_));
+            match action {
+                RuleAction::SetStatus(status) => patch.status = Some(status.clone()),
+                RuleAction::SetPriority(priority) => patch.priority = Some(priority.clone()),
+                RuleAction::AddAssignee(assignee) => patch.add_assignees.push(assignee.clone()),
+                RuleAction::AddTag(tag) => patch.add_tags.push(tag.clone()),
+                RuleAction::RemoveTag(tag) => patch.remove_tags.push(tag.clone()),
+                RuleAction::LinkNote(note_id) => patch.add_note_ids.push(*note_id),
+                RuleAction::CreateEntry(template) => templates.push(template),
+            }
+        }
+
+        if changes_entry {
+            if let Err(err) = self.apply_entry_patch(entry_id, patch) {
+                firing.error = Some(err.into_message());
+                return firing;
+            }
+        }
+        let project_id = self
+            .entries
+            .iter()
+            .find(|e| e.id == entry_id)
+            .and_then(|e| e.project_id);
+        for template in templates {
+            match self.save_entry_draft(template_draft(template, entry_id, project_id)) {
+                Ok(entry) => firing.created_entry_ids.push(entry.id),
+                Err(err) => {
+                    firing.error = Some(err.into_message());
+                    break;
+                }
+            }
+        }
+        firing
+    }
+
+    fn validate_rule(&self, draft: &AutomationRuleDraft) -> Result<(), String> {
+        if draft.name.trim().is_empty() {
+            return Err("Automation rules require a name.".to_string());
+        }
+        if draft.actions.is_empty() {
+            return Err("Automation rules need at least one action.".to_string());
+        }
+        let calendar = self.calendar();
+        for condition in &draft.conditions {
+            match condition {
+                RuleCondition::HasTag(name) | RuleCondition::AssignedTo(name)
+                    if name.trim().is_empty() =>
+                {
+                    return Err("Rule conditions need a tag or assignee name.".to_string());
+                }
+                RuleCondition::InProject(project_id)
+                    if !self.projects.iter().any(|p| p.id == *project_id) =>
+                {
+                    return Err(format!("Project {project_id} not found."));
+                }
+                RuleCondition::Matches(query) => {
+                    parse_query(query, &calendar)
+                        .map_err(|err| format!("Invalid rule query: {err}"))?;
+                }
+                _ => {}
+            }
+        }
+        for action in &draft.actions {
+            match action {
+                RuleAction::AddAssignee(name)
+                | RuleAction::AddTag(name)
+                | RuleAction::RemoveTag(name)
+                    if name.trim().is_empty() =>
+                {
+                    return Err("Rule actions need a tag or assignee name.".to_string());
+                }
+                RuleAction::LinkNote(note_id) if !self.notes.iter().any(|n| n.id == *note_id) => {
+                    return Err(format!("Note {note_id} not found."));
+                }
+                RuleAction::CreateEntry(template) if template.title.trim().is_empty() => {
+                    return Err("Entries created by a rule need a title.".to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn rule_from_draft(id: u64, draft: AutomationRuleDraft) -> AutomationRule {
+    AutomationRule {
+        id,
+        name: draft.name.trim().to_string(),
+        enabled: draft.enabled,
+        trigger: draft.trigger,
+        conditions: draft.conditions,
+        actions: draft.actions,
+    }
+}
+
+fn template_draft(
+    template: &RuleEntryTemplate,
+    entry_id: u64,
+    project_id: Option<u64>,
+) -> EntryDraft {
+    EntryDraft {
+        id: None,
+        title: template.title.clone(),
+        summary: String::new(),
+        description: template.description.clone(),
+        project: None,
+        project_id,
+        sprint_id: None,
+        state: None,
+        status: template.status.clone(),
+        priority: template.priority.clone(),
+        due_ts: None,
+        start_ts: None,
+        estimate_minutes: None,
+        dependencies: Vec::new(),
+        parent_id: template.as_subtask.then_some(entry_id),
+        note_ids: Vec::new(),
+        assignees: template.assignees.clone(),
+        tags: template.tags.clone(),
+        recurrence: None,
+        series_scope: None,
+        expected_revision: None,
+    }
+}
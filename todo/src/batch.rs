@@ -0,0 +1,125 @@
+use crate::{
+    BatchOperation, BatchOutcome, BatchReport, Entry, EntrySaveError, Note, NoteSaveError,
+    NoteVersion, Project, Sprint, TodoState, TrashItem,
+};
+
+/// The parts of the state a batch can change, restored if any operation fails.
+pub(crate) struct Checkpoint {
+    entries: Vec<Entry>,
+    notes: Vec<Note>,
+    trash: Vec<TrashItem>,
+    note_versions: Vec<NoteVersion>,
+    projects: Vec<Project>,
+    sprints: Vec<Sprint>,
+    next_entry_id: u64,
+    next_note_id: u64,
+    next_project_id: u64,
+    change_seq: u64,
+}
+
+impl TodoState {
+    /// Runs every operation, even after one fails, so the report covers the whole batch.
+    /// Changes are broadcast once as a single `Batch` message, and only if all succeeded.
+    pub(crate) fn apply_batch_operations(
+        &mut self,
+        operations: Vec<BatchOperation>,
+    ) -> BatchReport {
+        let checkpoint = self.checkpoint();
+        self.begin_batch();
+
+        let outcomes: Vec<BatchOutcome> = operations
+            .into_iter()
+            .map(|operation| self.apply_batch_operation(operation))
+            .collect();
+        let committed = !outcomes.iter().any(|outcome| {
+            matches!(
+                outcome,
+                BatchOutcome::Failed(_)
+                    | BatchOutcome::EntryConflict(_)
+                    | BatchOutcome::NoteConflict(_)
+            )
+        });
+
+        if committed {
+            self.commit_batch();
+        } else {
+            self.roll_back(checkpoint);
+        }
+        BatchReport {
+            committed,
+            outcomes,
+        }
+    }
+
+    pub(crate) fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            entries: self.entries.clone(),
+            notes: self.notes.clone(),
+            trash: self.trash.clone(),
+            note_versions: self.note_versions.clone(),
+            projects: self.projects.clone(),
+            sprints: self.sprints.clone(),
+            next_entry_id: self.next_entry_id,
+            next_note_id: self.next_note_id,
+            next_project_id: self.next_project_id,
+            change_seq: self.change_seq,
+        }
+    }
+
+    /// Drops the held-back changes of the current batch and puts `checkpoint` back.
+    pub(crate) fn roll_back(&mut self, checkpoint: Checkpoint) {
+        self.discard_batch();
+        self.entries = checkpoint.entries;
+        self.notes = checkpoint.notes;
+        self.trash = checkpoint.trash;
+        self.note_versions = checkpoint.note_versions;
+        self.projects = checkpoint.projects;
+        self.sprints = checkpoint.sprints;
+        self.next_entry_id = checkpoint.next_entry_id;
+        self.next_note_id = checkpoint.next_note_id;
+        self.next_project_id = checkpoint.next_project_id;
+        self.change_seq = checkpoint.change_seq;
+        self.rebuild_search_index();
+        self.journal
+            .reset(&self.entries, &self.notes, &self.projects);
+    }
+
+    fn apply_batch_operation(&mut self, operation: BatchOperation) -> BatchOutcome {
+        match operation {
+            BatchOperation::SaveEntry(draft) => entry_outcome(self.save_entry_draft(draft)),
+            BatchOperation::PatchEntry(op) => {
+                entry_outcome(self.apply_entry_patch(op.entry_id, op.patch))
+            }
+            BatchOperation::CompleteEntry(op) => self
+                .set_entry_completion(op.entry_id, op.completed)
+                .map_or_else(BatchOutcome::Failed, BatchOutcome::Entry),
+            BatchOperation::DeleteEntry(op) => self
+                .remove_entry(op.entry_id, op.children)
+                .map_or_else(BatchOutcome::Failed, |_| BatchOutcome::Deleted),
+            BatchOperation::SaveNote(draft) => note_outcome(self.save_note_draft(draft)),
+            BatchOperation::PatchNote(op) => {
+                note_outcome(self.apply_note_patch(op.note_id, op.patch))
+            }
+            BatchOperation::DeleteNote(note_id) => self
+                .remove_note(note_id)
+                .map_or_else(BatchOutcome::Failed, |_| BatchOutcome::Deleted),
+        }
+    }
+}
+
+fn entry_outcome(result: Result<Entry, EntrySaveError>) -> BatchOutcome {
+    match result {
+        Ok(entry) => BatchOutcome::Entry(entry),
+        Err(EntrySaveError::Invalid(message)) => BatchOutcome::Failed(message),
+        Err(EntrySaveError::Conflict(conflict)) => BatchOutcome::EntryConflict(conflict),
+        Err(EntrySaveError::WipLimit(usage)) => BatchOutcome::Failed(usage.describe()),
+    }
+}
+
+fn note_outcome(result: Result<Note, NoteSaveError>) -> BatchOutcome {
+    match result {
+        Ok(note) => BatchOutcome::Note(note),
+        Err(NoteSaveError::Invalid(message)) => BatchOutcome::Failed(message),
+        Err(NoteSaveError::Conflict(conflict)) => BatchOutcome::NoteConflict(conflict),
+    }
+}
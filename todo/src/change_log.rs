@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+
+use crate::{search_index::DocKey, TodoState, WsServerMessage};
+
+/// How many change events are kept for clients resuming with `since_seq`.
+const CHANGE_LOG_CAPACITY: usize = 1000;
+
+/// Recent change events, oldest first. Not persisted: after a restart every resume that
+/// reaches back before the restart falls back to a snapshot.
+#[derive(Debug, Default)]
+pub(crate) struct ChangeLog {
+    records: VecDeque<(u64, WsServerMessage)>,
+    /// Changes held back while a batch is applied; `None` outside a batch.
+    pending: Option<Vec<(u64, WsServerMessage)>>,
+}
+
+impl ChangeLog {
+    fn push(&mut self, seq: u64, message: WsServerMessage) {
+        if self.records.len() == CHANGE_LOG_CAPACITY {
+            self.records.pop_front();
+        }
+        self.records.push_back((seq, message));
+    }
+
+    pub(crate) fn is_batching(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Every change after `since_seq`, or `None` if some of them were already dropped.
+    fn since(&self, since_seq: u64, current_seq: u64) -> Option<Vec<&WsServerMessage>> {
+        if since_seq > current_seq {
+            // The client saw a sequence this state never reached
+            return None;
+        }
+        if since_seq < current_seq {
+            let oldest = self.records.front().map(|(seq, _)| *seq)?;
+            if oldest > since_seq + 1 {
+                return None;
+            }
+        }
+        Some(
+            self.records
+                .iter()
+                .filter(|(seq, _)| *seq > since_seq)
+                .map(|(_, message)| message)
+                .collect(),
+        )
+    }
+}
+
+impl TodoState {
+    /// Stamps a change event with the next sequence number, logs it and broadcasts it.
+    pub(crate) fn publish_change(&mut self, build: impl FnOnce(u64) -> WsServerMessage) {
+        self.change_seq += 1;
+        let message = build(self.change_seq);
+        self.journal.observe(&message);
+        if let Some(pending) = &mut self.change_log.pending {
+            pending.push((self.change_seq, message));
+            return;
+        }
+        self.broadcast(&message);
+        self.change_log.push(self.change_seq, message);
+    }
+
+    /// Holds back change events until `commit_batch` or `discard_batch`.
+    pub(crate) fn begin_batch(&mut self) {
+        self.change_log.pending = Some(Vec::new());
+    }
+
+    /// Logs the held-back changes and broadcasts them as one `Batch` message, keeping only
+    /// the latest change per record. Live views are refreshed once at the end.
+    pub(crate) fn commit_batch(&mut self) {
+        let pending = self.change_log.pending.take().unwrap_or_default();
+        let mut changes: Vec<WsServerMessage> = Vec::new();
+        for (seq, message) in pending {
+            let key = change_key(&message);
+            changes.retain(|change| key.is_none() || change_key(change) != key);
+            changes.push(message.clone());
+            self.change_log.push(seq, message);
+        }
+        if !changes.is_empty() {
+            self.broadcast(&WsServerMessage::Batch {
+                changes,
+                seq: self.change_seq,
+            });
+        }
+        self.refresh_live_views();
+    }
+
+    /// Drops the held-back changes of a batch that was rolled back.
+    pub(crate) fn discard_batch(&mut self) {
+        self.change_log.pending = None;
+    }
+
+    /// Entries changed or removed after `since_seq`, or `None` when the log no longer
+    /// reaches back that far.
+    pub(crate) fn entries_changed_since(&self, since_seq: u64) -> Option<Vec<u64>> {
+        let messages = self.change_log.since(since_seq, self.change_seq)?;
+        let mut entry_ids = Vec::new();
+        for message in messages {
+            if let Some(DocKey::Entry(id)) = change_key(message) {
+                if !entry_ids.contains(&id) {
+                    entry_ids.push(id);
+                }
+            }
+        }
+        Some(entry_ids)
+    }
+
+    /// Brings a (re)connecting channel up to date, replaying the changes after `since_seq`
+    /// when the log still holds all of them and sending a full snapshot otherwise.
+    pub(crate) fn resume_channel(&self, channel_id: u32, since_seq: Option<u64>) {
+        let missed = since_seq.and_then(|since| self.change_log.since(since, self.change_seq));
+        match missed {
+            Some(messages) => {
+                for message in messages {
+                    self.send_ws_message(channel_id, message);
+                }
+            }
+            None => self.send_snapshot(channel_id),
+        }
+    }
+}
+
+/// The record a change event describes, so later changes can supersede earlier ones.
+pub(crate) fn change_key(message: &WsServerMessage) -> Option<DocKey> {
+    match message {
+        WsServerMessage::EntryUpdated { entry, .. } => Some(DocKey::Entry(entry.id)),
+        WsServerMessage::EntryRemoved { entry_id, .. } => Some(DocKey::Entry(*entry_id)),
+        WsServerMessage::NoteUpdated { note, .. } => Some(DocKey::Note(note.id)),
+        WsServerMessage::NoteRemoved { note_id, .. } => Some(DocKey::Note(*note_id)),
+        _ => None,
+    }
+}
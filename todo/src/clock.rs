@@ -0,0 +1,374 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+use hyperware_process_lib::{hyperapp, our, println, Request as ProcessRequest};
+use serde_json::json;
+
+use crate::{
+    now_ts, refresh_entry_timescale, wip::validate_wip_limits, TimescaleRefresh, TodoState,
+    UserSettings,
+};
+
+/// Upper bound on a single timer sleep so time zone changes are picked up within the hour.
+const MAX_REFRESH_SLEEP_MS: i64 = 60 * 60 * 1000;
+
+/// The user's calendar pinned to a single instant: time zone, week start and working days.
+///
+/// Timescales and recurrences are computed against a `Calendar` rather than the runtime's
+/// local clock so results follow the user's settings and stay reproducible.
+pub(crate) struct Calendar {
+    tz: Tz,
+    week_start: Weekday,
+    working_days: Vec<Weekday>,
+    now_ts: i64,
+    /// The running sprint and its last day.
+    sprint: Option<(u64, NaiveDate)>,
+}
+
+impl Calendar {
+    pub(crate) fn new(settings: &UserSettings, now_ts: i64) -> Self {
+        Self {
+            // Settings are validated on save; fall back to UTC for anything older
+            tz: settings.time_zone.parse().unwrap_or(Tz::UTC),
+            week_start: settings.week_start.to_chrono(),
+            working_days: settings
+                .working_days
+                .iter()
+                .map(|day| day.to_chrono())
+                .collect(),
+            now_ts,
+            sprint: None,
+        }
+    }
+
+    pub(crate) fn with_sprint(self, sprint_id: u64, end: NaiveDate) -> Self {
+        Self {
+            sprint: Some((sprint_id, end)),
+            ..self
+        }
+    }
+
+    pub(crate) fn in_sprint(&self, sprint_id: u64) -> bool {
+        self.sprint.is_some_and(|(id, _)| id == sprint_id)
+    }
+
+    pub(crate) fn sprint_end(&self) -> Option<NaiveDate> {
+        self.sprint.map(|(_, end)| end)
+    }
+
+    pub(crate) fn now_ts(&self) -> i64 {
+        self.now_ts
+    }
+
+    pub(crate) fn local_datetime(&self, ts: i64) -> Option<DateTime<Tz>> {
+        DateTime::from_timestamp_millis(ts).map(|dt| dt.with_timezone(&self.tz))
+    }
+
+    pub(crate) fn date_of(&self, ts: i64) -> Option<NaiveDate> {
+        self.local_datetime(ts).map(|dt| dt.date_naive())
+    }
+
+    pub(crate) fn today(&self) -> NaiveDate {
+        self.date_of(self.now_ts).unwrap_or_default()
+    }
+
+    pub(crate) fn week_start_of(&self, date: NaiveDate) -> NaiveDate {
+        let offset = (7 + date.weekday().num_days_from_monday()
+            - self.week_start.num_days_from_monday())
+            % 7;
+        date - Duration::days(offset as i64)
+    }
+
+    pub(crate) fn end_of_week(&self, date: NaiveDate) -> NaiveDate {
+        self.week_start_of(date) + Duration::days(6)
+    }
+
+    /// Rolls `date` forward to the next working day; every day counts when no mask is set.
+    pub(crate) fn next_working_day(&self, date: NaiveDate) -> NaiveDate {
+        if self.working_days.is_empty() {
+            return date;
+        }
+        (0..7)
+            .map(|offset| date + Duration::days(offset))
+            .find(|day| self.working_days.contains(&day.weekday()))
+            .unwrap_or(date)
+    }
+
+    /// Start of the next local day.
+    pub(crate) fn next_midnight_ts(&self) -> i64 {
+        self.today()
+            .succ_opt()
+            .and_then(|tomorrow| self.timestamp(tomorrow, NaiveTime::MIN))
+            .unwrap_or(self.now_ts + Duration::days(1).num_milliseconds())
+    }
+
+    /// Converts a wall-clock time to a timestamp. Ambiguous times pick the earlier instant and
+    /// times skipped by a DST jump move an hour later.
+    pub(crate) fn timestamp(&self, date: NaiveDate, time: NaiveTime) -> Option<i64> {
+        let naive = date.and_time(time);
+        self.tz
+            .from_local_datetime(&naive)
+            .earliest()
+            .or_else(|| {
+                self.tz
+                    .from_local_datetime(&(naive + Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|dt| dt.timestamp_millis())
+    }
+}
+
+impl TodoState {
+    pub(crate) fn calendar(&self) -> Calendar {
+        let calendar = Calendar::new(&self.settings, now_ts());
+        let sprint_end = self
+            .running_sprint()
+            .and_then(|sprint| Some((sprint.id, calendar.date_of(sprint.end_ts)?)));
+        match sprint_end {
+            Some((sprint_id, end)) => calendar.with_sprint(sprint_id, end),
+            None => calendar,
+        }
+    }
+
+    /// Recomputes every entry's timescale, returning the ids whose bucket changed.
+    pub(crate) fn refresh_all_timescales(&mut self) -> Vec<u64> {
+        let calendar = self.calendar();
+        let mut changed = Vec::new();
+        for entry in &mut self.entries {
+            let before = entry.timescale;
+            refresh_entry_timescale(entry, &calendar);
+            if entry.timescale != before {
+                changed.push(entry.id);
+            }
+        }
+        changed
+    }
+
+    /// Refreshes every timescale and broadcasts only the entries whose bucket changed.
+    pub(crate) fn refresh_timescales_now(&mut self) -> TimescaleRefresh {
+        let changed_entry_ids = self.refresh_all_timescales();
+        for entry_id in &changed_entry_ids {
+            self.broadcast_entry(*entry_id);
+        }
+        // Date terms in smart list queries move on with the day, changed entries or not
+        self.invalidate_smart_lists();
+        self.refresh_smart_lists();
+        TimescaleRefresh {
+            changed_entry_ids,
+            next_refresh_ts: self.calendar().next_midnight_ts(),
+        }
+    }
+}
+
+/// Runs the timescale refresh at each local midnight for the lifetime of the process.
+///
+/// The spawned task cannot borrow the state, so it asks the process to refresh itself through
+/// the `RefreshTimescales` endpoint, which also reports when the next midnight falls.
+pub(crate) fn schedule_timescale_refresh(first_refresh_ts: i64) {
+    hyperapp::spawn(async move {
+        let mut next_refresh_ts = first_refresh_ts;
+        loop {
+            let delay_ms = (next_refresh_ts - now_ts()).clamp(0, MAX_REFRESH_SLEEP_MS);
+            if hyperapp::sleep(delay_ms as u64).await.is_err() {
+                println!("timescale refresh timer failed; retrying");
+            }
+
+            let body = json!({ "RefreshTimescales": null });
+            let Ok(bytes) = serde_json::to_vec(&body) else {
+                return;
+            };
+            let request = ProcessRequest::to(our()).body(bytes).expects_response(30);
+            next_refresh_ts =
+                match hyperapp::send::<Result<TimescaleRefresh, String>>(request).await {
+                    Ok(Ok(refresh)) => refresh.next_refresh_ts,
+                    _ => now_ts() + MAX_REFRESH_SLEEP_MS,
+                };
+        }
+    });
+}
+
+pub(crate) fn validate_settings(settings: &UserSettings) -> Result<(), String> {
+    settings
+        .time_zone
+        .parse::<Tz>()
+        .map_err(|_| format!("Unknown time zone '{}'", settings.time_zone))?;
+    if settings.trash_retention_days == 0 {
+        return Err("Trash retention must be at least one day.".to_string());
+    }
+    validate_wip_limits(&settings.wip_limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Timelike, Utc};
+
+    use super::*;
+    use crate::recurrence::next_occurrence_ts;
+    use crate::{
+        compute_timescale, last_day_of_month, DayOfWeek, EntryTimescale, RecurrenceFrequency,
+        RecurrenceRule,
+    };
+
+    fn settings(time_zone: &str, week_start: DayOfWeek) -> UserSettings {
+        UserSettings {
+            time_zone: time_zone.to_string(),
+            week_start,
+            working_days: Vec::new(),
+            ..UserSettings::default()
+        }
+    }
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> i64 {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn week_start_controls_this_week() {
+        // Saturday 2026-10-17, due Sunday 2026-10-18
+        let now = utc(2026, 10, 17, 12, 0);
+        let due = Some(utc(2026, 10, 18, 12, 0));
+        let monday = Calendar::new(&settings("UTC", DayOfWeek::Monday), now);
+        let sunday = Calendar::new(&settings("UTC", DayOfWeek::Sunday), now);
+        assert_eq!(compute_timescale(due, &monday), EntryTimescale::ThisWeek);
+        assert_eq!(compute_timescale(due, &sunday), EntryTimescale::ThisMonth);
+    }
+
+    #[test]
+    fn time_zone_decides_the_local_day() {
+        let now = utc(2026, 10, 17, 23, 30);
+        let due = Some(utc(2026, 10, 18, 1, 0));
+        let utc_calendar = Calendar::new(&settings("UTC", DayOfWeek::Monday), now);
+        let pacific = Calendar::new(&settings("America/Los_Angeles", DayOfWeek::Monday), now);
+        assert_eq!(
+            compute_timescale(due, &utc_calendar),
+            EntryTimescale::ThisWeek
+        );
+        assert_eq!(compute_timescale(due, &pacific), EntryTimescale::Today);
+    }
+
+    #[test]
+    fn today_spans_dst_transitions() {
+        let new_york = settings("America/New_York", DayOfWeek::Monday);
+
+        // Spring forward: 2026-03-08 is only 23 hours long
+        let calendar = Calendar::new(&new_york, utc(2026, 3, 8, 6, 0));
+        let late_today = Some(utc(2026, 3, 9, 3, 30));
+        let just_after = Some(utc(2026, 3, 9, 4, 30));
+        assert_eq!(
+            compute_timescale(late_today, &calendar),
+            EntryTimescale::Today
+        );
+        assert_ne!(
+            compute_timescale(just_after, &calendar),
+            EntryTimescale::Today
+        );
+
+        // Fall back: 2026-11-01 is 25 hours long
+        let calendar = Calendar::new(&new_york, utc(2026, 11, 1, 4, 30));
+        assert_eq!(
+            compute_timescale(Some(utc(2026, 11, 2, 4, 30)), &calendar),
+            EntryTimescale::Today
+        );
+        assert_eq!(
+            compute_timescale(Some(utc(2026, 11, 1, 3, 30)), &calendar),
+            EntryTimescale::Overdue
+        );
+    }
+
+    #[test]
+    fn month_and_year_boundaries() {
+        // Thursday 2026-12-31; the Monday-start week runs until Sunday 2027-01-03
+        let calendar = Calendar::new(
+            &settings("UTC", DayOfWeek::Monday),
+            utc(2026, 12, 31, 12, 0),
+        );
+        assert_eq!(
+            compute_timescale(Some(utc(2027, 1, 2, 9, 0)), &calendar),
+            EntryTimescale::ThisWeek
+        );
+        assert_eq!(
+            compute_timescale(Some(utc(2027, 1, 5, 9, 0)), &calendar),
+            EntryTimescale::Later
+        );
+        assert_eq!(
+            last_day_of_month(2026, 12),
+            NaiveDate::from_ymd_opt(2026, 12, 31).unwrap()
+        );
+        assert_eq!(
+            last_day_of_month(2028, 2),
+            NaiveDate::from_ymd_opt(2028, 2, 29).unwrap()
+        );
+    }
+
+    #[test]
+    fn next_midnight_follows_the_local_day() {
+        // Spring forward in New York: the next local midnight is 04:00 UTC, not 05:00
+        let calendar = Calendar::new(
+            &settings("America/New_York", DayOfWeek::Monday),
+            utc(2026, 3, 8, 18, 0),
+        );
+        assert_eq!(calendar.next_midnight_ts(), utc(2026, 3, 9, 4, 0));
+    }
+
+    #[test]
+    fn weekend_due_dates_roll_to_next_working_day() {
+        let mut weekdays = settings("UTC", DayOfWeek::Monday);
+        weekdays.working_days = vec![
+            DayOfWeek::Monday,
+            DayOfWeek::Tuesday,
+            DayOfWeek::Wednesday,
+            DayOfWeek::Thursday,
+            DayOfWeek::Friday,
+        ];
+        // Friday 2026-10-16, due Saturday: effectively due next Monday
+        let calendar = Calendar::new(&weekdays, utc(2026, 10, 16, 12, 0));
+        assert_eq!(
+            compute_timescale(Some(utc(2026, 10, 17, 12, 0)), &calendar),
+            EntryTimescale::ThisMonth
+        );
+    }
+
+    #[test]
+    fn running_sprint_sits_between_week_and_month() {
+        // Wednesday 2026-10-14; the sprint runs until Friday 2026-10-23
+        let calendar = Calendar::new(
+            &settings("UTC", DayOfWeek::Monday),
+            utc(2026, 10, 14, 12, 0),
+        )
+        .with_sprint(1, NaiveDate::from_ymd_opt(2026, 10, 23).unwrap());
+        assert_eq!(
+            compute_timescale(Some(utc(2026, 10, 18, 12, 0)), &calendar),
+            EntryTimescale::ThisWeek
+        );
+        assert_eq!(
+            compute_timescale(Some(utc(2026, 10, 23, 12, 0)), &calendar),
+            EntryTimescale::ThisSprint
+        );
+        assert_eq!(
+            compute_timescale(Some(utc(2026, 10, 24, 12, 0)), &calendar),
+            EntryTimescale::ThisMonth
+        );
+    }
+
+    #[test]
+    fn weekly_recurrence_keeps_wall_clock_time_across_dst() {
+        let calendar = Calendar::new(
+            &settings("America/New_York", DayOfWeek::Monday),
+            utc(2026, 3, 1, 0, 0),
+        );
+        let rule = RecurrenceRule {
+            frequency: RecurrenceFrequency::Weekly,
+            interval: 1,
+            by_weekday: Vec::new(),
+            by_month_day: Vec::new(),
+            until_ts: None,
+            count: None,
+        };
+        // Thursday 2026-03-05 09:00 EST
+        let next = next_occurrence_ts(&rule, utc(2026, 3, 5, 14, 0), 1, &calendar).unwrap();
+        let local = calendar.local_datetime(next).unwrap();
+        assert_eq!((local.day(), local.hour()), (12, 9));
+        assert_eq!(next, utc(2026, 3, 12, 13, 0));
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{
+    analytics::{day_ends, resolve_window, status_at, Timeline},
+    AssigneeWorkload, BurndownPoint, EntryPriority, EntryStatus, EntryTimescale,
+    PriorityCompletion, ProjectDashboard, TimescaleCount, TodoState,
+};
+
+/// Burndown window used when the caller gives no start.
+const DEFAULT_WINDOW_DAYS: i64 = 14;
+
+const PRIORITIES: [EntryPriority; 3] = [
+    EntryPriority::High,
+    EntryPriority::Medium,
+    EntryPriority::Low,
+];
+
+const OPEN_TIMESCALES: [EntryTimescale; 7] = [
+    EntryTimescale::Overdue,
+    EntryTimescale::Today,
+    EntryTimescale::ThisWeek,
+    EntryTimescale::ThisSprint,
+    EntryTimescale::ThisMonth,
+    EntryTimescale::Later,
+    EntryTimescale::Someday,
+];
+
+impl TodoState {
+    /// Burndown over the window plus current timescale, priority and assignee breakdowns
+    /// for project `project_id`, or for every entry when it is `None`. Archived entries are
+    /// left out throughout. Estimate totals saturate rather than overflow.
+    pub(crate) fn project_dashboard(
+        &self,
+        project_id: Option<u64>,
+        since_ts: Option<i64>,
+        until_ts: Option<i64>,
+    ) -> Result<ProjectDashboard, String> {
+        let project = match project_id {
+            Some(id) => Some(
+                self.projects
+                    .iter()
+                    .find(|p| p.id == id)
+                    .map(|p| p.name.clone())
+                    .ok_or_else(|| "Project not found".to_string())?,
+            ),
+            None => None,
+        };
+        let calendar = self.calendar();
+        let (since_ts, until_ts) =
+            resolve_window(&calendar, since_ts, until_ts, DEFAULT_WINDOW_DAYS)?;
+        let timelines: Vec<Timeline> = self
+            .timelines(project_id)
+            .into_iter()
+            .filter(|t| t.entry.status != EntryStatus::Archived)
+            .collect();
+
+        let burndown = day_ends(&calendar, since_ts, until_ts)
+            .into_iter()
+            .map(|ts| {
+                let mut point = BurndownPoint {
+                    ts,
+                    open: 0,
+                    done: 0,
+                    open_estimate_minutes: 0,
+                    done_estimate_minutes: 0,
+                };
+                for timeline in &timelines {
+                    let estimate = timeline.entry.estimate_minutes.unwrap_or(0);
+                    match done_at(timeline, ts) {
+                        Some(true) => {
+                            point.done += 1;
+                            point.done_estimate_minutes =
+                                point.done_estimate_minutes.saturating_add(estimate);
+                        }
+                        Some(false) => {
+                            point.open += 1;
+                            point.open_estimate_minutes =
+                                point.open_estimate_minutes.saturating_add(estimate);
+                        }
+                        None => {}
+                    }
+                }
+                point
+            })
+            .collect();
+
+        let open: Vec<&Timeline> = timelines.iter().filter(|t| !t.entry.is_completed).collect();
+        let timescales = OPEN_TIMESCALES
+            .iter()
+            .map(|timescale| TimescaleCount {
+                timescale: *timescale,
+                count: open
+                    .iter()
+                    .filter(|t| t.entry.timescale == *timescale)
+                    .count() as u32,
+            })
+            .collect();
+
+        let completion_by_priority = PRIORITIES
+            .iter()
+            .map(|priority| {
+                let total = timelines
+                    .iter()
+                    .filter(|t| t.entry.priority == *priority)
+                    .count() as u32;
+                let done = timelines
+                    .iter()
+                    .filter(|t| t.entry.priority == *priority && t.entry.is_completed)
+                    .count() as u32;
+                PriorityCompletion {
+                    priority: priority.clone(),
+                    total,
+                    done,
+                    completion_percent: (u64::from(done) * 100)
+                        .checked_div(u64::from(total))
+                        .unwrap_or(0) as u8,
+                }
+            })
+            .collect();
+
+        let mut workload: Vec<AssigneeWorkload> = Vec::new();
+        for timeline in &timelines {
+            let entry = timeline.entry;
+            let assignees: Vec<Option<String>> = if entry.assignees.is_empty() {
+                vec![None]
+            } else {
+                entry.assignees.iter().cloned().map(Some).collect()
+            };
+            for assignee in assignees {
+                let idx = match workload.iter().position(|w| w.assignee == assignee) {
+                    Some(idx) => idx,
+                    None => {
+                        workload.push(AssigneeWorkload {
+                            assignee,
+                            open: 0,
+                            open_estimate_minutes: 0,
+                            overdue: 0,
+                            completed_in_window: 0,
+                        });
+                        workload.len() - 1
+                    }
+                };
+                let load = &mut workload[idx];
+                if entry.is_completed {
+                    if completed_between(timeline, since_ts, until_ts) {
+                        load.completed_in_window += 1;
+                    }
+                } else {
+                    load.open += 1;
+                    load.open_estimate_minutes = load
+                        .open_estimate_minutes
+                        .saturating_add(entry.estimate_minutes.unwrap_or(0));
+                    if entry.timescale == EntryTimescale::Overdue {
+                        load.overdue += 1;
+                    }
+                }
+            }
+        }
+        workload.sort_by(|a, b| {
+            b.open
+                .cmp(&a.open)
+                .then_with(|| a.assignee.cmp(&b.assignee))
+        });
+
+        Ok(ProjectDashboard {
+            project,
+            project_id,
+            since_ts,
+            until_ts,
+            burndown,
+            overdue: open
+                .iter()
+                .filter(|t| t.entry.timescale == EntryTimescale::Overdue)
+                .count() as u32,
+            timescales,
+            completion_by_priority,
+            workload,
+        })
+    }
+}
+
+/// Whether the entry counted as done at `ts`, or `None` if it did not exist yet, had been
+/// deleted or was archived. Entries with no recorded history go by `completed_at_ts`.
+fn done_at(timeline: &Timeline, ts: i64) -> Option<bool> {
+    if timeline.segments.is_empty() {
+        return Some(
+            timeline
+                .entry
+                .completed_at_ts
+                .is_some_and(|done| done <= ts),
+        );
+    }
+    match status_at(timeline, ts)? {
+        EntryStatus::Archived => None,
+        status => Some(*status == EntryStatus::Done),
+    }
+}
+
+fn completed_between(timeline: &Timeline, since_ts: i64, until_ts: i64) -> bool {
+    timeline
+        .entry
+        .completed_at_ts
+        .is_some_and(|ts| (since_ts..=until_ts).contains(&ts))
+}
@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+
+use crate::{EntryStatus, TodoState};
+
+impl TodoState {
+    /// Rejects unknown dependency ids and dependency cycles through `entry_id`.
+    pub(crate) fn validate_dependencies(
+        &self,
+        entry_id: Option<u64>,
+        dependencies: &[u64],
+    ) -> Result<(), String> {
+        let unknown: Vec<String> = dependencies
+            .iter()
+            .filter(|id| !self.entries.iter().any(|e| e.id == **id))
+            .map(|id| id.to_string())
+            .collect();
+        if !unknown.is_empty() {
+            return Err(format!("Unknown dependency ids: {}", unknown.join(", ")));
+        }
+
+        let Some(entry_id) = entry_id else {
+            // A brand-new entry has no dependents yet, so it cannot close a cycle
+            return Ok(());
+        };
+        if dependencies.contains(&entry_id) {
+            return Err("An entry cannot depend on itself.".to_string());
+        }
+        for dependency in dependencies {
+            if let Some(mut path) = self.dependency_path(*dependency, entry_id) {
+                path.insert(0, entry_id);
+                let path: Vec<String> = path.iter().map(|id| id.to_string()).collect();
+                return Err(format!("Dependency cycle detected: {}", path.join(" -> ")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds a chain of dependencies leading from `from` to `to`, inclusive of both ends.
+    fn dependency_path(&self, from: u64, to: u64) -> Option<Vec<u64>> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![vec![from]];
+        while let Some(path) = stack.pop() {
+            let current = *path.last()?;
+            if current == to {
+                return Some(path);
+            }
+            if !visited.insert(current) {
+                continue;
+            }
+            if let Some(entry) = self.entries.iter().find(|e| e.id == current) {
+                for next in &entry.dependencies {
+                    let mut extended = path.clone();
+                    extended.push(*next);
+                    stack.push(extended);
+                }
+            }
+        }
+        None
+    }
+
+    /// Moves an entry into `Blocked` while any dependency is incomplete and restores the
+    /// previous status once they are all done. Returns whether the status changed.
+    pub(crate) fn refresh_blocked_status(&mut self, entry_id: u64) -> bool {
+        let blocked = match self.entries.iter().find(|e| e.id == entry_id) {
+            Some(entry) => entry.dependencies.iter().any(|dep| {
+                self.entries
+                    .iter()
+                    .any(|other| other.id == *dep && !other.is_completed)
+            }),
+            None => return false,
+        };
+        let Some(entry) = self.entries.iter_mut().find(|e| e.id == entry_id) else {
+            return false;
+        };
+
+        if entry.is_completed || entry.status == EntryStatus::Archived {
+            entry.blocked_from = None;
+            return false;
+        }
+        if blocked && entry.status != EntryStatus::Blocked {
+            entry.blocked_from = Some(entry.status.clone());
+            entry.status = EntryStatus::Blocked;
+            return true;
+        }
+        if !blocked && entry.status == EntryStatus::Blocked {
+            if let Some(previous) = entry.blocked_from.take() {
+                entry.status = previous;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Re-evaluates every entry that depends on `entry_id`, returning those whose status changed.
+    pub(crate) fn refresh_dependents(&mut self, entry_id: u64) -> Vec<u64> {
+        let dependents: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|e| e.dependencies.contains(&entry_id))
+            .map(|e| e.id)
+            .collect();
+        dependents
+            .into_iter()
+            .filter(|id| self.refresh_blocked_status(*id))
+            .collect()
+    }
+
+    /// Drops dangling references to `removed` ids, returning the entries that were touched.
+    pub(crate) fn remove_dependency_references(&mut self, removed: &[u64]) -> Vec<u64> {
+        let mut touched = Vec::new();
+        for entry in &mut self.entries {
+            let before = entry.dependencies.len();
+            entry.dependencies.retain(|id| !removed.contains(id));
+            if entry.dependencies.len() != before {
+                touched.push(entry.id);
+            }
+        }
+        for id in &touched {
+            self.refresh_blocked_status(*id);
+        }
+        touched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_entry;
+
+    /// Entries 1 to 3 where each depends on the next; 3 depends on nothing.
+    fn chain() -> TodoState {
+        let mut state = TodoState::default();
+        let mut first = test_entry(1, "First");
+        first.dependencies = vec![2];
+        let mut second = test_entry(2, "Second");
+        second.dependencies = vec![3];
+        state.entries = vec![first, second, test_entry(3, "Third")];
+        state
+    }
+
+    fn status(state: &TodoState, id: u64) -> (EntryStatus, Option<EntryStatus>) {
+        let entry = state.entries.iter().find(|e| e.id == id).unwrap();
+        (entry.status.clone(), entry.blocked_from.clone())
+    }
+
+    #[test]
+    fn unknown_and_self_dependencies_are_rejected() {
+        let state = chain();
+        assert_eq!(
+            state.validate_dependencies(Some(1), &[3, 7, 9]),
+            Err("Unknown dependency ids: 7, 9".to_string())
+        );
+        assert_eq!(
+            state.validate_dependencies(Some(2), &[2]),
+            Err("An entry cannot depend on itself.".to_string())
+        );
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_path() {
+        let state = chain();
+        assert_eq!(
+            state.validate_dependencies(Some(3), &[1]),
+            Err("Dependency cycle detected: 3 -> 1 -> 2 -> 3".to_string())
+        );
+        assert_eq!(
+            state.validate_dependencies(Some(2), &[1]),
+            Err("Dependency cycle detected: 2 -> 1 -> 2".to_string())
+        );
+        // Shared dependencies without a loop are fine, as are brand-new entries
+        assert_eq!(state.validate_dependencies(Some(1), &[2, 3]), Ok(()));
+        assert_eq!(state.validate_dependencies(None, &[1, 2, 3]), Ok(()));
+    }
+
+    #[test]
+    fn incomplete_dependencies_hold_an_entry_in_blocked() {
+        let mut state = chain();
+        state.entries[1].status = EntryStatus::InProgress;
+
+        assert!(state.refresh_blocked_status(2));
+        assert_eq!(
+            status(&state, 2),
+            (EntryStatus::Blocked, Some(EntryStatus::InProgress))
+        );
+        // Already blocked, nothing more to do
+        assert!(!state.refresh_blocked_status(2));
+
+        state.entries[2].is_completed = true;
+        assert_eq!(state.refresh_dependents(3), vec![2]);
+        assert_eq!(status(&state, 2), (EntryStatus::InProgress, None));
+    }
+
+    #[test]
+    fn entries_blocked_by_hand_stay_blocked() {
+        let mut state = chain();
+        state.entries[1].status = EntryStatus::Blocked;
+        state.entries[2].is_completed = true;
+
+        assert!(!state.refresh_blocked_status(2));
+        assert_eq!(status(&state, 2), (EntryStatus::Blocked, None));
+    }
+
+    #[test]
+    fn removing_a_dependency_releases_its_dependents() {
+        let mut state = chain();
+        state.entries[0].status = EntryStatus::UpNext;
+        state.refresh_blocked_status(1);
+        assert_eq!(
+            status(&state, 1),
+            (EntryStatus::Blocked, Some(EntryStatus::UpNext))
+        );
+
+        state.entries.remove(1);
+        assert_eq!(state.remove_dependency_references(&[2]), vec![1]);
+        assert!(state.entries[0].dependencies.is_empty());
+        assert_eq!(status(&state, 1), (EntryStatus::UpNext, None));
+    }
+
+    #[test]
+    fn completed_entries_forget_their_held_status() {
+        let mut state = chain();
+        state.refresh_blocked_status(1);
+        assert_eq!(
+            status(&state, 1),
+            (EntryStatus::Blocked, Some(EntryStatus::Backlog))
+        );
+
+        state.entries[0].is_completed = true;
+        assert!(!state.refresh_blocked_status(1));
+        assert_eq!(status(&state, 1).1, None);
+    }
+}
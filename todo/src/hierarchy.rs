@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use crate::{Entry, EntryStatus, TodoState, WsServerMessage};
+
+impl TodoState {
+    /// Checks that `parent_id` exists and that nesting `entry_id` under it keeps the tree acyclic.
+    pub(crate) fn validate_parent(
+        &self,
+        entry_id: Option<u64>,
+        parent_id: u64,
+    ) -> Result<(), String> {
+        if entry_id == Some(parent_id) {
+            return Err("An entry cannot be its own parent.".to_string());
+        }
+        if !self.entries.iter().any(|e| e.id == parent_id) {
+            return Err(format!("Parent entry {parent_id} not found"));
+        }
+        if let Some(entry_id) = entry_id {
+            if self.ancestor_ids(parent_id).contains(&entry_id) {
+                return Err(format!(
+                    "Moving entry {entry_id} under {parent_id} would create a cycle."
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `entry_id` followed by every ancestor above it, nearest first.
+    fn ancestor_ids(&self, entry_id: u64) -> Vec<u64> {
+        let mut chain = vec![entry_id];
+        let mut current = entry_id;
+        while let Some(parent_id) = self
+            .entries
+            .iter()
+            .find(|e| e.id == current)
+            .and_then(|e| e.parent_id)
+        {
+            // Guard against cycles left behind by older data
+            if chain.contains(&parent_id) {
+                break;
+            }
+            chain.push(parent_id);
+            current = parent_id;
+        }
+        chain
+    }
+
+    /// Clones the chain starting at `parent_id` and walking up to the root.
+    pub(crate) fn ancestors_from(&self, parent_id: Option<u64>) -> Vec<Entry> {
+        let Some(parent_id) = parent_id else {
+            return Vec::new();
+        };
+        self.ancestor_ids(parent_id)
+            .into_iter()
+            .filter_map(|id| self.entries.iter().find(|e| e.id == id).cloned())
+            .collect()
+    }
+
+    /// Returns `entry_id` and all of its descendants, parents before children.
+    pub(crate) fn subtree_ids(&self, entry_id: u64) -> Vec<u64> {
+        let mut ids = vec![entry_id];
+        let mut idx = 0;
+        while idx < ids.len() {
+            let current = ids[idx];
+            for child in self.entries.iter().filter(|e| e.parent_id == Some(current)) {
+                if !ids.contains(&child.id) {
+                    ids.push(child.id);
+                }
+            }
+            idx += 1;
+        }
+        ids
+    }
+
+    /// Moves the direct children of `entry_id` under `new_parent`, returning the moved ids.
+    pub(crate) fn reparent_children(&mut self, entry_id: u64, new_parent: Option<u64>) -> Vec<u64> {
+        let mut moved = Vec::new();
+        for entry in &mut self.entries {
+            if entry.parent_id == Some(entry_id) {
+                entry.parent_id = new_parent;
+                moved.push(entry.id);
+            }
+        }
+        moved
+    }
+
+    fn compute_progress(&self, entry_id: u64) -> u8 {
+        let Some(entry) = self.entries.iter().find(|e| e.id == entry_id) else {
+            return 0;
+        };
+        if entry.is_completed {
+            return 100;
+        }
+        // Archived subtasks are shelved and no longer count towards the parent
+        let children: Vec<u32> = self
+            .entries
+            .iter()
+            .filter(|e| e.parent_id == Some(entry_id) && e.status != EntryStatus::Archived)
+            .map(|e| e.progress as u32)
+            .collect();
+        if children.is_empty() {
+            return 0;
+        }
+        (children.iter().sum::<u32>() / children.len() as u32) as u8
+    }
+
+    /// Recomputes the rolled-up progress of `entry_id` and every ancestor above it.
+    pub(crate) fn refresh_progress_upwards(&mut self, entry_id: u64) {
+        for id in self.ancestor_ids(entry_id) {
+            let progress = self.compute_progress(id);
+            if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+                entry.progress = progress;
+            }
+        }
+    }
+
+    /// Recomputes progress for every entry, deepest subtasks first.
+    pub(crate) fn refresh_all_progress(&mut self) {
+        let depths: HashMap<u64, usize> = self
+            .entries
+            .iter()
+            .map(|e| (e.id, self.ancestor_ids(e.id).len()))
+            .collect();
+        let mut ids: Vec<u64> = depths.keys().copied().collect();
+        ids.sort_by_key(|id| std::cmp::Reverse(depths[id]));
+        for id in ids {
+            let progress = self.compute_progress(id);
+            if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+                entry.progress = progress;
+            }
+        }
+    }
+
+    /// Bumps the revision of `entry_id` and broadcasts `EntryUpdated` with its ancestors.
+    ///
+    /// Every changed copy sent to clients carries a new revision, so edits based on an older
+    /// copy can be told apart in `save_entry`.
+    pub(crate) fn broadcast_entry(&mut self, entry_id: u64) -> Option<Entry> {
+        let entry = self.entries.iter_mut().find(|e| e.id == entry_id)?;
+        entry.revision += 1;
+        let entry = entry.clone();
+        let ancestors = self.ancestors_from(entry.parent_id);
+        self.publish_change(|seq| WsServerMessage::EntryUpdated {
+            entry: Box::new(entry.clone()),
+            ancestors,
+            seq,
+        });
+        Some(entry)
+    }
+}
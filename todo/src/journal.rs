@@ -0,0 +1,490 @@
+use std::collections::{hash_map, HashMap};
+
+use crate::{
+    refresh_entry_timescale, Entry, HistoryRestore, HistoryStatus, Note, Project, TodoState,
+    TrashKey, WsServerMessage,
+};
+
+/// Undoable steps kept per session; older ones drop off the bottom.
+const JOURNAL_DEPTH: usize = 100;
+
+/// Sessions with history kept at once; past this the least recently used one is dropped.
+const MAX_SESSIONS: usize = 50;
+
+/// Undo/redo history of entry, note and project mutations, per client session. Kept in
+/// memory only, so history starts over when the process restarts.
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    sessions: HashMap<String, SessionHistory>,
+    /// Bumped on every use of a session, to find the least recently used one.
+    clock: u64,
+    /// Every record as last published. Before-images come from here, so recording a call
+    /// only clones the records it touches.
+    published: PublishedRecords,
+    recording: Option<Recording>,
+}
+
+#[derive(Debug, Default)]
+struct SessionHistory {
+    undo: Vec<JournalStep>,
+    redo: Vec<JournalStep>,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct PublishedRecords {
+    entries: HashMap<u64, Entry>,
+    notes: HashMap<u64, Note>,
+    projects: HashMap<u64, Project>,
+}
+
+/// Before-images of the records the call being recorded has published changes for.
+#[derive(Debug)]
+struct Recording {
+    entries: BeforeImages<Entry>,
+    notes: BeforeImages<Note>,
+    projects: BeforeImages<Project>,
+    next_project_id: u64,
+}
+
+/// Records of one kind in the order they were first touched, each with its copy from before
+/// the call. `None` means the record did not exist yet.
+#[derive(Debug)]
+struct BeforeImages<T> {
+    order: Vec<u64>,
+    images: HashMap<u64, Option<T>>,
+}
+
+impl<T> Default for BeforeImages<T> {
+    fn default() -> Self {
+        Self {
+            order: Vec::new(),
+            images: HashMap::new(),
+        }
+    }
+}
+
+impl<T> BeforeImages<T> {
+    /// Keeps `before` for `id`, unless an earlier change in the same call already did.
+    fn keep(&mut self, id: u64, before: Option<T>) {
+        if let hash_map::Entry::Vacant(slot) = self.images.entry(id) {
+            slot.insert(before);
+            self.order.push(id);
+        }
+    }
+
+    /// The touched records in order, each with its before-image.
+    fn into_images(mut self) -> impl Iterator<Item = (u64, Option<T>)> {
+        let order = std::mem::take(&mut self.order);
+        order
+            .into_iter()
+            .map(move |id| (id, self.images.remove(&id).flatten()))
+    }
+}
+
+/// One mutating call, as before/after images of every record it changed. `None` means the
+/// record did not exist on that side.
+#[derive(Debug, Clone)]
+struct JournalStep {
+    label: String,
+    entries: Vec<RecordImages<Entry>>,
+    notes: Vec<RecordImages<Note>>,
+    projects: Vec<RecordImages<Project>>,
+    /// The project id counter before and after the call.
+    next_project_id: (u64, u64),
+}
+
+#[derive(Debug, Clone)]
+struct RecordImages<T> {
+    id: u64,
+    before: Option<T>,
+    after: Option<T>,
+}
+
+impl Journal {
+    /// Starts over from the given records, after they were replaced wholesale.
+    pub(crate) fn reset(&mut self, entries: &[Entry], notes: &[Note], projects: &[Project]) {
+        self.published = PublishedRecords {
+            entries: entries.iter().map(|e| (e.id, e.clone())).collect(),
+            notes: notes.iter().map(|n| (n.id, n.clone())).collect(),
+            projects: projects.iter().map(|p| (p.id, p.clone())).collect(),
+        };
+    }
+
+    /// Takes the published change into the copy of published records and, the first time a
+    /// record is touched while recording, keeps its previous copy as the before-image.
+    pub(crate) fn observe(&mut self, message: &WsServerMessage) {
+        let published = &mut self.published;
+        let recording = self.recording.as_mut();
+        match message {
+            WsServerMessage::EntryUpdated { entry, .. } => {
+                let before = published.entries.insert(entry.id, (**entry).clone());
+                if let Some(recording) = recording {
+                    recording.entries.keep(entry.id, before);
+                }
+            }
+            WsServerMessage::EntryRemoved { entry_id, .. } => {
+                let before = published.entries.remove(entry_id);
+                if let Some(recording) = recording {
+                    recording.entries.keep(*entry_id, before);
+                }
+            }
+            WsServerMessage::NoteUpdated { note, .. } => {
+                let before = published.notes.insert(note.id, note.clone());
+                if let Some(recording) = recording {
+                    recording.notes.keep(note.id, before);
+                }
+            }
+            WsServerMessage::NoteRemoved { note_id, .. } => {
+                let before = published.notes.remove(note_id);
+                if let Some(recording) = recording {
+                    recording.notes.keep(*note_id, before);
+                }
+            }
+            WsServerMessage::ProjectUpdated { project, .. } => {
+                let before = published.projects.insert(project.id, project.clone());
+                if let Some(recording) = recording {
+                    recording.projects.keep(project.id, before);
+                }
+            }
+            WsServerMessage::ProjectRemoved { project_id, .. } => {
+                let before = published.projects.remove(project_id);
+                if let Some(recording) = recording {
+                    recording.projects.keep(*project_id, before);
+                }
+            }
+            // Column ranks live on the entry; smart list ranks don't
+            WsServerMessage::EntriesReranked {
+                list_id: None,
+                ranks,
+                ..
+            } => {
+                for rank in ranks {
+                    if let Some(entry) = published.entries.get_mut(&rank.entry_id) {
+                        entry.rank = rank.rank.clone();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The history of `session_id`, created if needed. Creating one past `MAX_SESSIONS`
+    /// drops the least recently used session.
+    fn session(&mut self, session_id: &str) -> &mut SessionHistory {
+        self.clock += 1;
+        if !self.sessions.contains_key(session_id) && self.sessions.len() >= MAX_SESSIONS {
+            let oldest = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, history)| history.last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                self.sessions.remove(&oldest);
+            }
+        }
+        let history = self.sessions.entry(session_id.to_string()).or_default();
+        history.last_used = self.clock;
+        history
+    }
+
+    /// Pops the latest undo (or, with `redo`, redo) step of `session_id`.
+    fn pop_step(&mut self, session_id: &str, redo: bool) -> Option<JournalStep> {
+        if !self.sessions.contains_key(session_id) {
+            return None;
+        }
+        let history = self.session(session_id);
+        if redo {
+            history.redo.pop()
+        } else {
+            history.undo.pop()
+        }
+    }
+
+    pub(crate) fn status(&self, session_id: &str) -> HistoryStatus {
+        let history = self.sessions.get(session_id);
+        HistoryStatus {
+            undo_label: history
+                .and_then(|h| h.undo.last())
+                .map(|step| step.label.clone()),
+            redo_label: history
+                .and_then(|h| h.redo.last())
+                .map(|step| step.label.clone()),
+        }
+    }
+}
+
+impl TodoState {
+    /// Runs `mutation` as one undoable step in the history of `session_id`.
+    ///
+    /// Every record the mutation publishes a change for is journaled, which covers link
+    /// sync, re-parenting and the other records a single call can touch. The activity log is
+    /// written from the same recording, so every endpoint that changes entries runs through
+    /// here; only derived fields (timescales and ranks) change outside it.
+    pub(crate) fn journaled<T>(
+        &mut self,
+        session_id: Option<String>,
+        label: &str,
+        mutation: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        self.start_recording();
+        let result = mutation(self);
+        if let Some(step) = self.finish_recording(label) {
+            let history = self.journal.session(&session_id.unwrap_or_default());
+            history.undo.push(step);
+            if history.undo.len() > JOURNAL_DEPTH {
+                history.undo.remove(0);
+            }
+            history.redo.clear();
+        }
+        result
+    }
+
+    /// Reverts the latest step of `session_id` (or re-applies the latest undone one when
+    /// `redo` is set) and re-broadcasts the restored records.
+    pub(crate) fn step_history(
+        &mut self,
+        session_id: Option<String>,
+        redo: bool,
+    ) -> Result<HistoryRestore, String> {
+        let session_id = session_id.unwrap_or_default();
+        let step = self
+            .journal
+            .pop_step(&session_id, redo)
+            .ok_or_else(|| format!("Nothing to {}.", if redo { "redo" } else { "undo" }))?;
+
+        let inverse = match self.revert_step(&step) {
+            Ok(inverse) => inverse,
+            Err(err) => {
+                // Leave the step in place so the history stays as the user last saw it
+                let history = self.journal.session(&session_id);
+                if redo {
+                    history.redo.push(step);
+                } else {
+                    history.undo.push(step);
+                }
+                return Err(err);
+            }
+        };
+
+        let mut restore = HistoryRestore {
+            label: step.label,
+            entries: Vec::new(),
+            notes: Vec::new(),
+            removed_entry_ids: Vec::new(),
+            removed_note_ids: Vec::new(),
+            history: HistoryStatus {
+                undo_label: None,
+                redo_label: None,
+            },
+        };
+        for image in &inverse.entries {
+            match &image.after {
+                Some(entry) => restore.entries.push(entry.clone()),
+                None => restore.removed_entry_ids.push(image.id),
+            }
+        }
+        for image in &inverse.notes {
+            match &image.after {
+                Some(note) => restore.notes.push(note.clone()),
+                None => restore.removed_note_ids.push(image.id),
+            }
+        }
+
+        let history = self.journal.session(&session_id);
+        if redo {
+            history.undo.push(inverse);
+        } else {
+            history.redo.push(inverse);
+        }
+        restore.history = self.journal.status(&session_id);
+        Ok(restore)
+    }
+
+    fn start_recording(&mut self) {
+        self.journal.recording = Some(Recording {
+            entries: BeforeImages::default(),
+            notes: BeforeImages::default(),
+            projects: BeforeImages::default(),
+            next_project_id: self.next_project_id,
+        });
+    }
+
+    /// Turns the current recording into a step, or `None` if nothing ended up changed (for
+    /// instance because a batch was rolled back). Every kept change bumps the revision, so
+    /// comparing revisions is enough; projects carry no revision and are compared whole.
+    /// Entry changes also go to the activity log.
+    fn finish_recording(&mut self, label: &str) -> Option<JournalStep> {
+        let recording = self.journal.recording.take()?;
+        let mut step = JournalStep {
+            label: label.to_string(),
+            entries: Vec::new(),
+            notes: Vec::new(),
+            projects: Vec::new(),
+            next_project_id: (recording.next_project_id, self.next_project_id),
+        };
+        for (id, before) in recording.entries.into_images() {
+            let after = self.entries.iter().find(|e| e.id == id).cloned();
+            if before.as_ref().map(|e| e.revision) != after.as_ref().map(|e| e.revision) {
+                step.entries.push(RecordImages { id, before, after });
+            }
+        }
+        for (id, before) in recording.notes.into_images() {
+            let after = self.notes.iter().find(|n| n.id == id).cloned();
+            if before.as_ref().map(|n| n.revision) != after.as_ref().map(|n| n.revision) {
+                step.notes.push(RecordImages { id, before, after });
+            }
+        }
+        for (id, before) in recording.projects.into_images() {
+            let after = self.projects.iter().find(|p| p.id == id).cloned();
+            if before != after {
+                step.projects.push(RecordImages { id, before, after });
+            }
+        }
+        for image in &step.entries {
+            self.record_entry_activity(image.id, image.before.as_ref(), image.after.as_ref());
+        }
+        (!step.entries.is_empty() || !step.notes.is_empty() || !step.projects.is_empty())
+            .then_some(step)
+    }
+
+    /// Puts back the before-images of `step` and returns the step that reverses this.
+    ///
+    /// Refuses when any record was changed after `step`, rather than overwrite that change.
+    fn revert_step(&mut self, step: &JournalStep) -> Result<JournalStep, String> {
+        for image in &step.entries {
+            let current = self.entries.iter().find(|e| e.id == image.id);
+            if current.map(|e| e.revision) != image.after.as_ref().map(|e| e.revision) {
+                return Err(format!(
+                    "Entry {} has changed since '{}'; nothing was restored.",
+                    image.id, step.label
+                ));
+            }
+        }
+        for image in &step.notes {
+            let current = self.notes.iter().find(|n| n.id == image.id);
+            if current.map(|n| n.revision) != image.after.as_ref().map(|n| n.revision) {
+                return Err(format!(
+                    "Note {} has changed since '{}'; nothing was restored.",
+                    image.id, step.label
+                ));
+            }
+        }
+
+        for image in &step.projects {
+            let current = self.projects.iter().find(|p| p.id == image.id);
+            if current != image.after.as_ref() {
+                return Err(format!(
+                    "Project {} has changed since '{}'; nothing was restored.",
+                    image.id, step.label
+                ));
+            }
+        }
+
+        self.start_recording();
+        // Projects go first so restored entries find the projects they belong to
+        for image in &step.projects {
+            self.apply_project_image(image.id, image.before.clone());
+        }
+        let (before_id, after_id) = step.next_project_id;
+        if self.next_project_id == after_id {
+            self.next_project_id = before_id;
+        }
+        for image in &step.entries {
+            self.apply_entry_image(image.id, image.before.clone());
+        }
+        for image in &step.notes {
+            self.apply_note_image(image.id, image.before.clone());
+        }
+        self.refresh_live_views();
+        self.finish_recording(&step.label)
+            .ok_or_else(|| "Nothing to restore.".to_string())
+    }
+
+    /// Replaces, recreates or removes `entry_id` so it matches `image`.
+    fn apply_entry_image(&mut self, entry_id: u64, image: Option<Entry>) {
+        let idx = self.entries.iter().position(|e| e.id == entry_id);
+        let Some(mut entry) = image else {
+            if idx.is_some() {
+                // Removed like a delete, so dependents and the parent are brought up to date
+                let _ = self.remove_entry(entry_id, None);
+            }
+            return;
+        };
+
+        let calendar = self.calendar();
+        let previous_sprint = idx.and_then(|idx| self.entries[idx].sprint_id);
+        self.track_sprint_scope(entry_id, previous_sprint, entry.sprint_id);
+        // The project may have been merged away or deleted since the image was taken
+        if entry
+            .project_id
+            .is_some_and(|id| !self.projects.iter().any(|p| p.id == id))
+        {
+            entry.project_id = None;
+            entry.project = None;
+        }
+        refresh_entry_timescale(&mut entry, &calendar);
+        match idx {
+            Some(idx) => {
+                // Continue from the current revision so older copies still conflict
+                entry.revision = self.entries[idx].revision;
+                self.entries[idx] = entry;
+            }
+            None => {
+                self.forget_trashed(TrashKey::Entry(entry_id));
+                self.entries.push(entry);
+            }
+        }
+        // The workflow may have changed since the image was taken
+        self.sync_entry_state(entry_id);
+        self.index_entry(entry_id);
+        self.broadcast_entry(entry_id);
+    }
+
+    /// Replaces, recreates or removes `project_id` so it matches `image`.
+    fn apply_project_image(&mut self, project_id: u64, image: Option<Project>) {
+        let idx = self.projects.iter().position(|p| p.id == project_id);
+        match (image, idx) {
+            (Some(project), Some(idx)) => {
+                self.projects[idx] = project.clone();
+                self.publish_change(|seq| WsServerMessage::ProjectUpdated { project, seq });
+            }
+            (Some(project), None) => {
+                // Ids grow with creation, so this puts the project back in its old place
+                let idx = self.projects.partition_point(|p| p.id < project_id);
+                self.projects.insert(idx, project.clone());
+                self.publish_change(|seq| WsServerMessage::ProjectUpdated { project, seq });
+            }
+            (None, Some(idx)) => {
+                self.projects.remove(idx);
+                self.publish_change(|seq| WsServerMessage::ProjectRemoved { project_id, seq });
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Replaces, recreates or removes `note_id` so it matches `image`.
+    fn apply_note_image(&mut self, note_id: u64, image: Option<Note>) {
+        let idx = self.notes.iter().position(|n| n.id == note_id);
+        match (image, idx) {
+            (Some(mut note), Some(idx)) => {
+                note.revision = self.notes[idx].revision;
+                self.notes[idx] = note;
+            }
+            (Some(note), None) => {
+                self.forget_trashed(TrashKey::Note(note_id));
+                self.notes.push(note);
+            }
+            (None, Some(idx)) => {
+                let removed = self.notes.remove(idx);
+                self.trash_note(removed);
+            }
+            (None, None) => return,
+        }
+        self.index_note(note_id);
+        self.record_note_version(note_id);
+        if self.broadcast_note(note_id).is_none() {
+            self.publish_change(|seq| WsServerMessage::NoteRemoved { note_id, seq });
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::HashSet;
 
-use chrono::{Datelike, Duration, Local, LocalResult, NaiveDate, TimeZone};
+use chrono::{Datelike, NaiveDate, Utc};
 use hyperware_process_lib::{
     homepage::add_to_homepage,
     http::server::{send_ws_push, WsMessageType},
@@ -11,6 +11,41 @@ use hyperware_process_lib::{
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+mod activity;
+mod analytics;
+mod automation;
+mod batch;
+mod change_log;
+mod clock;
+mod dashboard;
+mod dependencies;
+mod hierarchy;
+mod journal;
+mod note_history;
+mod patch;
+mod projects;
+mod query;
+mod ranks;
+mod recurrence;
+mod schedule;
+mod search_index;
+mod smart_lists;
+mod sprints;
+mod trash;
+mod wip;
+mod workflows;
+
+use change_log::ChangeLog;
+use clock::{schedule_timescale_refresh, validate_settings, Calendar};
+use journal::Journal;
+use projects::ProjectChoice;
+use query::parse_query;
+use ranks::order_smart_list;
+use recurrence::{parse_rrule, validate_recurrence};
+use search_index::SearchIndex;
+use smart_lists::SmartListSubscription;
+use workflows::{set_entry_state, state_status, DEFAULT_WORKFLOW_ID};
+
 const ICON: &str = include_str!("./icon");
 const SPIDER_PROCESS_ID: (&str, &str, &str) = ("spider", "spider", "sys");
 
@@ -21,8 +56,56 @@ pub struct TodoState {
     next_entry_id: u64,
     next_note_id: u64,
     spider_api_key: Option<String>,
+    #[serde(default)]
+    settings: UserSettings,
+    #[serde(default)]
+    smart_lists: Vec<SmartList>,
+    #[serde(default)]
+    next_smart_list_id: u64,
+    /// Sequence number of the latest change event.
+    #[serde(default)]
+    change_seq: u64,
+    /// Deleted entries and notes, oldest first, until restored or purged.
+    #[serde(default)]
+    trash: Vec<TrashItem>,
+    /// Saved versions of every note, oldest first.
+    #[serde(default)]
+    note_versions: Vec<NoteVersion>,
+    /// Append-only record of changes to entries, oldest first.
+    #[serde(default)]
+    activity: Vec<ActivityEvent>,
+    #[serde(default)]
+    next_activity_id: u64,
+    #[serde(default)]
+    projects: Vec<Project>,
+    #[serde(default)]
+    next_project_id: u64,
+    #[serde(default)]
+    sprints: Vec<Sprint>,
+    #[serde(default)]
+    next_sprint_id: u64,
+    /// Always holds the built-in default workflow.
+    #[serde(default = "workflows::default_workflows")]
+    workflows: Vec<Workflow>,
+    #[serde(default)]
+    next_workflow_id: u64,
+    #[serde(default)]
+    automation_rules: Vec<AutomationRule>,
+    #[serde(default)]
+    next_rule_id: u64,
     #[serde(skip)]
     connected_channels: HashSet<u32>,
+    #[serde(skip)]
+    search_index: SearchIndex,
+    #[serde(skip)]
+    smart_list_subscriptions: Vec<SmartListSubscription>,
+    #[serde(skip)]
+    change_log: ChangeLog,
+    #[serde(skip)]
+    journal: Journal,
+    /// Over-limit columns as last broadcast.
+    #[serde(skip)]
+    wip_over_limit: Vec<WipUsage>,
 }
 
 impl Default for TodoState {
@@ -33,7 +116,28 @@ impl Default for TodoState {
             next_entry_id: 1,
             next_note_id: 1,
             spider_api_key: None,
+            settings: UserSettings::default(),
+            smart_lists: Vec::new(),
+            next_smart_list_id: 1,
+            change_seq: 0,
+            trash: Vec::new(),
+            note_versions: Vec::new(),
+            activity: Vec::new(),
+            next_activity_id: 1,
+            projects: Vec::new(),
+            next_project_id: 1,
+            sprints: Vec::new(),
+            next_sprint_id: 1,
+            workflows: workflows::default_workflows(),
+            next_workflow_id: DEFAULT_WORKFLOW_ID + 1,
+            automation_rules: Vec::new(),
+            next_rule_id: 1,
             connected_channels: HashSet::new(),
+            search_index: SearchIndex::default(),
+            smart_list_subscriptions: Vec::new(),
+            change_log: ChangeLog::default(),
+            journal: Journal::default(),
+            wip_over_limit: Vec::new(),
         }
     }
 }
@@ -61,29 +165,72 @@ pub enum EntryTimescale {
     Overdue,
     Today,
     ThisWeek,
+    /// Due, or planned into the running sprint, before that sprint ends.
+    ThisSprint,
     ThisMonth,
     Later,
     Someday,
     Completed,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Entry {
     pub id: u64,
     pub title: String,
     pub summary: String,
     pub description: String,
+    /// Name of the project in `project_id`, kept in step with the project record.
     pub project: Option<String>,
+    #[serde(default)]
+    pub project_id: Option<u64>,
+    #[serde(default)]
+    pub sprint_id: Option<u64>,
+    /// Key of the entry's state in its project's workflow.
+    #[serde(default)]
+    pub state: String,
+    /// The built-in status `state` reports as, or `Blocked` while a dependency is open.
     pub status: EntryStatus,
+    /// Position within the entry's status column; ranks compare as plain strings.
+    #[serde(default)]
+    pub rank: String,
     pub timescale: EntryTimescale,
     pub priority: EntryPriority,
     pub due_ts: Option<i64>,
     pub start_ts: Option<i64>,
+    #[serde(default)]
+    pub estimate_minutes: Option<u32>,
     pub dependencies: Vec<u64>,
+    #[serde(default)]
+    pub parent_id: Option<u64>,
     pub note_ids: Vec<u64>,
     pub assignees: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub is_completed: bool,
     pub completed_at_ts: Option<i64>,
+    /// Completion percentage rolled up from subtasks (0-100).
+    #[serde(default)]
+    pub progress: u8,
+    #[serde(default)]
+    pub recurrence: Option<RecurrenceRule>,
+    /// Id of the first entry in this entry's recurring series.
+    #[serde(default)]
+    pub series_id: Option<u64>,
+    /// 1-based position within the series.
+    #[serde(default)]
+    pub occurrence: u32,
+    /// Set once completing this occurrence has spawned the next one.
+    #[serde(default)]
+    pub next_occurrence_id: Option<u64>,
+    /// Status to restore once every dependency is complete.
+    #[serde(default)]
+    pub blocked_from: Option<EntryStatus>,
+    /// State the entry held before archiving its project archived it; restored on unarchive.
+    #[serde(default)]
+    pub archived_from: Option<String>,
+    /// Bumped every time a changed copy is sent to clients.
+    #[serde(default)]
+    pub revision: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -92,14 +239,85 @@ pub struct EntryDraft {
     pub title: String,
     pub summary: String,
     pub description: String,
+    /// Matched to a project by name (ignoring case) when `project_id` is unset; a new name
+    /// creates the project.
     pub project: Option<String>,
+    #[serde(default)]
+    pub project_id: Option<u64>,
+    #[serde(default)]
+    pub sprint_id: Option<u64>,
+    /// Workflow state to move to. When unset or unchanged, a changed `status` picks the
+    /// matching state instead.
+    #[serde(default)]
+    pub state: Option<String>,
     pub status: EntryStatus,
     pub priority: EntryPriority,
     pub due_ts: Option<i64>,
     pub start_ts: Option<i64>,
+    #[serde(default)]
+    pub estimate_minutes: Option<u32>,
     pub dependencies: Vec<u64>,
+    #[serde(default)]
+    pub parent_id: Option<u64>,
     pub note_ids: Vec<u64>,
     pub assignees: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub recurrence: Option<RecurrenceRule>,
+    /// Defaults to editing only this occurrence.
+    #[serde(default)]
+    pub series_scope: Option<SeriesEditScope>,
+    /// Revision the edit was based on; stale drafts are rejected with a conflict.
+    #[serde(default)]
+    pub expected_revision: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DayOfWeek {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RecurrenceFrequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// Subset of an RFC 5545 RRULE: FREQ, INTERVAL, BYDAY, BYMONTHDAY, UNTIL and COUNT.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub frequency: RecurrenceFrequency,
+    pub interval: u32,
+    pub by_weekday: Vec<DayOfWeek>,
+    /// Days of the month; negative values count back from the last day.
+    pub by_month_day: Vec<i32>,
+    pub until_ts: Option<i64>,
+    pub count: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SeriesEditScope {
+    ThisOccurrence,
+    /// Also copy the shared fields onto every open occurrence of the series.
+    WholeSeries,
+}
+
+/// What happens to the subtasks of an entry when it is deleted.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ChildEntryPolicy {
+    /// Delete the whole subtree along with the entry.
+    Cascade,
+    /// Move direct children up to the deleted entry's parent.
+    Reparent,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -113,6 +331,8 @@ pub struct Note {
     pub summary: String,
     pub accent: String,
     pub last_edited_ts: i64,
+    #[serde(default)]
+    pub revision: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -124,318 +344,1712 @@ pub struct NoteDraft {
     pub tags: Vec<String>,
     pub linked_entry_ids: Vec<u64>,
     pub accent: Option<String>,
+    #[serde(default)]
+    pub expected_revision: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ProjectStatus {
+    Planned,
+    #[default]
+    Active,
+    OnHold,
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Project {
+    pub id: u64,
+    /// Unique, ignoring case.
+    pub name: String,
+    pub description: String,
+    pub color: String,
+    pub owner: Option<String>,
+    pub status: ProjectStatus,
+    pub target_ts: Option<i64>,
+    /// Archived projects accept no new entries.
+    pub archived: bool,
+    /// `None` uses the default workflow.
+    #[serde(default)]
+    pub workflow_id: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppBootstrap {
-    pub entries: Vec<Entry>,
-    pub notes: Vec<Note>,
-    pub is_public_mode: bool,
+pub struct ProjectDraft {
+    pub id: Option<u64>,
+    /// Saving an existing project under a new name renames it on every member entry.
+    pub name: String,
+    pub description: String,
+    pub color: Option<String>,
+    pub owner: Option<String>,
+    pub status: ProjectStatus,
+    pub target_ts: Option<i64>,
+    /// Changing the workflow moves every entry to the matching state of the new one.
+    #[serde(default)]
+    pub workflow_id: Option<u64>,
+}
+
+/// How a workflow state counts towards completion.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WorkflowCategory {
+    Todo,
+    Active,
+    /// Entries in a done state are completed.
+    Done,
+    /// Shelved: neither open nor completed.
+    Archived,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SearchAllResult {
+pub struct WorkflowState {
+    /// Stable identifier stored on entries.
+    pub key: String,
+    pub name: String,
+    pub category: WorkflowCategory,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WorkflowTransition {
+    pub from: String,
+    pub to: String,
+}
+
+/// Named states and the moves allowed between them. Projects pick a workflow; entries
+/// outside a project, or in a project without one, use the default workflow, whose states
+/// are the `EntryStatus` values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    pub id: u64,
+    pub name: String,
+    /// In board order.
+    pub states: Vec<WorkflowState>,
+    /// Empty means any state can move to any other.
+    pub transitions: Vec<WorkflowTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowDraft {
+    pub id: Option<u64>,
+    pub name: String,
+    pub states: Vec<WorkflowState>,
+    pub transitions: Vec<WorkflowTransition>,
+}
+
+/// The change that sets an automation rule off.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RuleTrigger {
+    /// Any save of an entry, including its creation.
+    EntrySaved,
+    EntryCreated,
+    /// The entry moved into this status.
+    StatusChanged(EntryStatus),
+    EntryCompleted,
+    /// The last open subtask of an entry was completed; the rule acts on the parent.
+    SubtasksCompleted,
+    /// A note was saved; the rule acts on every entry linked to it.
+    NoteSaved,
+}
+
+/// A test on the entry a rule acts on. All of a rule's conditions must hold.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RuleCondition {
+    StatusIs(EntryStatus),
+    PriorityIs(EntryPriority),
+    TimescaleIs(EntryTimescale),
+    HasTag(String),
+    AssignedTo(String),
+    Unassigned,
+    InProject(u64),
+    /// The entry matches a `search_all` query.
+    Matches(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RuleAction {
+    SetStatus(EntryStatus),
+    SetPriority(EntryPriority),
+    AddAssignee(String),
+    AddTag(String),
+    RemoveTag(String),
+    LinkNote(u64),
+    CreateEntry(RuleEntryTemplate),
+}
+
+/// An entry a rule creates, in the project of the entry the rule acts on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RuleEntryTemplate {
+    pub title: String,
+    pub description: String,
+    pub status: EntryStatus,
+    pub priority: EntryPriority,
+    pub assignees: Vec<String>,
+    pub tags: Vec<String>,
+    /// Creates the entry as a subtask of the one the rule acts on.
+    pub as_subtask: bool,
+}
+
+/// "When `trigger`, if every condition holds, then apply `actions`." Rules run in order
+/// after `save_entry`, `toggle_entry_completion` and `save_note`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutomationRule {
+    pub id: u64,
+    pub name: String,
+    pub enabled: bool,
+    pub trigger: RuleTrigger,
+    pub conditions: Vec<RuleCondition>,
+    pub actions: Vec<RuleAction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutomationRuleDraft {
+    pub id: Option<u64>,
+    pub name: String,
+    pub enabled: bool,
+    pub trigger: RuleTrigger,
+    pub conditions: Vec<RuleCondition>,
+    pub actions: Vec<RuleAction>,
+}
+
+/// One rule acting on one entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleFiring {
+    pub rule_id: u64,
+    pub rule_name: String,
+    pub entry_id: u64,
+    pub created_entry_ids: Vec<u64>,
+    /// The action that failed, if any. Actions before it were kept.
+    pub error: Option<String>,
+}
+
+/// What the rules did in response to one change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutomationRun {
+    /// In the order the rules fired, follow-up firings included.
+    pub firings: Vec<RuleFiring>,
+    /// Copies of the entries the rules changed or created, as they ended up.
     pub entries: Vec<Entry>,
-    pub notes: Vec<Note>,
+    /// Set when the run hit the firing limit, which stops rules that keep setting each
+    /// other off.
+    pub stopped_early: bool,
 }
 
+/// Result of `dry_run_automation`: what saving the draft would do, none of it kept.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum WsServerMessage {
-    Snapshot {
-        entries: Vec<Entry>,
-        notes: Vec<Note>,
-    },
-    EntryUpdated {
-        entry: Entry,
-    },
-    EntryRemoved {
-        entry_id: u64,
-    },
-    NoteUpdated {
-        note: Note,
-    },
-    NoteRemoved {
-        note_id: u64,
-    },
+pub struct AutomationPreview {
+    /// The draft as saved, after the rules ran.
+    pub entry: Entry,
+    pub run: AutomationRun,
 }
 
-#[derive(Debug, Deserialize)]
-enum WsClientMessage {
-    Subscribe,
-    Ping,
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SprintKind {
+    /// Only one sprint runs at a time, and it drives the `ThisSprint` timescale.
+    Sprint,
+    Milestone,
 }
 
-#[hyperapp_macro::hyperapp(
-    name = "Todo App",
-    ui = Some(hyperware_process_lib::http::server::HttpBindingConfig::default().authenticated(false)),
-    endpoints = vec![
-        hyperware_process_lib::hyperapp::Binding::Http {
-            path: "/api",
-            config: hyperware_process_lib::http::server::HttpBindingConfig::default().authenticated(false),
-        },
-        hyperware_process_lib::hyperapp::Binding::Ws {
-            path: "/ws",
-            config: hyperware_process_lib::http::server::WsBindingConfig::default().authenticated(false),
-        },
-    ],
-    save_config = hyperware_process_lib::hyperapp::SaveOptions::EveryMessage,
-    wit_world = "todo-ware-dot-hypr-v0"
-)]
-impl TodoState {
-    #[init]
-    async fn initialize(&mut self) {
-        add_to_homepage("Todo App", Some(ICON), Some("/"), None);
-        self.connected_channels.clear();
-        println!("Todo app ready on node {}", our().node.clone());
-        self.ensure_demo_content();
-    }
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SprintStatus {
+    Planned,
+    Active,
+    Closed,
+}
 
-    #[http]
-    async fn bootstrap(&mut self) -> Result<AppBootstrap, String> {
-        self.ensure_demo_content();
-        Ok(AppBootstrap {
-            entries: self.entries.clone(),
-            notes: self.notes.clone(),
-            #[cfg(feature = "public-mode")]
-            is_public_mode: true,
-            #[cfg(not(feature = "public-mode"))]
-            is_public_mode: false,
-        })
-    }
+/// A time-boxed container for entries: a sprint or a milestone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sprint {
+    pub id: u64,
+    pub name: String,
+    pub goal: String,
+    pub kind: SprintKind,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub status: SprintStatus,
+    /// Open members when the sprint started.
+    pub committed_entry_ids: Vec<u64>,
+    /// Entries assigned while the sprint was running.
+    pub added_entry_ids: Vec<u64>,
+    /// Committed entries taken out or deleted while the sprint was running.
+    pub removed_entry_ids: Vec<u64>,
+    /// Members that were complete when the sprint closed.
+    pub completed_entry_ids: Vec<u64>,
+    /// Unfinished members moved on when the sprint closed.
+    pub carried_over_entry_ids: Vec<u64>,
+    pub carried_over_to: Option<u64>,
+    pub closed_ts: Option<i64>,
+}
 
-    #[local]
-    #[http]
-    async fn save_entry(&mut self, mut draft: EntryDraft) -> Result<Entry, String> {
-        if draft.title.trim().is_empty() {
-            return Err("Entries require a title.".to_string());
-        }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SprintDraft {
+    pub id: Option<u64>,
+    pub name: String,
+    pub goal: String,
+    pub kind: SprintKind,
+    pub start_ts: i64,
+    pub end_ts: i64,
+}
 
-        if draft.summary.trim().is_empty() {
-            draft.summary = summarize_text(&draft.description);
-        }
+/// Committed versus delivered scope of a sprint, from `get_sprint_report`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SprintReport {
+    pub sprint: Sprint,
+    /// Before the sprint starts this is its current plan.
+    pub committed: SprintScope,
+    pub added: SprintScope,
+    pub removed_entry_ids: Vec<u64>,
+    pub carried_over_entry_ids: Vec<u64>,
+}
 
-        let entry = if let Some(id) = draft.id {
-            let entry = self
-                .entries
-                .iter_mut()
-                .find(|e| e.id == id)
-                .ok_or_else(|| "Entry not found".to_string())?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SprintScope {
+    pub entry_ids: Vec<u64>,
+    pub completed_entry_ids: Vec<u64>,
+    pub estimate_minutes: u32,
+    pub completed_estimate_minutes: u32,
+}
 
-            entry.title = draft.title;
-            entry.summary = draft.summary;
-            entry.description = draft.description;
-            entry.project = draft.project;
-            entry.status = draft.status;
-            entry.priority = draft.priority;
-            entry.due_ts = draft.due_ts;
-            entry.start_ts = draft.start_ts;
-            entry.dependencies = draft.dependencies;
-            entry.note_ids = draft.note_ids.clone();
-            entry.assignees = draft.assignees;
-            refresh_entry_timescale(entry);
-            entry.clone()
-        } else {
-            let mut entry = Entry {
-                id: self.next_entry_id(),
-                title: draft.title,
-                summary: draft.summary,
-                description: draft.description,
-                project: draft.project,
-                status: draft.status,
-                timescale: EntryTimescale::Someday,
-                priority: draft.priority,
-                due_ts: draft.due_ts,
-                start_ts: draft.start_ts,
-                dependencies: draft.dependencies,
-                note_ids: draft.note_ids.clone(),
-                assignees: draft.assignees,
-                is_completed: false,
-                completed_at_ts: None,
-            };
-            refresh_entry_timescale(&mut entry);
-            self.entries.push(entry.clone());
-            entry
-        };
+/// A partial update for `patch_entry`. Unset fields keep their stored value.
+///
+/// `clear` resets optional fields before the values here are applied; list edits remove
+/// before they add, and adding an item that is already present is a no-op.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntryPatch {
+    pub expected_revision: Option<u64>,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub project: Option<String>,
+    pub project_id: Option<u64>,
+    pub sprint_id: Option<u64>,
+    pub state: Option<String>,
+    pub status: Option<EntryStatus>,
+    pub priority: Option<EntryPriority>,
+    pub due_ts: Option<i64>,
+    pub start_ts: Option<i64>,
+    pub estimate_minutes: Option<u32>,
+    pub parent_id: Option<u64>,
+    pub recurrence: Option<RecurrenceRule>,
+    pub series_scope: Option<SeriesEditScope>,
+    pub clear: Vec<EntryPatchField>,
+    pub add_dependencies: Vec<u64>,
+    pub remove_dependencies: Vec<u64>,
+    pub add_assignees: Vec<String>,
+    pub remove_assignees: Vec<String>,
+    pub add_note_ids: Vec<u64>,
+    pub remove_note_ids: Vec<u64>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+}
 
-        let touched_notes = self.sync_entry_note_links(entry.id, entry.note_ids.clone());
-        for note in touched_notes {
-            self.broadcast(&WsServerMessage::NoteUpdated { note });
-        }
-        self.broadcast(&WsServerMessage::EntryUpdated {
-            entry: entry.clone(),
-        });
-        Ok(entry)
-    }
+/// Optional entry fields an `EntryPatch` can reset.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EntryPatchField {
+    Project,
+    DueTs,
+    StartTs,
+    EstimateMinutes,
+    ParentId,
+    Recurrence,
+    SprintId,
+}
 
-    #[local]
-    #[http]
-    async fn toggle_entry_completion(
-        &mut self,
-        entry_id: u64,
-        completed: bool,
-    ) -> Result<Entry, String> {
-        let entry = self
-            .entries
-            .iter_mut()
-            .find(|e| e.id == entry_id)
-            .ok_or_else(|| "Entry not found".to_string())?;
+/// A partial update for `patch_note`. Unset fields keep their stored value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotePatch {
+    pub expected_revision: Option<u64>,
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub pinned: Option<bool>,
+    pub accent: Option<String>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    pub add_linked_entry_ids: Vec<u64>,
+    pub remove_linked_entry_ids: Vec<u64>,
+}
 
-        entry.is_completed = completed;
-        entry.completed_at_ts = if completed {
-            entry.status = EntryStatus::Done;
-            Some(now_ts())
-        } else {
-            None
-        };
+/// One step of `apply_batch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BatchOperation {
+    /// Creates an entry, or updates it when the draft carries an id.
+    SaveEntry(EntryDraft),
+    PatchEntry(BatchEntryPatch),
+    CompleteEntry(BatchEntryCompletion),
+    DeleteEntry(BatchEntryDeletion),
+    SaveNote(NoteDraft),
+    PatchNote(BatchNotePatch),
+    DeleteNote(u64),
+}
 
-        refresh_entry_timescale(entry);
-        let snapshot = entry.clone();
-        self.broadcast(&WsServerMessage::EntryUpdated {
-            entry: snapshot.clone(),
-        });
-        Ok(snapshot)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchEntryPatch {
+    pub entry_id: u64,
+    pub patch: EntryPatch,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchEntryCompletion {
+    pub entry_id: u64,
+    pub completed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchEntryDeletion {
+    pub entry_id: u64,
+    pub children: Option<ChildEntryPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchNotePatch {
+    pub note_id: u64,
+    pub patch: NotePatch,
+}
+
+/// What one batch operation did, or why it failed.
+// Same constraint as `EntrySaveError`: boxed payloads have no WIT mapping
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BatchOutcome {
+    Entry(Entry),
+    Note(Note),
+    Deleted,
+    Failed(String),
+    EntryConflict(EntryConflict),
+    NoteConflict(NoteConflict),
+}
+
+/// Outcomes line up with the submitted operations. When `committed` is false at least one
+/// operation failed and none of them were kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchReport {
+    pub committed: bool,
+    pub outcomes: Vec<BatchOutcome>,
+}
+
+/// What `undo` or `redo` put back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRestore {
+    pub label: String,
+    pub entries: Vec<Entry>,
+    pub notes: Vec<Note>,
+    pub removed_entry_ids: Vec<u64>,
+    pub removed_note_ids: Vec<u64>,
+    pub history: HistoryStatus,
+}
+
+/// Labels of the steps `undo` and `redo` would act on next.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryStatus {
+    pub undo_label: Option<String>,
+    pub redo_label: Option<String>,
+}
+
+/// Why `save_entry` rejected a draft.
+// Conflicts are rare and boxing the entry would not map onto the generated WIT types
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EntrySaveError {
+    Invalid(String),
+    /// The draft was based on an older revision than the stored entry.
+    Conflict(EntryConflict),
+    /// The move would push a column with an enforced limit over it.
+    WipLimit(WipUsage),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryConflict {
+    pub expected_revision: u64,
+    /// The server's current copy, for the client to merge against.
+    pub current: Entry,
+}
+
+impl From<String> for EntrySaveError {
+    fn from(message: String) -> Self {
+        EntrySaveError::Invalid(message)
+    }
+}
+
+impl EntrySaveError {
+    /// The error as a message, for callers that report failures as plain strings.
+    pub(crate) fn into_message(self) -> String {
+        match self {
+            EntrySaveError::Invalid(message) => message,
+            EntrySaveError::Conflict(conflict) => {
+                format!("Entry {} changed in the meantime.", conflict.current.id)
+            }
+            EntrySaveError::WipLimit(usage) => usage.describe(),
+        }
+    }
+}
+
+/// Why `save_note` rejected a draft.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum NoteSaveError {
+    Invalid(String),
+    /// The draft was based on an older revision than the stored note.
+    Conflict(NoteConflict),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteConflict {
+    pub expected_revision: u64,
+    /// The server's current copy, for the client to merge against.
+    pub current: Note,
+}
+
+impl From<String> for NoteSaveError {
+    fn from(message: String) -> Self {
+        NoteSaveError::Invalid(message)
+    }
+}
+
+/// Per-user calendar preferences used for timescales and recurrences.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UserSettings {
+    /// IANA time zone name such as `Europe/Berlin`.
+    pub time_zone: String,
+    pub week_start: DayOfWeek,
+    /// Days that count as working days; empty means every day does.
+    pub working_days: Vec<DayOfWeek>,
+    /// How long deleted entries and notes stay in the trash before they are purged.
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+    /// At most one limit per status and scope.
+    #[serde(default)]
+    pub wip_limits: Vec<WipLimit>,
+}
+
+/// A cap on how many open entries may sit in `status` at once.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WipLimit {
+    pub status: EntryStatus,
+    pub max_entries: u32,
+    /// Applies the cap to each assignee's entries separately instead of the whole column.
+    pub per_assignee: bool,
+    /// Rejects saves that would break the limit instead of only warning about them.
+    pub enforced: bool,
+}
+
+/// How full a limited column is. `assignee` is set for per-assignee limits.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WipUsage {
+    pub status: EntryStatus,
+    pub assignee: Option<String>,
+    pub count: u32,
+    pub max_entries: u32,
+    pub enforced: bool,
+}
+
+/// What `save_entry` stored, plus the WIP limits the move broke without being refused.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedEntry {
+    pub entry: Entry,
+    pub wip_warnings: Vec<WipUsage>,
+}
+
+fn default_trash_retention_days() -> u32 {
+    30
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            time_zone: "UTC".to_string(),
+            week_start: DayOfWeek::Monday,
+            working_days: Vec::new(),
+            trash_retention_days: default_trash_retention_days(),
+            wip_limits: Vec::new(),
+        }
+    }
+}
+
+/// A saved state of a note's title and text. Versions are numbered from 1 per note and,
+/// unlike `Note::revision`, only advance when the title or text changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteVersion {
+    pub note_id: u64,
+    pub version: u32,
+    pub title: String,
+    pub content: String,
+    pub edited_ts: i64,
+    /// Where the save that made this version came from, classified like activity events;
+    /// unknown for versions recorded before history was kept.
+    pub editor: Option<ActivitySource>,
+    /// Set when this version was created by restoring an older one.
+    pub restored_from: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteDiff {
+    pub note_id: u64,
+    pub from_version: u32,
+    pub to_version: u32,
+    pub title_changed: bool,
+    pub lines: Vec<DiffLine>,
+}
+
+/// One line of a diff. Line numbers are 1-based and refer to the side(s) the line is on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub text: String,
+    pub old_line: Option<u32>,
+    pub new_line: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DiffLineKind {
+    Same,
+    Added,
+    Removed,
+}
+
+/// One recorded change to an entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityEvent {
+    pub id: u64,
+    pub entry_id: u64,
+    pub ts: i64,
+    pub source: ActivitySource,
+    pub change: ActivityChange,
+}
+
+/// Where the request that made a change came from. Process variants carry the sender's
+/// address.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ActivitySource {
+    /// The web UI or another HTTP client.
+    Http,
+    /// The Spider AI agent.
+    Agent(String),
+    Local(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ActivityChange {
+    Created,
+    Deleted,
+    Restored,
+    Status(StatusChange),
+    Priority(PriorityChange),
+    Assignees(ListChange),
+    DueDate(DueDateChange),
+    Dependencies(DependencyChange),
+    Completed,
+    Reopened,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub from: EntryStatus,
+    pub to: EntryStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityChange {
+    pub from: EntryPriority,
+    pub to: EntryPriority,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListChange {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DueDateChange {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependencyChange {
+    pub added: Vec<u64>,
+    pub removed: Vec<u64>,
+}
+
+/// `next_before_id` is set when older events remain; pass it back to fetch the next page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityPage {
+    pub events: Vec<ActivityEvent>,
+    pub next_before_id: Option<u64>,
+}
+
+/// Flow metrics from `get_flow_analytics`. Durations are in milliseconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowAnalytics {
+    pub since_ts: i64,
+    pub until_ts: i64,
+    /// Creation to Done, for entries finished in the window.
+    pub lead_time: DurationStats,
+    /// First move to InProgress to Done, for entries finished in the window.
+    pub cycle_time: DurationStats,
+    pub time_in_status: Vec<StatusTime>,
+    pub weekly_throughput: Vec<ThroughputWeek>,
+    pub cumulative_flow: Vec<ProjectFlow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DurationStats {
+    pub count: u32,
+    pub mean_ms: Option<i64>,
+    pub median_ms: Option<i64>,
+    pub p85_ms: Option<i64>,
+}
+
+/// Total time entries spent in `status` within the window, and how many entries did.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusTime {
+    pub status: EntryStatus,
+    pub total_ms: i64,
+    pub entries: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThroughputWeek {
+    pub week_start_ts: i64,
+    pub completed: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectFlow {
+    pub project: Option<String>,
+    pub project_id: Option<u64>,
+    pub points: Vec<FlowPoint>,
+}
+
+/// Entries per status at `ts`, the end of one day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowPoint {
+    pub ts: i64,
+    pub counts: Vec<StatusCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusCount {
+    pub status: EntryStatus,
+    pub count: u32,
+}
+
+/// Numbers for a project dashboard from `get_project_dashboard`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectDashboard {
+    pub project: Option<String>,
+    pub project_id: Option<u64>,
+    pub since_ts: i64,
+    pub until_ts: i64,
+    /// One point per day at the end of that day.
+    pub burndown: Vec<BurndownPoint>,
+    /// Open entries per timescale bucket.
+    pub timescales: Vec<TimescaleCount>,
+    pub overdue: u32,
+    pub completion_by_priority: Vec<PriorityCompletion>,
+    /// Busiest assignees first; `assignee` is `None` for unassigned entries.
+    pub workload: Vec<AssigneeWorkload>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BurndownPoint {
+    pub ts: i64,
+    pub open: u32,
+    pub done: u32,
+    pub open_estimate_minutes: u32,
+    pub done_estimate_minutes: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimescaleCount {
+    pub timescale: EntryTimescale,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityCompletion {
+    pub priority: EntryPriority,
+    pub total: u32,
+    pub done: u32,
+    pub completion_percent: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssigneeWorkload {
+    pub assignee: Option<String>,
+    pub open: u32,
+    pub open_estimate_minutes: u32,
+    pub overdue: u32,
+    pub completed_in_window: u32,
+}
+
+/// A deleted entry or note, kept with its links intact so it can be restored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub record: TrashedRecord,
+    pub deleted_at_ts: i64,
+    /// For subtasks removed by a cascading delete, the entry that was deleted.
+    pub deleted_with: Option<u64>,
+    /// Entries that depended on the trashed entry; restoring it re-adds those dependencies.
+    #[serde(default)]
+    pub dependents: Vec<u64>,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TrashedRecord {
+    Entry(Entry),
+    Note(Note),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TrashKey {
+    Entry(u64),
+    Note(u64),
+}
+
+/// A saved `search_all` query.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartList {
+    pub id: u64,
+    pub name: String,
+    pub query: String,
+    /// Manual order of the members that were moved; the rest follow in query order.
+    #[serde(default)]
+    pub entry_ranks: Vec<EntryRank>,
+}
+
+/// Where an entry sits in a manually ordered column or smart list.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EntryRank {
+    pub entry_id: u64,
+    pub rank: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartListDraft {
+    pub id: Option<u64>,
+    pub name: String,
+    pub query: String,
+}
+
+/// Outcome of a scheduled timescale refresh.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimescaleRefresh {
+    /// Entries whose timescale moved to a different bucket.
+    pub changed_entry_ids: Vec<u64>,
+    /// Next local midnight, when the following refresh is due.
+    pub next_refresh_ts: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppBootstrap {
+    pub entries: Vec<Entry>,
+    pub notes: Vec<Note>,
+    pub projects: Vec<Project>,
+    pub sprints: Vec<Sprint>,
+    pub workflows: Vec<Workflow>,
+    pub settings: UserSettings,
+    pub is_public_mode: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchAllResult {
+    pub entries: Vec<Entry>,
+    pub notes: Vec<Note>,
+    /// Entries and notes that matched the query text, most relevant first.
+    pub hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SearchHitKind {
+    Entry,
+    Note,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SearchField {
+    Title,
+    Summary,
+    Description,
+    Content,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub kind: SearchHitKind,
+    pub id: u64,
+    pub score: f64,
+    /// Field the snippet was taken from.
+    pub field: SearchField,
+    pub snippet: Vec<SnippetSegment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnippetSegment {
+    pub text: String,
+    pub highlighted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleReport {
+    pub generated_at_ts: i64,
+    pub projects: Vec<ProjectSchedule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectSchedule {
+    pub project: Option<String>,
+    pub start_ts: i64,
+    pub finish_ts: i64,
+    /// Entry ids along the critical path, first to last.
+    pub critical_path: Vec<u64>,
+    /// Entries in topological order.
+    pub entries: Vec<ScheduledEntry>,
+    pub infeasible: Vec<InfeasibleEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledEntry {
+    pub entry_id: u64,
+    pub duration_minutes: u32,
+    pub earliest_start_ts: i64,
+    pub earliest_finish_ts: i64,
+    pub latest_start_ts: i64,
+    /// The earlier of the project finish, the entry's due date and what its successors need.
+    pub latest_finish_ts: i64,
+    /// Negative when the entry cannot make its due date.
+    pub slack_ms: i64,
+    pub is_critical: bool,
+}
+
+/// An entry whose predecessors cannot finish in time for its due date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InfeasibleEntry {
+    pub entry_id: u64,
+    pub due_ts: i64,
+    pub earliest_finish_ts: i64,
+    pub overrun_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WsServerMessage {
+    /// `seq` is the latest change already reflected in the snapshot.
+    Snapshot {
+        entries: Vec<Entry>,
+        notes: Vec<Note>,
+        projects: Vec<Project>,
+        sprints: Vec<Sprint>,
+        workflows: Vec<Workflow>,
+        seq: u64,
+    },
+    /// `ancestors` lists the parent chain (nearest first) with refreshed progress.
+    EntryUpdated {
+        entry: Box<Entry>,
+        ancestors: Vec<Entry>,
+        seq: u64,
+    },
+    EntryRemoved {
+        entry_id: u64,
+        ancestors: Vec<Entry>,
+        seq: u64,
+    },
+    NoteUpdated {
+        note: Note,
+        seq: u64,
+    },
+    NoteRemoved {
+        note_id: u64,
+        seq: u64,
+    },
+    ProjectUpdated {
+        project: Project,
+        seq: u64,
+    },
+    ProjectRemoved {
+        project_id: u64,
+        seq: u64,
+    },
+    SprintUpdated {
+        sprint: Sprint,
+        seq: u64,
+    },
+    SprintRemoved {
+        sprint_id: u64,
+        seq: u64,
+    },
+    WorkflowUpdated {
+        workflow: Workflow,
+        seq: u64,
+    },
+    WorkflowRemoved {
+        workflow_id: u64,
+        seq: u64,
+    },
+    AutomationRuleUpdated {
+        rule: AutomationRule,
+        seq: u64,
+    },
+    AutomationRuleRemoved {
+        rule_id: u64,
+        seq: u64,
+    },
+    /// The changes of one `apply_batch` call, latest per record; `seq` is the last of them.
+    Batch {
+        changes: Vec<WsServerMessage>,
+        seq: u64,
+    },
+    SettingsUpdated {
+        settings: UserSettings,
+    },
+    /// Every limited column currently over its limit; empty once all are back under.
+    WipStatus {
+        over_limit: Vec<WipUsage>,
+    },
+    /// Ranks changed by a move: within status columns, or within `list_id` when set.
+    EntriesReranked {
+        list_id: Option<u64>,
+        ranks: Vec<EntryRank>,
+        seq: u64,
+    },
+    SmartListSaved {
+        list: SmartList,
+    },
+    SmartListRemoved {
+        list_id: u64,
+    },
+    /// Sent to a channel when it subscribes to a smart list.
+    SmartListSnapshot {
+        list_id: u64,
+        entries: Vec<Entry>,
+    },
+    SmartListEntered {
+        list_id: u64,
+        entry: Box<Entry>,
+    },
+    SmartListLeft {
+        list_id: u64,
+        entry_id: u64,
+    },
+    /// An entry that stayed in the list but changed.
+    SmartListEntryUpdated {
+        list_id: u64,
+        entry: Box<Entry>,
+    },
+    /// Reply to a client message that could not be read.
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+enum WsClientMessage {
+    Subscribe,
+    /// Subscribes after a reconnect, replaying the changes after `since_seq` instead of
+    /// sending a snapshot while the change log still holds them.
+    Resume {
+        since_seq: u64,
+    },
+    /// Follow one smart list instead of receiving every change.
+    SubscribeList {
+        list_id: u64,
+    },
+    UnsubscribeList {
+        list_id: u64,
+    },
+    Ping,
+}
+
+#[hyperapp_macro::hyperapp(
+    name = "Todo App",
+    ui = Some(hyperware_process_lib::http::server::HttpBindingConfig::default().authenticated(false)),
+    endpoints = vec![
+        hyperware_process_lib::hyperapp::Binding::Http {
+            path: "/api",
+            config: hyperware_process_lib::http::server::HttpBindingConfig::default().authenticated(false),
+        },
+        hyperware_process_lib::hyperapp::Binding::Ws {
+            path: "/ws",
+            config: hyperware_process_lib::http::server::WsBindingConfig::default().authenticated(false),
+        },
+    ],
+    save_config = hyperware_process_lib::hyperapp::SaveOptions::EveryMessage,
+    wit_world = "todo-ware-dot-hypr-v0"
+)]
+impl TodoState {
+    #[init]
+    async fn initialize(&mut self) {
+        add_to_homepage("Todo App", Some(ICON), Some("/"), None);
+        self.connected_channels.clear();
+        self.refresh_all_progress();
+        self.rebuild_search_index();
+        self.purge_expired_trash();
+        self.seed_note_versions();
+        self.migrate_projects();
+        self.migrate_entry_states();
+        self.migrate_entry_ranks();
+        self.journal
+            .reset(&self.entries, &self.notes, &self.projects);
+        let refresh = self.refresh_timescales_now();
+        schedule_timescale_refresh(refresh.next_refresh_ts);
+        println!("Todo app ready on node {}", our().node.clone());
+        self.ensure_demo_content();
+    }
+
+    #[http]
+    async fn bootstrap(&mut self) -> Result<AppBootstrap, String> {
+        self.ensure_demo_content();
+        Ok(AppBootstrap {
+            entries: self.entries.clone(),
+            notes: self.notes.clone(),
+            projects: self.projects.clone(),
+            sprints: self.sprints.clone(),
+            workflows: self.workflows.clone(),
+            settings: self.settings.clone(),
+            #[cfg(feature = "public-mode")]
+            is_public_mode: true,
+            #[cfg(not(feature = "public-mode"))]
+            is_public_mode: false,
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
+    #[local]
+    #[http]
+    async fn save_entry(
+        &mut self,
+        draft: EntryDraft,
+        session_id: Option<String>,
+    ) -> Result<SavedEntry, EntrySaveError> {
+        let label = if draft.id.is_some() {
+            "Edit entry"
+        } else {
+            "Create entry"
+        };
+        self.journaled(session_id, label, |state| {
+            let before = draft
+                .id
+                .and_then(|id| state.entries.iter().find(|e| e.id == id))
+                .cloned();
+            let mut saved = state.save_entry_checked(draft)?;
+            let run = state.automate_entry_change(before, saved.entry.id);
+            if let Some(entry) = run.entries.iter().find(|e| e.id == saved.entry.id) {
+                saved.entry = entry.clone();
+            }
+            Ok(saved)
+        })
+    }
+
+    /// Updates only the fields named in `patch`, validated and broadcast like `save_entry`.
+    #[allow(clippy::result_large_err)]
+    #[local]
+    #[http]
+    async fn patch_entry(
+        &mut self,
+        entry_id: u64,
+        patch: EntryPatch,
+        session_id: Option<String>,
+    ) -> Result<Entry, EntrySaveError> {
+        self.journaled(session_id, "Edit entry", |state| {
+            state.apply_entry_patch(entry_id, patch)
+        })
+    }
+
+    /// Applies `operations` in order, all or nothing; see `BatchReport`.
+    #[local]
+    #[http]
+    async fn apply_batch(
+        &mut self,
+        operations: Vec<BatchOperation>,
+        session_id: Option<String>,
+    ) -> Result<BatchReport, String> {
+        Ok(self.journaled(session_id, "Batch edit", |state| {
+            state.apply_batch_operations(operations)
+        }))
+    }
+
+    #[local]
+    #[http]
+    async fn toggle_entry_completion(
+        &mut self,
+        entry_id: u64,
+        completed: bool,
+        session_id: Option<String>,
+    ) -> Result<Entry, String> {
+        let label = if completed {
+            "Complete entry"
+        } else {
+            "Reopen entry"
+        };
+        self.journaled(session_id, label, |state| {
+            let before = state.entries.iter().find(|e| e.id == entry_id).cloned();
+            let entry = state.set_entry_completion(entry_id, completed)?;
+            let run = state.automate_entry_change(before, entry_id);
+            Ok(run
+                .entries
+                .into_iter()
+                .find(|e| e.id == entry_id)
+                .unwrap_or(entry))
+        })
+    }
+
+    /// Deletes an entry; its subtasks are re-parented unless `children` asks for a cascade.
+    #[local]
+    #[http]
+    async fn delete_entry(
+        &mut self,
+        entry_id: u64,
+        children: Option<ChildEntryPolicy>,
+        session_id: Option<String>,
+    ) -> Result<bool, String> {
+        self.journaled(session_id, "Delete entry", |state| {
+            state.remove_entry(entry_id, children)
+        })
+    }
+
+    /// Parses an RFC 5545 RRULE string (with or without the `RRULE:` prefix).
+    #[local]
+    #[http]
+    async fn parse_recurrence(&self, rrule: String) -> Result<RecurrenceRule, String> {
+        parse_rrule(&rrule, &self.calendar())
+    }
+
+    /// Critical path analysis over entry dependencies, optionally limited to one project.
+    #[local]
+    #[http]
+    async fn compute_schedule(&self, project: Option<String>) -> Result<ScheduleReport, String> {
+        schedule::compute_schedule(&self.entries, now_ts(), project.as_deref())
+    }
+
+    #[local]
+    #[http]
+    async fn get_settings(&self) -> Result<UserSettings, String> {
+        Ok(self.settings.clone())
+    }
+
+    /// Replaces the calendar settings and re-buckets every entry under the new rules.
+    #[local]
+    #[http]
+    async fn update_settings(
+        &mut self,
+        mut settings: UserSettings,
+    ) -> Result<UserSettings, String> {
+        validate_settings(&settings)?;
+        let mut seen_days = Vec::new();
+        settings.working_days.retain(|day| {
+            let first = !seen_days.contains(day);
+            seen_days.push(*day);
+            first
+        });
+
+        self.settings = settings.clone();
+        for entry_id in self.refresh_all_timescales() {
+            self.broadcast_entry(entry_id);
+        }
+        self.broadcast(&WsServerMessage::SettingsUpdated {
+            settings: settings.clone(),
+        });
+        // Date terms in smart list queries follow the time zone
+        self.invalidate_smart_lists();
+        self.refresh_live_views();
+        Ok(settings)
+    }
+
+    /// Re-buckets entries once the local day rolls over and purges expired trash; called by
+    /// the midnight timer.
+    #[local]
+    async fn refresh_timescales(&mut self) -> Result<TimescaleRefresh, String> {
+        self.purge_expired_trash();
+        Ok(self.refresh_timescales_now())
+    }
+
+    #[local]
+    #[http]
+    async fn get_smart_lists(&self) -> Result<Vec<SmartList>, String> {
+        Ok(self.smart_lists.clone())
+    }
+
+    #[local]
+    #[http]
+    async fn save_smart_list(&mut self, draft: SmartListDraft) -> Result<SmartList, String> {
+        self.validate_smart_list(&draft.name, &draft.query)?;
+
+        let list = if let Some(id) = draft.id {
+            let list = self
+                .smart_lists
+                .iter_mut()
+                .find(|l| l.id == id)
+                .ok_or_else(|| "Smart list not found".to_string())?;
+            list.name = draft.name;
+            list.query = draft.query;
+            list.clone()
+        } else {
+            let list = SmartList {
+                id: self.next_smart_list_id(),
+                name: draft.name,
+                query: draft.query,
+                entry_ranks: Vec::new(),
+            };
+            self.smart_lists.push(list.clone());
+            list
+        };
+
+        self.broadcast(&WsServerMessage::SmartListSaved { list: list.clone() });
+        self.refresh_smart_lists();
+        Ok(list)
+    }
+
+    #[local]
+    #[http]
+    async fn delete_smart_list(&mut self, list_id: u64) -> Result<bool, String> {
+        let Some(idx) = self.smart_lists.iter().position(|l| l.id == list_id) else {
+            return Err("Smart list not found".to_string());
+        };
+        self.smart_lists.remove(idx);
+        self.broadcast(&WsServerMessage::SmartListRemoved { list_id });
+        self.refresh_smart_lists();
+        Ok(true)
+    }
+
+    /// Runs a saved smart list's query, exactly as `search_all` would.
+    #[local]
+    #[http]
+    async fn run_smart_list(&self, list_id: u64) -> Result<SearchAllResult, String> {
+        let list = self
+            .smart_lists
+            .iter()
+            .find(|l| l.id == list_id)
+            .ok_or_else(|| "Smart list not found".to_string())?;
+        let calendar = self.calendar();
+        let query = parse_query(&list.query, &calendar)?;
+        let mut result = self.run_query(&query, &calendar);
+        order_smart_list(list, &mut result.entries);
+        Ok(result)
+    }
+
+    /// Drops `entry_id` between `before_id` (the entry left directly above it) and
+    /// `after_id` (directly below) in its status column, or in smart list `list_id`.
+    /// Returns the ranks that changed.
+    #[local]
+    #[http]
+    async fn move_entry(
+        &mut self,
+        entry_id: u64,
+        list_id: Option<u64>,
+        before_id: Option<u64>,
+        after_id: Option<u64>,
+    ) -> Result<Vec<EntryRank>, String> {
+        self.move_entry_rank(entry_id, list_id, before_id, after_id)
+    }
+
+    #[local]
+    #[http]
+    async fn get_projects(&self) -> Result<Vec<Project>, String> {
+        Ok(self.projects.clone())
+    }
+
+    /// Renaming or switching workflow updates the member entries as one undoable step.
+    #[local]
+    #[http]
+    async fn save_project(
+        &mut self,
+        draft: ProjectDraft,
+        session_id: Option<String>,
+    ) -> Result<Project, String> {
+        self.journaled(session_id, "Edit project", |state| {
+            state.save_project_draft(draft)
+        })
+    }
+
+    /// Archiving shelves the project's open entries; unarchiving puts them back in the
+    /// states they were shelved from.
+    #[local]
+    #[http]
+    async fn archive_project(
+        &mut self,
+        project_id: u64,
+        archived: bool,
+        session_id: Option<String>,
+    ) -> Result<Project, String> {
+        let label = if archived {
+            "Archive project"
+        } else {
+            "Unarchive project"
+        };
+        self.journaled(session_id, label, |state| {
+            state.set_project_archived(project_id, archived)
+        })
+    }
+
+    /// Moves every entry of `source_id` into `target_id` and deletes `source_id`.
+    #[local]
+    #[http]
+    async fn merge_projects(
+        &mut self,
+        source_id: u64,
+        target_id: u64,
+        session_id: Option<String>,
+    ) -> Result<Project, String> {
+        self.journaled(session_id, "Merge projects", |state| {
+            state.merge_project_into(source_id, target_id)
+        })
+    }
+
+    /// Deletes the project record; its entries are kept without a project.
+    #[local]
+    #[http]
+    async fn delete_project(
+        &mut self,
+        project_id: u64,
+        session_id: Option<String>,
+    ) -> Result<bool, String> {
+        self.journaled(session_id, "Delete project", |state| {
+            state.remove_project(project_id)
+        })?;
+        Ok(true)
+    }
+
+    #[local]
+    #[http]
+    async fn get_sprints(&self) -> Result<Vec<Sprint>, String> {
+        Ok(self.sprints.clone())
+    }
+
+    #[local]
+    #[http]
+    async fn save_sprint(&mut self, draft: SprintDraft) -> Result<Sprint, String> {
+        self.save_sprint_draft(draft)
+    }
+
+    /// Commits the sprint's current open entries and starts tracking scope changes.
+    #[local]
+    #[http]
+    async fn start_sprint(&mut self, sprint_id: u64) -> Result<Sprint, String> {
+        self.begin_sprint(sprint_id)
+    }
+
+    /// Closes a running sprint. Unfinished entries move to `carry_over_to`, or out of any
+    /// sprint when it is `None`.
+    #[local]
+    #[http]
+    async fn close_sprint(
+        &mut self,
+        sprint_id: u64,
+        carry_over_to: Option<u64>,
+        session_id: Option<String>,
+    ) -> Result<SprintReport, String> {
+        self.journaled(session_id, "Close sprint", |state| {
+            state.finish_sprint(sprint_id, carry_over_to)
+        })?;
+        self.sprint_report(sprint_id)
+    }
+
+    /// Deletes the sprint record; its entries are kept outside any sprint.
+    #[local]
+    #[http]
+    async fn delete_sprint(
+        &mut self,
+        sprint_id: u64,
+        session_id: Option<String>,
+    ) -> Result<bool, String> {
+        self.journaled(session_id, "Delete sprint", |state| {
+            state.remove_sprint(sprint_id)
+        })?;
+        Ok(true)
+    }
+
+    #[local]
+    #[http]
+    async fn get_sprint_report(&self, sprint_id: u64) -> Result<SprintReport, String> {
+        self.sprint_report(sprint_id)
+    }
+
+    #[local]
+    #[http]
+    async fn get_workflows(&self) -> Result<Vec<Workflow>, String> {
+        Ok(self.workflows.clone())
+    }
+
+    /// Creates or replaces a workflow. States still held by entries cannot be dropped.
+    #[local]
+    #[http]
+    async fn save_workflow(
+        &mut self,
+        draft: WorkflowDraft,
+        session_id: Option<String>,
+    ) -> Result<Workflow, String> {
+        self.journaled(session_id, "Edit workflow", |state| {
+            state.save_workflow_draft(draft)
+        })
+    }
+
+    #[local]
+    #[http]
+    async fn delete_workflow(
+        &mut self,
+        workflow_id: u64,
+        session_id: Option<String>,
+    ) -> Result<bool, String> {
+        self.journaled(session_id, "Delete workflow", |state| {
+            state.remove_workflow(workflow_id)
+        })?;
+        Ok(true)
+    }
+
+    #[local]
+    #[http]
+    async fn get_automation_rules(&self) -> Result<Vec<AutomationRule>, String> {
+        Ok(self.automation_rules.clone())
+    }
+
+    #[local]
+    #[http]
+    async fn save_automation_rule(
+        &mut self,
+        draft: AutomationRuleDraft,
+    ) -> Result<AutomationRule, String> {
+        self.save_rule_draft(draft)
+    }
+
+    #[local]
+    #[http]
+    async fn delete_automation_rule(&mut self, rule_id: u64) -> Result<bool, String> {
+        self.remove_rule(rule_id)?;
+        Ok(true)
+    }
+
+    /// Shows what saving `draft` would set off, with `rule` (if given) tried out alongside
+    /// the saved rules. Nothing is kept or broadcast.
+    #[local]
+    #[http]
+    async fn dry_run_automation(
+        &mut self,
+        draft: EntryDraft,
+        rule: Option<AutomationRuleDraft>,
+    ) -> Result<AutomationPreview, String> {
+        self.preview_automation(draft, rule)
+    }
+
+    #[allow(clippy::result_large_err)]
+    #[local]
+    #[http]
+    async fn save_note(
+        &mut self,
+        draft: NoteDraft,
+        session_id: Option<String>,
+    ) -> Result<Note, NoteSaveError> {
+        let label = if draft.id.is_some() {
+            "Edit note"
+        } else {
+            "Create note"
+        };
+        self.journaled(session_id, label, |state| {
+            let note = state.save_note_draft(draft)?;
+            state.automate_note_change(note.id);
+            Ok(note)
+        })
+    }
+
+    /// Updates only the fields named in `patch`, validated and broadcast like `save_note`.
+    #[allow(clippy::result_large_err)]
+    #[local]
+    #[http]
+    async fn patch_note(
+        &mut self,
+        note_id: u64,
+        patch: NotePatch,
+        session_id: Option<String>,
+    ) -> Result<Note, NoteSaveError> {
+        self.journaled(session_id, "Edit note", |state| {
+            state.apply_note_patch(note_id, patch)
+        })
+    }
+
+    #[local]
+    #[http]
+    async fn delete_note(
+        &mut self,
+        note_id: u64,
+        session_id: Option<String>,
+    ) -> Result<bool, String> {
+        self.journaled(session_id, "Delete note", |state| {
+            state.remove_note(note_id)
+        })
+    }
+
+    /// Pages through recorded entry changes, newest first, for `entry_id` or every entry.
+    #[local]
+    #[http]
+    async fn get_activity(
+        &self,
+        entry_id: Option<u64>,
+        before_id: Option<u64>,
+        limit: Option<u32>,
+    ) -> Result<ActivityPage, String> {
+        Ok(self.activity_page(entry_id, before_id, limit))
+    }
+
+    /// Lead time, cycle time, time per status, weekly throughput and cumulative flow,
+    /// rebuilt from the activity log. The window defaults to the last 12 weeks.
+    #[local]
+    #[http]
+    async fn get_flow_analytics(
+        &self,
+        project_id: Option<u64>,
+        since_ts: Option<i64>,
+        until_ts: Option<i64>,
+    ) -> Result<FlowAnalytics, String> {
+        self.flow_analytics(project_id, since_ts, until_ts)
+    }
+
+    /// Burndown and completion numbers for one project (or all entries), computed here so
+    /// every client shows the same figures. The burndown window defaults to the last 14 days.
+    #[local]
+    #[http]
+    async fn get_project_dashboard(
+        &self,
+        project_id: Option<u64>,
+        since_ts: Option<i64>,
+        until_ts: Option<i64>,
+    ) -> Result<ProjectDashboard, String> {
+        self.project_dashboard(project_id, since_ts, until_ts)
+    }
+
+    /// Every saved version of a note, oldest first.
+    #[local]
+    #[http]
+    async fn get_note_versions(&self, note_id: u64) -> Result<Vec<NoteVersion>, String> {
+        let versions: Vec<NoteVersion> = self
+            .note_versions
+            .iter()
+            .filter(|v| v.note_id == note_id)
+            .cloned()
+            .collect();
+        if versions.is_empty() {
+            return Err("Note not found".to_string());
+        }
+        Ok(versions)
+    }
+
+    /// Line diff of a note's text between two of its versions, in either order.
+    #[local]
+    #[http]
+    async fn diff_note_versions(
+        &self,
+        note_id: u64,
+        from_version: u32,
+        to_version: u32,
+    ) -> Result<NoteDiff, String> {
+        self.note_version_diff(note_id, from_version, to_version)
+    }
+
+    /// Saves an older version's title and text as the newest version of the note.
+    #[allow(clippy::result_large_err)]
+    #[local]
+    #[http]
+    async fn restore_note_version(
+        &mut self,
+        note_id: u64,
+        version: u32,
+        expected_revision: Option<u64>,
+        session_id: Option<String>,
+    ) -> Result<Note, NoteSaveError> {
+        let old = self.note_version(note_id, version)?;
+        let latest_before = self.latest_note_version(note_id);
+        let patch = NotePatch {
+            expected_revision,
+            title: Some(old.title.clone()),
+            content: Some(old.content.clone()),
+            ..NotePatch::default()
+        };
+        let note = self.journaled(session_id, "Restore note version", |state| {
+            state.apply_note_patch(note_id, patch)
+        })?;
+        if self.latest_note_version(note_id) != latest_before {
+            if let Some(latest) = self
+                .note_versions
+                .iter_mut()
+                .rfind(|v| v.note_id == note_id)
+            {
+                latest.restored_from = Some(version);
+            }
+        }
+        Ok(note)
+    }
+
+    /// Deleted entries and notes, oldest first. Items are purged automatically once
+    /// `UserSettings::trash_retention_days` have passed.
+    #[local]
+    #[http]
+    async fn get_trash(&self) -> Result<Vec<TrashItem>, String> {
+        Ok(self.trash.clone())
+    }
+
+    /// Restores a deleted entry with the subtasks its delete cascaded to. Links to notes
+    /// and parents that are gone are dropped.
+    #[local]
+    #[http]
+    async fn restore_entry(
+        &mut self,
+        entry_id: u64,
+        session_id: Option<String>,
+    ) -> Result<Entry, String> {
+        self.journaled(session_id, "Restore entry", |state| {
+            state.restore_entry_from_trash(entry_id)
+        })
+    }
+
+    #[local]
+    #[http]
+    async fn restore_note(
+        &mut self,
+        note_id: u64,
+        session_id: Option<String>,
+    ) -> Result<Note, String> {
+        self.journaled(session_id, "Restore note", |state| {
+            state.restore_note_from_trash(note_id)
+        })
     }
 
+    /// Permanently deletes `items` from the trash, or empties it when `items` is `None`.
+    /// Returns how many items were purged.
     #[local]
     #[http]
-    async fn delete_entry(&mut self, entry_id: u64) -> Result<bool, String> {
-        if let Some(idx) = self.entries.iter().position(|e| e.id == entry_id) {
-            let entry = self.entries.remove(idx);
-            let touched_notes = self.sync_entry_note_links(entry.id, Vec::new());
-            self.broadcast(&WsServerMessage::EntryRemoved { entry_id });
-            for note in touched_notes {
-                self.broadcast(&WsServerMessage::NoteUpdated { note });
-            }
-            Ok(true)
-        } else {
-            Err("Entry not found".to_string())
-        }
+    async fn purge_trash(&mut self, items: Option<Vec<TrashKey>>) -> Result<u32, String> {
+        Ok(self.purge_trash_items(items))
     }
 
+    /// Reverts the latest change made in `session_id`, re-broadcasting the restored records.
+    ///
+    /// Fails without touching anything if a record involved was changed since.
     #[local]
     #[http]
-    async fn save_note(&mut self, draft: NoteDraft) -> Result<Note, String> {
-        if draft.title.trim().is_empty() {
-            return Err("Notes require a title.".to_string());
-        }
-
-        let accent = draft
-            .accent
-            .unwrap_or_else(|| random_accent_for(&draft.tags));
-
-        let note = if let Some(id) = draft.id {
-            let note = self
-                .notes
-                .iter_mut()
-                .find(|n| n.id == id)
-                .ok_or_else(|| "Note not found".to_string())?;
-
-            note.title = draft.title;
-            note.content = draft.content;
-            note.pinned = draft.pinned;
-            note.tags = draft.tags;
-            note.linked_entry_ids = draft.linked_entry_ids.clone();
-            note.summary = summarize_text(&note.content);
-            note.last_edited_ts = now_ts();
-            note.accent = accent;
-            note.clone()
-        } else {
-            let mut note = Note {
-                id: self.next_note_id(),
-                title: draft.title,
-                content: draft.content,
-                pinned: draft.pinned,
-                tags: draft.tags,
-                linked_entry_ids: draft.linked_entry_ids.clone(),
-                summary: String::new(),
-                accent,
-                last_edited_ts: now_ts(),
-            };
-            note.summary = summarize_text(&note.content);
-            self.notes.push(note.clone());
-            note
-        };
+    async fn undo(&mut self, session_id: Option<String>) -> Result<HistoryRestore, String> {
+        self.step_history(session_id, false)
+    }
 
-        let touched_entries = self.sync_note_entry_links(note.id, note.linked_entry_ids.clone());
-        for entry in touched_entries {
-            self.broadcast(&WsServerMessage::EntryUpdated { entry });
-        }
-        self.broadcast(&WsServerMessage::NoteUpdated { note: note.clone() });
-        Ok(note)
+    /// Re-applies the change most recently undone in `session_id`.
+    #[local]
+    #[http]
+    async fn redo(&mut self, session_id: Option<String>) -> Result<HistoryRestore, String> {
+        self.step_history(session_id, true)
     }
 
     #[local]
     #[http]
-    async fn delete_note(&mut self, note_id: u64) -> Result<bool, String> {
-        if let Some(idx) = self.notes.iter().position(|n| n.id == note_id) {
-            self.notes.remove(idx);
-            let touched_entries = self.sync_note_entry_links(note_id, Vec::new());
-            self.broadcast(&WsServerMessage::NoteRemoved { note_id });
-            for entry in touched_entries {
-                self.broadcast(&WsServerMessage::EntryUpdated { entry });
-            }
-            Ok(true)
-        } else {
-            Err("Note not found".to_string())
-        }
+    async fn get_history(&self, session_id: Option<String>) -> Result<HistoryStatus, String> {
+        Ok(self.journal.status(&session_id.unwrap_or_default()))
     }
 
+    /// Runs a structured query (see `query::parse_query`) over entries and notes.
+    ///
+    /// Free text is matched through the search index and ranks the results; archived entries
+    /// are only returned when the query asks for them, e.g. `is:archived`.
     #[local]
     #[http]
     async fn search_all(&self, query: Option<String>) -> Result<SearchAllResult, String> {
-        let query = query.unwrap_or_default();
-        let query_lower = query.to_lowercase();
-        let match_all = query.is_empty() || query == "*";
-
-        let matching_entries: Vec<Entry> = self
-            .entries
-            .iter()
-            .filter(|entry| {
-                // Exclude archived entries from search results
-                if entry.status == EntryStatus::Archived {
-                    return false;
-                }
-                if match_all {
-                    return true;
-                }
-                entry.title.to_lowercase().contains(&query_lower)
-                    || entry.summary.to_lowercase().contains(&query_lower)
-                    || entry.description.to_lowercase().contains(&query_lower)
-                    || entry
-                        .project
-                        .as_ref()
-                        .map(|p| p.to_lowercase().contains(&query_lower))
-                        .unwrap_or(false)
-                    || entry
-                        .assignees
-                        .iter()
-                        .any(|a| a.to_lowercase().contains(&query_lower))
-            })
-            .cloned()
-            .collect();
-
-        let matching_notes: Vec<Note> = self
-            .notes
-            .iter()
-            .filter(|note| {
-                if match_all {
-                    return true;
-                }
-                note.title.to_lowercase().contains(&query_lower)
-                    || note.content.to_lowercase().contains(&query_lower)
-                    || note.summary.to_lowercase().contains(&query_lower)
-                    || note
-                        .tags
-                        .iter()
-                        .any(|t| t.to_lowercase().contains(&query_lower))
-            })
-            .cloned()
-            .collect();
-
-        Ok(SearchAllResult {
-            entries: matching_entries,
-            notes: matching_notes,
-        })
+        let calendar = self.calendar();
+        let query = parse_query(query.as_deref().unwrap_or_default(), &calendar)?;
+        Ok(self.run_query(&query, &calendar))
     }
 
     #[http]
@@ -585,22 +2199,37 @@ impl TodoState {
     fn websocket(&mut self, channel_id: u32, message_type: WsMessageType, blob: LazyLoadBlob) {
         match message_type {
             WsMessageType::Text => {
-                if let Ok(text) = String::from_utf8(blob.bytes) {
-                    if let Ok(msg) = serde_json::from_str::<WsClientMessage>(&text) {
-                        match msg {
-                            WsClientMessage::Subscribe => {
-                                self.connected_channels.insert(channel_id);
-                                self.send_snapshot(channel_id);
-                            }
-                            WsClientMessage::Ping => {
-                                // Keep-alive; no action needed beyond acknowledging receipt
-                            }
-                        }
+                let msg = String::from_utf8(blob.bytes)
+                    .map_err(|err| err.to_string())
+                    .and_then(|text| {
+                        serde_json::from_str::<WsClientMessage>(&text)
+                            .map_err(|err| err.to_string())
+                    });
+                match msg {
+                    Ok(WsClientMessage::Subscribe) => self.subscribe_channel(channel_id, None),
+                    Ok(WsClientMessage::Resume { since_seq }) => {
+                        self.subscribe_channel(channel_id, Some(since_seq));
+                    }
+                    Ok(WsClientMessage::SubscribeList { list_id }) => {
+                        self.subscribe_to_smart_list(channel_id, list_id);
+                    }
+                    Ok(WsClientMessage::UnsubscribeList { list_id }) => {
+                        self.unsubscribe_from_smart_lists(channel_id, Some(list_id));
+                    }
+                    Ok(WsClientMessage::Ping) => {
+                        // Keep-alive; no action needed beyond acknowledging receipt
                     }
+                    Err(err) => self.send_ws_message(
+                        channel_id,
+                        &WsServerMessage::Error {
+                            message: format!("Unreadable message: {err}"),
+                        },
+                    ),
                 }
             }
             WsMessageType::Close => {
                 self.connected_channels.remove(&channel_id);
+                self.unsubscribe_from_smart_lists(channel_id, None);
             }
             WsMessageType::Pong | WsMessageType::Ping | WsMessageType::Binary => {}
         }
@@ -612,6 +2241,373 @@ impl TodoState {
         // No demo content - users start with an empty slate
     }
 
+    #[allow(clippy::result_large_err)]
+    fn save_entry_draft(&mut self, draft: EntryDraft) -> Result<Entry, EntrySaveError> {
+        self.save_entry_checked(draft).map(|saved| saved.entry)
+    }
+
+    /// Validates and stores `draft`, then broadcasts every record it touched.
+    #[allow(clippy::result_large_err)]
+    fn save_entry_checked(&mut self, mut draft: EntryDraft) -> Result<SavedEntry, EntrySaveError> {
+        if let (Some(id), Some(expected_revision)) = (draft.id, draft.expected_revision) {
+            if let Some(current) = self.entries.iter().find(|e| e.id == id) {
+                if current.revision != expected_revision {
+                    return Err(EntrySaveError::Conflict(EntryConflict {
+                        expected_revision,
+                        current: current.clone(),
+                    }));
+                }
+            }
+        }
+        if draft.title.trim().is_empty() {
+            return Err("Entries require a title.".to_string().into());
+        }
+
+        if draft.summary.trim().is_empty() {
+            draft.summary = summarize_text(&draft.description);
+        }
+
+        if let Some(parent_id) = draft.parent_id {
+            self.validate_parent(draft.id, parent_id)?;
+        }
+
+        let mut seen_dependencies = HashSet::new();
+        draft
+            .dependencies
+            .retain(|id| seen_dependencies.insert(*id));
+        self.validate_dependencies(draft.id, &draft.dependencies)?;
+
+        if let Some(rule) = &draft.recurrence {
+            validate_recurrence(rule)?;
+        }
+        let series_scope = draft
+            .series_scope
+            .unwrap_or(SeriesEditScope::ThisOccurrence);
+        let previous_sprint = self.check_sprint_assignment(draft.id, draft.sprint_id)?;
+        let project =
+            self.resolve_entry_project(draft.id, draft.project_id, draft.project.as_deref())?;
+        let state = self.resolve_entry_state(
+            draft.id,
+            project.as_ref().and_then(ProjectChoice::workflow_id),
+            draft.state.as_deref(),
+            &draft.status,
+        )?;
+        let wip_warnings = self.check_wip_limits(
+            draft.id,
+            &state_status(&state),
+            &draft.assignees,
+            state.category == WorkflowCategory::Done,
+        )?;
+        let (project_id, project) = match project.map(|choice| self.claim_project(choice)) {
+            Some(project) => (Some(project.id), Some(project.name)),
+            None => (None, None),
+        };
+
+        // An entry changing columns goes to the bottom of its new one; a dependency hold
+        // is not a column change
+        let column_rank = draft
+            .id
+            .and_then(|id| self.entries.iter().find(|e| e.id == id))
+            .filter(|e| *e.blocked_from.as_ref().unwrap_or(&e.status) != state_status(&state))
+            .map(|_| self.column_end_rank(&state_status(&state)));
+
+        let calendar = self.calendar();
+        let mut previous_parent = None;
+        let entry = if let Some(id) = draft.id {
+            let entry = self
+                .entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or_else(|| "Entry not found".to_string())?;
+
+            if entry.parent_id != draft.parent_id {
+                previous_parent = entry.parent_id;
+            }
+
+            entry.title = draft.title;
+            entry.summary = draft.summary;
+            entry.description = draft.description;
+            entry.project = project;
+            entry.project_id = project_id;
+            entry.sprint_id = draft.sprint_id;
+            set_entry_state(entry, &state);
+            if let Some(rank) = column_rank {
+                entry.rank = rank;
+            }
+            entry.priority = draft.priority;
+            entry.due_ts = draft.due_ts;
+            entry.start_ts = draft.start_ts;
+            entry.estimate_minutes = draft.estimate_minutes;
+            entry.dependencies = draft.dependencies;
+            entry.parent_id = draft.parent_id;
+            entry.note_ids = draft.note_ids.clone();
+            entry.assignees = draft.assignees;
+            entry.tags = draft.tags;
+            entry.recurrence = draft.recurrence;
+            if entry.recurrence.is_some() && entry.series_id.is_none() {
+                entry.series_id = Some(entry.id);
+                entry.occurrence = 1;
+            }
+            refresh_entry_timescale(entry, &calendar);
+            entry.clone()
+        } else {
+            let id = self.next_entry_id();
+            let series_id = draft.recurrence.as_ref().map(|_| id);
+            let rank = self.next_entry_rank();
+            let mut entry = Entry {
+                id,
+                title: draft.title,
+                summary: draft.summary,
+                description: draft.description,
+                project,
+                project_id,
+                sprint_id: draft.sprint_id,
+                state: state.key.clone(),
+                status: state_status(&state),
+                rank,
+                timescale: EntryTimescale::Someday,
+                priority: draft.priority,
+                due_ts: draft.due_ts,
+                start_ts: draft.start_ts,
+                estimate_minutes: draft.estimate_minutes,
+                dependencies: draft.dependencies,
+                parent_id: draft.parent_id,
+                note_ids: draft.note_ids.clone(),
+                assignees: draft.assignees,
+                tags: draft.tags,
+                is_completed: false,
+                completed_at_ts: None,
+                progress: 0,
+                recurrence: draft.recurrence,
+                series_id,
+                occurrence: if series_id.is_some() { 1 } else { 0 },
+                next_occurrence_id: None,
+                blocked_from: None,
+                archived_from: None,
+                revision: 0,
+            };
+            refresh_entry_timescale(&mut entry, &calendar);
+            self.entries.push(entry.clone());
+            entry
+        };
+
+        self.track_sprint_scope(entry.id, previous_sprint, entry.sprint_id);
+        let completed = state.category == WorkflowCategory::Done;
+        if completed != entry.is_completed {
+            self.apply_completion(entry.id, completed);
+        }
+        self.index_entry(entry.id);
+        let touched_notes = self.sync_entry_note_links(entry.id, entry.note_ids.clone());
+        for note in touched_notes {
+            self.broadcast_note(note.id);
+        }
+        if let Some(previous_parent) = previous_parent {
+            self.refresh_progress_upwards(previous_parent);
+            self.broadcast_entry(previous_parent);
+        }
+        self.refresh_blocked_status(entry.id);
+        self.refresh_progress_upwards(entry.id);
+        let entry = self.broadcast_entry(entry.id).unwrap_or(entry);
+
+        if series_scope == SeriesEditScope::WholeSeries {
+            for sibling_id in self.apply_to_series(&entry) {
+                self.index_entry(sibling_id);
+                self.broadcast_entry(sibling_id);
+            }
+        }
+        self.refresh_live_views();
+        Ok(SavedEntry {
+            entry,
+            wip_warnings,
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn save_note_draft(&mut self, draft: NoteDraft) -> Result<Note, NoteSaveError> {
+        if let (Some(id), Some(expected_revision)) = (draft.id, draft.expected_revision) {
+            if let Some(current) = self.notes.iter().find(|n| n.id == id) {
+                if current.revision != expected_revision {
+                    return Err(NoteSaveError::Conflict(NoteConflict {
+                        expected_revision,
+                        current: current.clone(),
+                    }));
+                }
+            }
+        }
+        if draft.title.trim().is_empty() {
+            return Err("Notes require a title.".to_string().into());
+        }
+
+        let accent = draft
+            .accent
+            .unwrap_or_else(|| random_accent_for(&draft.tags));
+
+        let note = if let Some(id) = draft.id {
+            let note = self
+                .notes
+                .iter_mut()
+                .find(|n| n.id == id)
+                .ok_or_else(|| "Note not found".to_string())?;
+
+            note.title = draft.title;
+            note.content = draft.content;
+            note.pinned = draft.pinned;
+            note.tags = draft.tags;
+            note.linked_entry_ids = draft.linked_entry_ids.clone();
+            note.summary = summarize_text(&note.content);
+            note.last_edited_ts = now_ts();
+            note.accent = accent;
+            note.clone()
+        } else {
+            let mut note = Note {
+                id: self.next_note_id(),
+                title: draft.title,
+                content: draft.content,
+                pinned: draft.pinned,
+                tags: draft.tags,
+                linked_entry_ids: draft.linked_entry_ids.clone(),
+                summary: String::new(),
+                accent,
+                last_edited_ts: now_ts(),
+                revision: 0,
+            };
+            note.summary = summarize_text(&note.content);
+            self.notes.push(note.clone());
+            note
+        };
+
+        self.index_note(note.id);
+        let touched_entries = self.sync_note_entry_links(note.id, note.linked_entry_ids.clone());
+        for entry in touched_entries {
+            self.broadcast_entry(entry.id);
+        }
+        self.record_note_version(note.id);
+        Ok(self.broadcast_note(note.id).unwrap_or(note))
+    }
+
+    fn set_entry_completion(&mut self, entry_id: u64, completed: bool) -> Result<Entry, String> {
+        if let Some(state) = self.completion_state(entry_id, completed)? {
+            if !completed {
+                let assignees = self
+                    .entries
+                    .iter()
+                    .find(|e| e.id == entry_id)
+                    .map(|e| e.assignees.clone())
+                    .unwrap_or_default();
+                self.check_wip_limits(Some(entry_id), &state_status(&state), &assignees, false)
+                    .map_err(EntrySaveError::into_message)?;
+            }
+            let rank = self.column_end_rank(&state_status(&state));
+            if let Some(entry) = self.entries.iter_mut().find(|e| e.id == entry_id) {
+                set_entry_state(entry, &state);
+                entry.rank = rank;
+            }
+        }
+        self.apply_completion(entry_id, completed);
+        // Live views pick up the changes broadcast before they refresh
+        let entry = self.broadcast_entry(entry_id);
+        self.refresh_live_views();
+        entry.ok_or_else(|| "Entry not found".to_string())
+    }
+
+    /// Marks `entry_id` complete or open and updates what depends on that: its timescale,
+    /// parent progress, the next occurrence of a series and blocked dependents. The entry
+    /// itself is left for the caller to broadcast.
+    fn apply_completion(&mut self, entry_id: u64, completed: bool) {
+        let calendar = self.calendar();
+        let Some(entry) = self.entries.iter_mut().find(|e| e.id == entry_id) else {
+            return;
+        };
+
+        entry.is_completed = completed;
+        entry.completed_at_ts = completed.then(|| calendar.now_ts());
+        let spawns_next =
+            completed && entry.recurrence.is_some() && entry.next_occurrence_id.is_none();
+
+        refresh_entry_timescale(entry, &calendar);
+        self.refresh_blocked_status(entry_id);
+        self.refresh_progress_upwards(entry_id);
+        if spawns_next {
+            if let Some(next_id) = self.spawn_next_occurrence(entry_id) {
+                self.index_entry(next_id);
+                self.refresh_blocked_status(next_id);
+                self.refresh_progress_upwards(next_id);
+                self.broadcast_entry(next_id);
+            }
+        }
+        for dependent_id in self.refresh_dependents(entry_id) {
+            self.broadcast_entry(dependent_id);
+        }
+    }
+
+    fn remove_entry(
+        &mut self,
+        entry_id: u64,
+        children: Option<ChildEntryPolicy>,
+    ) -> Result<bool, String> {
+        let Some(idx) = self.entries.iter().position(|e| e.id == entry_id) else {
+            return Err("Entry not found".to_string());
+        };
+        let parent_id = self.entries[idx].parent_id;
+
+        let removed_ids = match children.unwrap_or(ChildEntryPolicy::Reparent) {
+            ChildEntryPolicy::Cascade => self.subtree_ids(entry_id),
+            ChildEntryPolicy::Reparent => {
+                for child_id in self.reparent_children(entry_id, parent_id) {
+                    self.broadcast_entry(child_id);
+                }
+                vec![entry_id]
+            }
+        };
+
+        for &removed_id in &removed_ids {
+            if let Some(idx) = self.entries.iter().position(|e| e.id == removed_id) {
+                let removed = self.entries.remove(idx);
+                self.track_sprint_scope(removed_id, removed.sprint_id, None);
+                self.trash_entry(removed, (removed_id != entry_id).then_some(entry_id));
+            }
+            self.index_entry(removed_id);
+            let touched_notes = self.sync_entry_note_links(removed_id, Vec::new());
+            let ancestors = if removed_id == entry_id {
+                if let Some(parent_id) = parent_id {
+                    self.refresh_progress_upwards(parent_id);
+                }
+                self.ancestors_from(parent_id)
+            } else {
+                Vec::new()
+            };
+            self.publish_change(|seq| WsServerMessage::EntryRemoved {
+                entry_id: removed_id,
+                ancestors,
+                seq,
+            });
+            for note in touched_notes {
+                self.broadcast_note(note.id);
+            }
+        }
+        for dependent_id in self.remove_dependency_references(&removed_ids) {
+            self.broadcast_entry(dependent_id);
+        }
+        self.refresh_live_views();
+        Ok(true)
+    }
+
+    fn remove_note(&mut self, note_id: u64) -> Result<bool, String> {
+        if let Some(idx) = self.notes.iter().position(|n| n.id == note_id) {
+            let removed = self.notes.remove(idx);
+            self.trash_note(removed);
+            self.index_note(note_id);
+            let touched_entries = self.sync_note_entry_links(note_id, Vec::new());
+            self.publish_change(|seq| WsServerMessage::NoteRemoved { note_id, seq });
+            for entry in touched_entries {
+                self.broadcast_entry(entry.id);
+            }
+            Ok(true)
+        } else {
+            Err("Note not found".to_string())
+        }
+    }
+
     /// Validates a Spider API key by making a lightweight test request
     async fn validate_spider_key(&self, api_key: &str) -> bool {
         let body = json!({
@@ -692,6 +2688,26 @@ impl TodoState {
         touched
     }
 
+    /// Bumps the revision of `note_id` and broadcasts `NoteUpdated`.
+    fn broadcast_note(&mut self, note_id: u64) -> Option<Note> {
+        let note = self.notes.iter_mut().find(|n| n.id == note_id)?;
+        note.revision += 1;
+        let note = note.clone();
+        self.publish_change(|seq| WsServerMessage::NoteUpdated {
+            note: note.clone(),
+            seq,
+        });
+        Some(note)
+    }
+
+    /// Pushes what connected boards derive from the entries: smart list membership and
+    /// over-limit columns. Smart lists only re-check entries already broadcast, so this
+    /// runs after the changes are published.
+    fn refresh_live_views(&mut self) {
+        self.refresh_smart_lists();
+        self.refresh_wip_status();
+    }
+
     fn broadcast(&self, message: &WsServerMessage) {
         if self.connected_channels.is_empty() {
             return;
@@ -714,10 +2730,29 @@ impl TodoState {
             &WsServerMessage::Snapshot {
                 entries: self.entries.clone(),
                 notes: self.notes.clone(),
+                projects: self.projects.clone(),
+                sprints: self.sprints.clone(),
+                workflows: self.workflows.clone(),
+                seq: self.change_seq,
             },
         );
     }
 
+    /// Adds `channel_id` to the broadcast, brings it up to date and tells it which WIP limits
+    /// are exceeded.
+    fn subscribe_channel(&mut self, channel_id: u32, since_seq: Option<u64>) {
+        self.connected_channels.insert(channel_id);
+        self.resume_channel(channel_id, since_seq);
+        if !self.wip_over_limit.is_empty() {
+            self.send_ws_message(
+                channel_id,
+                &WsServerMessage::WipStatus {
+                    over_limit: self.wip_over_limit.clone(),
+                },
+            );
+        }
+    }
+
     fn send_ws_message(&self, channel_id: u32, message: &WsServerMessage) {
         if let Ok(json) = serde_json::to_string(message) {
             let blob = LazyLoadBlob {
@@ -734,6 +2769,13 @@ impl TodoState {
         id
     }
 
+    fn next_smart_list_id(&mut self) -> u64 {
+        // State saved before smart lists existed deserializes this as 0
+        let id = self.next_smart_list_id.max(1);
+        self.next_smart_list_id = id + 1;
+        id
+    }
+
     fn next_note_id(&mut self) -> u64 {
         let id = self.next_note_id;
         self.next_note_id += 1;
@@ -741,23 +2783,24 @@ impl TodoState {
     }
 }
 
-fn refresh_entry_timescale(entry: &mut Entry) {
+fn refresh_entry_timescale(entry: &mut Entry, calendar: &Calendar) {
     entry.timescale = if entry.is_completed {
         EntryTimescale::Completed
+    } else if entry.due_ts.is_none() && entry.sprint_id.is_some_and(|id| calendar.in_sprint(id)) {
+        EntryTimescale::ThisSprint
     } else {
-        compute_timescale(entry.due_ts)
+        compute_timescale(entry.due_ts, calendar)
     };
 }
 
-fn compute_timescale(due_ts: Option<i64>) -> EntryTimescale {
+fn compute_timescale(due_ts: Option<i64>, calendar: &Calendar) -> EntryTimescale {
     let due_ts = match due_ts {
         Some(ts) => ts,
         None => return EntryTimescale::Someday,
     };
 
-    if let LocalResult::Single(due) = Local.timestamp_millis_opt(due_ts) {
-        let today = Local::now().date_naive();
-        let due_date = due.date_naive();
+    if let Some(due_date) = calendar.date_of(due_ts) {
+        let today = calendar.today();
 
         if due_date < today {
             return EntryTimescale::Overdue;
@@ -767,13 +2810,21 @@ fn compute_timescale(due_ts: Option<i64>) -> EntryTimescale {
             return EntryTimescale::Today;
         }
 
-        let weekday = today.weekday().number_from_monday() as i64;
-        let end_of_week = today + Duration::days(7 - weekday);
+        // Work due on a day off is effectively due on the next working day
+        let due_date = calendar.next_working_day(due_date);
+        let end_of_week = calendar.end_of_week(today);
 
         if due_date <= end_of_week {
             return EntryTimescale::ThisWeek;
         }
 
+        if calendar
+            .sprint_end()
+            .is_some_and(|sprint_end| due_date <= sprint_end)
+        {
+            return EntryTimescale::ThisSprint;
+        }
+
         let end_of_month = last_day_of_month(today.year(), today.month());
 
         if due_date <= end_of_month {
@@ -811,7 +2862,7 @@ fn summarize_text(text: &str) -> String {
 }
 
 fn now_ts() -> i64 {
-    Local::now().timestamp_millis()
+    Utc::now().timestamp_millis()
 }
 
 fn random_accent_for(tags: &[String]) -> String {
@@ -1049,3 +3100,40 @@ fn decode_spider_message_obj(value: &serde_json::Value) -> Result<SpiderMessage,
         timestamp,
     })
 }
+
+/// An open backlog entry with nothing but `id` and `title` set, for unit tests.
+#[cfg(test)]
+pub(crate) fn test_entry(id: u64, title: &str) -> Entry {
+    Entry {
+        id,
+        title: title.to_string(),
+        summary: String::new(),
+        description: String::new(),
+        project: None,
+        project_id: None,
+        sprint_id: None,
+        state: String::new(),
+        status: EntryStatus::Backlog,
+        rank: String::new(),
+        timescale: EntryTimescale::Someday,
+        priority: EntryPriority::Medium,
+        due_ts: None,
+        start_ts: None,
+        estimate_minutes: None,
+        dependencies: Vec::new(),
+        parent_id: None,
+        note_ids: Vec::new(),
+        assignees: Vec::new(),
+        tags: Vec::new(),
+        is_completed: false,
+        completed_at_ts: None,
+        progress: 0,
+        recurrence: None,
+        series_id: None,
+        occurrence: 0,
+        next_occurrence_id: None,
+        blocked_from: None,
+        archived_from: None,
+        revision: 0,
+    }
+}
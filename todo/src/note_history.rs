@@ -0,0 +1,245 @@
+use crate::{activity::request_source, DiffLine, DiffLineKind, NoteDiff, NoteVersion, TodoState};
+
+impl TodoState {
+    /// Gives every note saved before versions were kept a first version holding its current
+    /// text, so there is always something to diff against.
+    pub(crate) fn seed_note_versions(&mut self) {
+        for note in &self.notes {
+            if !self.note_versions.iter().any(|v| v.note_id == note.id) {
+                self.note_versions.push(NoteVersion {
+                    note_id: note.id,
+                    version: 1,
+                    title: note.title.clone(),
+                    content: note.content.clone(),
+                    edited_ts: note.last_edited_ts,
+                    editor: None,
+                    restored_from: None,
+                });
+            }
+        }
+    }
+
+    /// Appends a version for `note_id` if its title or text differs from the latest one.
+    /// The editor is the source of the current request, as recorded for activity events.
+    pub(crate) fn record_note_version(&mut self, note_id: u64) -> Option<u32> {
+        let note = self.notes.iter().find(|n| n.id == note_id)?;
+        let latest = self.note_versions.iter().rfind(|v| v.note_id == note_id);
+        if latest.is_some_and(|v| v.title == note.title && v.content == note.content) {
+            return None;
+        }
+        let version = latest.map_or(1, |v| v.version + 1);
+        self.note_versions.push(NoteVersion {
+            note_id,
+            version,
+            title: note.title.clone(),
+            content: note.content.clone(),
+            edited_ts: note.last_edited_ts,
+            editor: Some(request_source()),
+            restored_from: None,
+        });
+        Some(version)
+    }
+
+    pub(crate) fn note_version(&self, note_id: u64, version: u32) -> Result<&NoteVersion, String> {
+        self.note_versions
+            .iter()
+            .find(|v| v.note_id == note_id && v.version == version)
+            .ok_or_else(|| format!("Note {note_id} has no version {version}"))
+    }
+
+    pub(crate) fn note_version_diff(
+        &self,
+        note_id: u64,
+        from_version: u32,
+        to_version: u32,
+    ) -> Result<NoteDiff, String> {
+        let from = self.note_version(note_id, from_version)?;
+        let to = self.note_version(note_id, to_version)?;
+        Ok(NoteDiff {
+            note_id,
+            from_version,
+            to_version,
+            title_changed: from.title != to.title,
+            lines: diff_lines(&from.content, &to.content)?,
+        })
+    }
+
+    pub(crate) fn latest_note_version(&self, note_id: u64) -> Option<u32> {
+        self.note_versions
+            .iter()
+            .rfind(|v| v.note_id == note_id)
+            .map(|v| v.version)
+    }
+
+    /// Forgets the versions of notes that were purged for good.
+    pub(crate) fn drop_note_versions(&mut self, note_ids: &[u64]) {
+        self.note_versions
+            .retain(|v| !note_ids.contains(&v.note_id));
+    }
+}
+
+/// Edit distance past which two texts count as too different to diff. The trace kept for
+/// the walk back grows with its square.
+const MAX_DIFF_EDITS: isize = 1000;
+
+/// Line diff between `old` and `new` using Myers' shortest edit script, so unchanged runs
+/// line up the way a reader expects. Fails when more than `MAX_DIFF_EDITS` lines differ.
+pub(crate) fn diff_lines(old: &str, new: &str) -> Result<Vec<DiffLine>, String> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    // One spare diagonal on each side, so the trace can always keep -d-1..=d+1
+    let offset = n + m + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // For each d, the furthest x on diagonals -d-1..=d+1 before round d
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=n + m {
+        if d > MAX_DIFF_EDITS {
+            return Err(format!(
+                "The versions differ in more than {MAX_DIFF_EDITS} lines; too many to compare."
+            ));
+        }
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk the trace back from the end, emitting lines in reverse
+    let mut lines = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let furthest = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && furthest(k - 1) < furthest(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = furthest(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            lines.push(diff_line(
+                DiffLineKind::Same,
+                a[x as usize],
+                Some(x),
+                Some(y),
+            ));
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                lines.push(diff_line(DiffLineKind::Added, b[y as usize], None, Some(y)));
+            } else {
+                x -= 1;
+                lines.push(diff_line(
+                    DiffLineKind::Removed,
+                    a[x as usize],
+                    Some(x),
+                    None,
+                ));
+            }
+        }
+    }
+    lines.reverse();
+    Ok(lines)
+}
+
+fn diff_line(kind: DiffLineKind, text: &str, old: Option<isize>, new: Option<isize>) -> DiffLine {
+    DiffLine {
+        kind,
+        text: text.to_string(),
+        old_line: old.map(|i| i as u32 + 1),
+        new_line: new.map(|i| i as u32 + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(lines: &[DiffLine]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                let mark = match line.kind {
+                    DiffLineKind::Same => ' ',
+                    DiffLineKind::Added => '+',
+                    DiffLineKind::Removed => '-',
+                };
+                format!("{mark}{}", line.text)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_texts_are_all_same() {
+        let lines = diff_lines("a\nb\nc", "a\nb\nc").unwrap();
+        assert_eq!(render(&lines), vec![" a", " b", " c"]);
+        assert_eq!(lines[2].old_line, Some(3));
+        assert_eq!(lines[2].new_line, Some(3));
+    }
+
+    #[test]
+    fn empty_sides_are_all_added_or_removed() {
+        assert!(diff_lines("", "").unwrap().is_empty());
+        assert_eq!(render(&diff_lines("", "a\nb").unwrap()), vec!["+a", "+b"]);
+        assert_eq!(render(&diff_lines("a\nb", "").unwrap()), vec!["-a", "-b"]);
+    }
+
+    #[test]
+    fn edits_line_up_unchanged_runs() {
+        let lines = diff_lines("a\nb\nc\nd", "a\nx\nc\nd\ne").unwrap();
+        assert_eq!(render(&lines), vec![" a", "-b", "+x", " c", " d", "+e"]);
+        let removed = &lines[1];
+        assert_eq!((removed.old_line, removed.new_line), (Some(2), None));
+        let added = &lines[2];
+        assert_eq!((added.old_line, added.new_line), (None, Some(2)));
+        let last = &lines[5];
+        assert_eq!((last.old_line, last.new_line), (None, Some(5)));
+    }
+
+    #[test]
+    fn diff_is_minimal() {
+        let lines = diff_lines("a\nb\nc\na\nb\nb\na", "c\nb\na\nb\na\nc").unwrap();
+        let edits = lines
+            .iter()
+            .filter(|line| line.kind != DiffLineKind::Same)
+            .count();
+        assert_eq!(edits, 5);
+    }
+
+    #[test]
+    fn too_many_edits_are_refused() {
+        let old = (0..=MAX_DIFF_EDITS)
+            .map(|i| format!("old {i}\n"))
+            .collect::<String>();
+        let new = (0..=MAX_DIFF_EDITS)
+            .map(|i| format!("new {i}\n"))
+            .collect::<String>();
+        assert!(diff_lines(&old, &new).is_err());
+        let near = format!("{old}extra");
+        assert_eq!(
+            diff_lines(&old, &near).unwrap().len(),
+            MAX_DIFF_EDITS as usize + 2
+        );
+    }
+}
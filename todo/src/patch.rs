@@ -0,0 +1,159 @@
+use crate::{
+    Entry, EntryDraft, EntryPatch, EntryPatchField, EntrySaveError, Note, NoteDraft, NotePatch,
+    NoteSaveError, TodoState,
+};
+
+impl TodoState {
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn apply_entry_patch(
+        &mut self,
+        entry_id: u64,
+        patch: EntryPatch,
+    ) -> Result<Entry, EntrySaveError> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|e| e.id == entry_id)
+            .ok_or_else(|| "Entry not found".to_string())?;
+        let draft = patch.merge_into(entry);
+        self.save_entry_draft(draft)
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn apply_note_patch(
+        &mut self,
+        note_id: u64,
+        patch: NotePatch,
+    ) -> Result<Note, NoteSaveError> {
+        let note = self
+            .notes
+            .iter()
+            .find(|n| n.id == note_id)
+            .ok_or_else(|| "Note not found".to_string())?;
+        let draft = patch.merge_into(note);
+        self.save_note_draft(draft)
+    }
+}
+
+impl EntryPatch {
+    /// Builds the full draft `save_entry` expects from `entry` with this patch applied.
+    pub(crate) fn merge_into(self, entry: &Entry) -> EntryDraft {
+        let mut draft = EntryDraft {
+            id: Some(entry.id),
+            title: entry.title.clone(),
+            summary: entry.summary.clone(),
+            description: entry.description.clone(),
+            project: entry.project.clone(),
+            project_id: entry.project_id,
+            sprint_id: entry.sprint_id,
+            state: None,
+            status: entry.status.clone(),
+            priority: entry.priority.clone(),
+            due_ts: entry.due_ts,
+            start_ts: entry.start_ts,
+            estimate_minutes: entry.estimate_minutes,
+            dependencies: entry.dependencies.clone(),
+            parent_id: entry.parent_id,
+            note_ids: entry.note_ids.clone(),
+            assignees: entry.assignees.clone(),
+            tags: entry.tags.clone(),
+            recurrence: entry.recurrence.clone(),
+            series_scope: self.series_scope,
+            expected_revision: self.expected_revision,
+        };
+
+        for field in &self.clear {
+            match field {
+                EntryPatchField::Project => {
+                    draft.project = None;
+                    draft.project_id = None;
+                }
+                EntryPatchField::DueTs => draft.due_ts = None,
+                EntryPatchField::StartTs => draft.start_ts = None,
+                EntryPatchField::EstimateMinutes => draft.estimate_minutes = None,
+                EntryPatchField::ParentId => draft.parent_id = None,
+                EntryPatchField::Recurrence => draft.recurrence = None,
+                EntryPatchField::SprintId => draft.sprint_id = None,
+            }
+        }
+
+        if let Some(title) = self.title {
+            draft.title = title;
+        }
+        if let Some(summary) = self.summary {
+            draft.summary = summary;
+        }
+        if let Some(description) = self.description {
+            draft.description = description;
+        }
+        if let Some(status) = self.status {
+            draft.status = status;
+        }
+        if let Some(priority) = self.priority {
+            draft.priority = priority;
+        }
+        if self.project.is_some() || self.project_id.is_some() {
+            // A bare name is looked up again rather than kept under the old project id
+            draft.project_id = self.project_id;
+            draft.project = self.project.or(draft.project);
+        }
+        draft.due_ts = self.due_ts.or(draft.due_ts);
+        draft.start_ts = self.start_ts.or(draft.start_ts);
+        draft.estimate_minutes = self.estimate_minutes.or(draft.estimate_minutes);
+        draft.parent_id = self.parent_id.or(draft.parent_id);
+        draft.recurrence = self.recurrence.or(draft.recurrence);
+        draft.sprint_id = self.sprint_id.or(draft.sprint_id);
+        draft.state = self.state;
+
+        edit_list(
+            &mut draft.dependencies,
+            self.add_dependencies,
+            &self.remove_dependencies,
+        );
+        edit_list(
+            &mut draft.assignees,
+            self.add_assignees,
+            &self.remove_assignees,
+        );
+        edit_list(
+            &mut draft.note_ids,
+            self.add_note_ids,
+            &self.remove_note_ids,
+        );
+        edit_list(&mut draft.tags, self.add_tags, &self.remove_tags);
+        draft
+    }
+}
+
+impl NotePatch {
+    /// Builds the full draft `save_note` expects from `note` with this patch applied.
+    pub(crate) fn merge_into(self, note: &Note) -> NoteDraft {
+        let mut draft = NoteDraft {
+            id: Some(note.id),
+            title: self.title.unwrap_or_else(|| note.title.clone()),
+            content: self.content.unwrap_or_else(|| note.content.clone()),
+            pinned: self.pinned.unwrap_or(note.pinned),
+            tags: note.tags.clone(),
+            linked_entry_ids: note.linked_entry_ids.clone(),
+            accent: Some(self.accent.unwrap_or_else(|| note.accent.clone())),
+            expected_revision: self.expected_revision,
+        };
+        edit_list(&mut draft.tags, self.add_tags, &self.remove_tags);
+        edit_list(
+            &mut draft.linked_entry_ids,
+            self.add_linked_entry_ids,
+            &self.remove_linked_entry_ids,
+        );
+        draft
+    }
+}
+
+/// Drops `remove` from `items`, then appends each of `add` that is not already there.
+fn edit_list<T: PartialEq>(items: &mut Vec<T>, add: Vec<T>, remove: &[T]) {
+    items.retain(|item| !remove.contains(item));
+    for item in add {
+        if !items.contains(&item) {
+            items.push(item);
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use crate::{
+    workflows::{set_entry_state, state_status},
+    EntrySaveError, EntryStatus, Project, ProjectDraft, ProjectStatus, TodoState, TrashedRecord,
+    WorkflowCategory, WsServerMessage,
+};
+
+/// The project a saved entry goes into; a new one is created by `claim_project`.
+pub(crate) enum ProjectChoice {
+    Existing(Project),
+    New(String),
+}
+
+impl ProjectChoice {
+    pub(crate) fn workflow_id(&self) -> Option<u64> {
+        match self {
+            ProjectChoice::Existing(project) => project.workflow_id,
+            ProjectChoice::New(_) => None,
+        }
+    }
+}
+
+/// Colors handed out to projects created without one, in turn.
+const PROJECT_COLORS: [&str; 6] = [
+    "#e0f2fe", "#dcfce7", "#fef3c7", "#fee2e2", "#ede9fe", "#fce7f3",
+];
+
+impl TodoState {
+    fn next_project_id(&mut self) -> u64 {
+        // State saved before projects existed deserializes the counter as 0
+        let id = self.next_project_id.max(1);
+        self.next_project_id = id + 1;
+        id
+    }
+
+    /// Gives every entry that only carries a project name (state saved before projects had
+    /// records) a project record, creating one per distinct name.
+    pub(crate) fn migrate_projects(&mut self) {
+        let names: Vec<String> = self
+            .entries
+            .iter()
+            .chain(self.trash.iter().filter_map(|item| match &item.record {
+                TrashedRecord::Entry(entry) => Some(entry),
+                TrashedRecord::Note(_) => None,
+            }))
+            .filter(|entry| entry.project_id.is_none())
+            .filter_map(|entry| entry.project.clone())
+            .collect();
+        for name in names {
+            let name = name.trim();
+            if !name.is_empty() && find_by_name(&self.projects, name).is_none() {
+                let project = self.new_project(name);
+                self.projects.push(project);
+            }
+        }
+
+        let projects = &self.projects;
+        let trashed = self
+            .trash
+            .iter_mut()
+            .filter_map(|item| match &mut item.record {
+                TrashedRecord::Entry(entry) => Some(entry),
+                TrashedRecord::Note(_) => None,
+            });
+        for entry in self.entries.iter_mut().chain(trashed) {
+            if entry.project_id.is_some() {
+                continue;
+            }
+            let project = entry
+                .project
+                .as_deref()
+                .and_then(|name| find_by_name(projects, name));
+            entry.project_id = project.map(|p| p.id);
+            entry.project = project.map(|p| p.name.clone());
+        }
+    }
+
+    /// Works out the project an entry is saved into. `project_id` wins over `name`; a name
+    /// that matches no project is only created by `claim_project`, once the rest of the
+    /// entry has been validated. Entries cannot move into an archived project.
+    pub(crate) fn resolve_entry_project(
+        &self,
+        entry_id: Option<u64>,
+        project_id: Option<u64>,
+        name: Option<&str>,
+    ) -> Result<Option<ProjectChoice>, String> {
+        let current = match entry_id {
+            Some(id) => {
+                self.entries
+                    .iter()
+                    .find(|e| e.id == id)
+                    .ok_or_else(|| "Entry not found".to_string())?
+                    .project_id
+            }
+            None => None,
+        };
+        let name = name.map(str::trim).filter(|name| !name.is_empty());
+        let project = match (project_id, name) {
+            (Some(id), _) => self
+                .projects
+                .iter()
+                .find(|p| p.id == id)
+                .cloned()
+                .ok_or_else(|| format!("Project {id} not found"))?,
+            (None, Some(name)) => match find_by_name(&self.projects, name) {
+                Some(project) => project.clone(),
+                None => return Ok(Some(ProjectChoice::New(name.to_string()))),
+            },
+            (None, None) => return Ok(None),
+        };
+        if project.archived && current != Some(project.id) {
+            return Err(format!("Project '{}' is archived.", project.name));
+        }
+        Ok(Some(ProjectChoice::Existing(project)))
+    }
+
+    pub(crate) fn claim_project(&mut self, choice: ProjectChoice) -> Project {
+        match choice {
+            ProjectChoice::Existing(project) => project,
+            ProjectChoice::New(name) => {
+                let project = self.new_project(&name);
+                self.projects.push(project.clone());
+                self.broadcast_project(&project);
+                project
+            }
+        }
+    }
+
+    pub(crate) fn save_project_draft(&mut self, draft: ProjectDraft) -> Result<Project, String> {
+        let name = draft.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Projects require a name.".to_string());
+        }
+        if let Some(existing) = find_by_name(&self.projects, &name) {
+            if Some(existing.id) != draft.id {
+                return Err(format!(
+                    "A project named '{}' already exists; merge into it instead.",
+                    existing.name
+                ));
+            }
+        }
+
+        if let Some(workflow_id) = draft.workflow_id {
+            if !self.workflows.iter().any(|w| w.id == workflow_id) {
+                return Err("Workflow not found".to_string());
+            }
+        }
+
+        let (project, renamed, workflow_changed) = if let Some(id) = draft.id {
+            let project = self
+                .projects
+                .iter_mut()
+                .find(|p| p.id == id)
+                .ok_or_else(|| "Project not found".to_string())?;
+            let renamed = project.name != name;
+            let workflow_changed = project.workflow_id != draft.workflow_id;
+            project.name = name;
+            project.description = draft.description;
+            if let Some(color) = draft.color {
+                project.color = color;
+            }
+            project.owner = draft.owner;
+            project.status = draft.status;
+            project.target_ts = draft.target_ts;
+            project.workflow_id = draft.workflow_id;
+            (project.clone(), renamed, workflow_changed)
+        } else {
+            let mut project = self.new_project(&name);
+            project.description = draft.description;
+            if let Some(color) = draft.color {
+                project.color = color;
+            }
+            project.owner = draft.owner;
+            project.status = draft.status;
+            project.target_ts = draft.target_ts;
+            project.workflow_id = draft.workflow_id;
+            self.projects.push(project.clone());
+            (project, false, false)
+        };
+
+        self.broadcast_project(&project);
+        if renamed {
+            self.move_project_members(project.id, Some(&project));
+        }
+        if workflow_changed {
+            let member_ids: Vec<u64> = self
+                .entries
+                .iter()
+                .filter(|e| e.project_id == Some(project.id))
+                .map(|e| e.id)
+                .collect();
+            self.sync_entry_states(&member_ids);
+        }
+        Ok(project)
+    }
+
+    /// Archiving moves the project's open entries to its workflow's archived state, when it
+    /// has one, remembering the state each came from; unarchiving puts those entries back.
+    /// Every move must be an allowed transition within the WIP limits, or nothing changes.
+    pub(crate) fn set_project_archived(
+        &mut self,
+        project_id: u64,
+        archived: bool,
+    ) -> Result<Project, String> {
+        if !self.projects.iter().any(|p| p.id == project_id) {
+            return Err("Project not found".to_string());
+        }
+        let checkpoint = self.checkpoint();
+        self.begin_batch();
+        match self.shelve_project(project_id, archived) {
+            Ok(project) => {
+                self.commit_batch();
+                Ok(project)
+            }
+            Err(err) => {
+                self.roll_back(checkpoint);
+                Err(err)
+            }
+        }
+    }
+
+    fn shelve_project(&mut self, project_id: u64, archived: bool) -> Result<Project, String> {
+        let project = self
+            .projects
+            .iter_mut()
+            .find(|p| p.id == project_id)
+            .ok_or_else(|| "Project not found".to_string())?;
+        project.archived = archived;
+        let project = project.clone();
+        self.broadcast_project(&project);
+
+        let workflow = self.entry_workflow(Some(project_id)).clone();
+        let archived_state = workflow.state_for_status(&EntryStatus::Archived);
+        let moves: Vec<(u64, Option<&str>, EntryStatus)> = self
+            .entries
+            .iter()
+            .filter(|e| e.project_id == Some(project_id))
+            .filter_map(|entry| {
+                if archived {
+                    let target = archived_state?;
+                    (!entry.is_completed && entry.status != EntryStatus::Archived)
+                        .then(|| (entry.id, Some(target.key.as_str()), state_status(target)))
+                } else {
+                    // A state the workflow has since dropped falls back to the backlog
+                    let previous = entry.archived_from.as_deref()?;
+                    let key = workflow.state(previous).map(|s| s.key.as_str());
+                    Some((entry.id, key, EntryStatus::Backlog))
+                }
+            })
+            .collect();
+
+        for (entry_id, key, status) in moves {
+            let target =
+                self.resolve_entry_state(Some(entry_id), Some(workflow.id), key, &status)?;
+            let Some(entry) = self.entries.iter().find(|e| e.id == entry_id) else {
+                continue;
+            };
+            self.check_wip_limits(
+                Some(entry_id),
+                &state_status(&target),
+                &entry.assignees,
+                target.category == WorkflowCategory::Done,
+            )
+            .map_err(EntrySaveError::into_message)?;
+
+            let Some(entry) = self.entries.iter_mut().find(|e| e.id == entry_id) else {
+                continue;
+            };
+            let previous = entry.state.clone();
+            set_entry_state(entry, &target);
+            if archived {
+                entry.archived_from = Some(previous);
+            }
+            self.refresh_blocked_status(entry_id);
+            self.refresh_progress_upwards(entry_id);
+            self.index_entry(entry_id);
+            self.broadcast_entry(entry_id);
+        }
+        Ok(project)
+    }
+
+    pub(crate) fn merge_project_into(
+        &mut self,
+        source_id: u64,
+        target_id: u64,
+    ) -> Result<Project, String> {
+        if source_id == target_id {
+            return Err("A project cannot be merged into itself.".to_string());
+        }
+        if !self.projects.iter().any(|p| p.id == source_id) {
+            return Err("Project not found".to_string());
+        }
+        let target = self
+            .projects
+            .iter()
+            .find(|p| p.id == target_id)
+            .cloned()
+            .ok_or_else(|| "Project not found".to_string())?;
+        if target.archived {
+            return Err(format!("Project '{}' is archived.", target.name));
+        }
+
+        self.move_project_members(source_id, Some(&target));
+        self.projects.retain(|p| p.id != source_id);
+        self.publish_change(|seq| WsServerMessage::ProjectRemoved {
+            project_id: source_id,
+            seq,
+        });
+        Ok(target)
+    }
+
+    pub(crate) fn remove_project(&mut self, project_id: u64) -> Result<(), String> {
+        if !self.projects.iter().any(|p| p.id == project_id) {
+            return Err("Project not found".to_string());
+        }
+        self.move_project_members(project_id, None);
+        self.projects.retain(|p| p.id != project_id);
+        self.publish_change(|seq| WsServerMessage::ProjectRemoved { project_id, seq });
+        Ok(())
+    }
+
+    /// Points every entry of `project_id`, trashed ones included, at `target` (or at no
+    /// project) and broadcasts the live ones.
+    fn move_project_members(&mut self, project_id: u64, target: Option<&Project>) {
+        let trashed = self
+            .trash
+            .iter_mut()
+            .filter_map(|item| match &mut item.record {
+                TrashedRecord::Entry(entry) => Some(entry),
+                TrashedRecord::Note(_) => None,
+            });
+        for entry in trashed.filter(|e| e.project_id == Some(project_id)) {
+            entry.project_id = target.map(|p| p.id);
+            entry.project = target.map(|p| p.name.clone());
+        }
+
+        let mut touched = Vec::new();
+        for entry in &mut self.entries {
+            if entry.project_id == Some(project_id) {
+                entry.project_id = target.map(|p| p.id);
+                entry.project = target.map(|p| p.name.clone());
+                touched.push(entry.id);
+            }
+        }
+        for entry_id in touched {
+            self.sync_entry_state(entry_id);
+            self.index_entry(entry_id);
+            self.broadcast_entry(entry_id);
+        }
+        self.refresh_live_views();
+    }
+
+    fn new_project(&mut self, name: &str) -> Project {
+        let id = self.next_project_id();
+        Project {
+            id,
+            name: name.to_string(),
+            description: String::new(),
+            color: PROJECT_COLORS[(id as usize - 1) % PROJECT_COLORS.len()].to_string(),
+            owner: None,
+            status: ProjectStatus::default(),
+            target_ts: None,
+            archived: false,
+            workflow_id: None,
+        }
+    }
+
+    fn broadcast_project(&mut self, project: &Project) {
+        self.publish_change(|seq| WsServerMessage::ProjectUpdated {
+            project: project.clone(),
+            seq,
+        });
+    }
+}
+
+fn find_by_name<'a>(projects: &'a [Project], name: &str) -> Option<&'a Project> {
+    let name = name.trim().to_lowercase();
+    projects.iter().find(|p| p.name.to_lowercase() == name)
+}
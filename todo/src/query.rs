@@ -0,0 +1,734 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, NaiveDate};
+
+use crate::{
+    clock::Calendar, search_index::DocKey, Entry, EntryPriority, EntryStatus, EntryTimescale, Note,
+    SearchAllResult, TodoState,
+};
+
+/// A parsed `search_all` query.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Query {
+    /// The empty query and `*` match everything.
+    All,
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Term {
+    /// A bare word or quoted phrase, looked up in the search index.
+    Text(String),
+    Status(EntryStatus),
+    Priority(EntryPriority),
+    Project(String),
+    Tag(String),
+    Assignee(String),
+    /// Compares the due date, as a local calendar day, against a date.
+    Due(Comparison, NaiveDate),
+    NoDueDate,
+    Is(Flag),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flag {
+    Done,
+    Open,
+    Archived,
+    Overdue,
+    Recurring,
+    Pinned,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Word(String),
+    Phrase(String),
+    Field {
+        name: String,
+        comparison: Comparison,
+        value: String,
+    },
+}
+
+/// Parses a query such as `status:blocked (tag:focus OR priority:high) -done "exact phrase"`.
+///
+/// Adjacent terms are ANDed; `AND`, `OR` and `NOT` must be upper case so the lower case words
+/// stay searchable. `-term` negates and parentheses group. The bare words `done`, `open`,
+/// `archived` and `pinned` are shorthands for the matching `is:` flags; quote them to search
+/// for the text instead. Relative dates such as `due<today` resolve against `calendar`.
+pub(crate) fn parse_query(input: &str, calendar: &Calendar) -> Result<Query, String> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() || trimmed == "*" {
+        return Ok(Query::All);
+    }
+    let tokens = tokenize(trimmed)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        calendar,
+    };
+    let query = parser.parse_or()?;
+    // parse_or only stops early at a ')' it has no group for
+    if parser.peek().is_some() {
+        return Err(format!("Unexpected ')' at column {}", parser.column()));
+    }
+    Ok(query)
+}
+
+impl TodoState {
+    /// Evaluates `query` against every entry and note, ranking by the query's free text.
+    pub(crate) fn run_query(&self, query: &Query, calendar: &Calendar) -> SearchAllResult {
+        let include_archived = query.mentions_archived();
+        let text_hits: Vec<(&str, HashSet<DocKey>)> = query
+            .text_terms(true)
+            .into_iter()
+            .map(|text| (text, self.text_matches(text)))
+            .collect();
+        let text_matches = |key: DocKey, text: &str| {
+            text_hits
+                .iter()
+                .any(|(hit_text, keys)| *hit_text == text && keys.contains(&key))
+        };
+
+        let mut matching_entries: Vec<Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| include_archived || entry.status != EntryStatus::Archived)
+            .filter(|entry| {
+                query.matches_entry(entry, calendar, &|text| {
+                    text_matches(DocKey::Entry(entry.id), text)
+                })
+            })
+            .cloned()
+            .collect();
+
+        let mut matching_notes: Vec<Note> = self
+            .notes
+            .iter()
+            .filter(|note| {
+                query.matches_note(note, &|text| text_matches(DocKey::Note(note.id), text))
+            })
+            .cloned()
+            .collect();
+
+        let hits = self.rank_results(
+            &mut matching_entries,
+            &mut matching_notes,
+            &query.text_terms(false),
+        );
+        SearchAllResult {
+            entries: matching_entries,
+            notes: matching_notes,
+            hits,
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        let column = idx + 1;
+        match chars[idx] {
+            c if c.is_whitespace() => idx += 1,
+            '(' => {
+                tokens.push((Token::LParen, column));
+                idx += 1;
+            }
+            ')' => {
+                tokens.push((Token::RParen, column));
+                idx += 1;
+            }
+            '"' => {
+                let (phrase, next) = read_quoted(&chars, idx)?;
+                tokens.push((Token::Phrase(phrase), column));
+                idx = next;
+            }
+            '-' if chars
+                .get(idx + 1)
+                .is_some_and(|c| !c.is_whitespace() && *c != ')') =>
+            {
+                tokens.push((Token::Not, column));
+                idx += 1;
+            }
+            _ => {
+                let (token, next) = read_word(&chars, idx)?;
+                tokens.push((token, column));
+                idx = next;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads a double-quoted string starting at `start`, returning it and the index after it.
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), String> {
+    let end = chars[start + 1..]
+        .iter()
+        .position(|c| *c == '"')
+        .map(|offset| start + 1 + offset)
+        .ok_or_else(|| format!("Unclosed quote starting at column {}", start + 1))?;
+    Ok((chars[start + 1..end].iter().collect(), end + 1))
+}
+
+fn read_word(chars: &[char], start: usize) -> Result<(Token, usize), String> {
+    let ends_word = |c: &char| c.is_whitespace() || matches!(c, '(' | ')' | '"');
+
+    let name_end = chars[start..]
+        .iter()
+        .position(|c| !c.is_ascii_alphabetic())
+        .map_or(chars.len(), |offset| start + offset);
+    let operator = match (chars.get(name_end), chars.get(name_end + 1)) {
+        _ if name_end == start => None,
+        (Some(':'), _) => Some((Comparison::Equal, 1)),
+        (Some('<'), Some('=')) => Some((Comparison::LessOrEqual, 2)),
+        (Some('>'), Some('=')) => Some((Comparison::GreaterOrEqual, 2)),
+        (Some('<'), _) => Some((Comparison::Less, 1)),
+        (Some('>'), _) => Some((Comparison::Greater, 1)),
+        (Some('='), _) => Some((Comparison::Equal, 1)),
+        _ => None,
+    };
+
+    if let Some((comparison, width)) = operator {
+        let name: String = chars[start..name_end].iter().collect();
+        let value_start = name_end + width;
+        let (value, next) = if chars.get(value_start) == Some(&'"') {
+            read_quoted(chars, value_start)?
+        } else {
+            let end = chars[value_start..]
+                .iter()
+                .position(ends_word)
+                .map_or(chars.len(), |offset| value_start + offset);
+            (chars[value_start..end].iter().collect(), end)
+        };
+        let token = Token::Field {
+            name: name.to_ascii_lowercase(),
+            comparison,
+            value,
+        };
+        return Ok((token, next));
+    }
+
+    let end = chars[start..]
+        .iter()
+        .position(ends_word)
+        .map_or(chars.len(), |offset| start + offset);
+    let word: String = chars[start..end].iter().collect();
+    let token = match word.as_str() {
+        "AND" => Token::And,
+        "OR" => Token::Or,
+        "NOT" => Token::Not,
+        _ => Token::Word(word),
+    };
+    Ok((token, end))
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    calendar: &'a Calendar,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map(|(_, column)| *column)
+            .unwrap_or_default()
+    }
+
+    fn parse_or(&mut self) -> Result<Query, String> {
+        let mut branches = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            branches.push(self.parse_and()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.remove(0)
+        } else {
+            Query::Or(branches)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Query, String> {
+        let mut clauses = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::RParen) => break,
+                Some(Token::And) => self.position += 1,
+                _ => {}
+            }
+            clauses.push(self.parse_unary()?);
+        }
+        Ok(if clauses.len() == 1 {
+            clauses.remove(0)
+        } else {
+            Query::And(clauses)
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Query, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+            return Ok(Query::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, String> {
+        let column = self.column();
+        let Some((token, _)) = self.tokens.get(self.position).cloned() else {
+            return Err("Query ends where a search term was expected".to_string());
+        };
+        self.position += 1;
+        match token {
+            Token::LParen => {
+                let inner = self.parse_or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(format!("Missing ')' for the '(' at column {column}"));
+                }
+                self.position += 1;
+                Ok(inner)
+            }
+            Token::RParen => Err(format!("Unexpected ')' at column {column}")),
+            Token::And => Err(format!(
+                "Expected a search term before AND at column {column}"
+            )),
+            Token::Or => Err(format!(
+                "Expected a search term before OR at column {column}"
+            )),
+            Token::Not => unreachable!("NOT is consumed by parse_unary"),
+            Token::Word(word) => Ok(Query::Term(match word.to_lowercase().as_str() {
+                "done" => Term::Is(Flag::Done),
+                "open" => Term::Is(Flag::Open),
+                "archived" => Term::Is(Flag::Archived),
+                "pinned" => Term::Is(Flag::Pinned),
+                lower => Term::Text(lower.to_string()),
+            })),
+            Token::Phrase(phrase) => Ok(Query::Term(Term::Text(phrase.to_lowercase()))),
+            Token::Field {
+                name,
+                comparison,
+                value,
+            } => self
+                .field_term(&name, comparison, &value)
+                .map(Query::Term)
+                .map_err(|err| format!("{err} (column {column})")),
+        }
+    }
+
+    fn field_term(&self, name: &str, comparison: Comparison, value: &str) -> Result<Term, String> {
+        if value.trim().is_empty() {
+            return Err(format!("Missing value for '{name}'"));
+        }
+        if name == "due" {
+            return self.due_term(comparison, value);
+        }
+        if comparison != Comparison::Equal {
+            return Err(format!(
+                "Only 'due' supports comparisons; use '{name}:{value}'"
+            ));
+        }
+
+        let normalized = value.trim().to_lowercase();
+        Ok(match name {
+            "status" => Term::Status(parse_status(&normalized)?),
+            "priority" => Term::Priority(match normalized.as_str() {
+                "low" => EntryPriority::Low,
+                "medium" => EntryPriority::Medium,
+                "high" => EntryPriority::High,
+                _ => {
+                    return Err(format!(
+                        "Unknown priority '{value}'; expected low, medium or high"
+                    ))
+                }
+            }),
+            "project" => Term::Project(normalized),
+            "tag" => Term::Tag(normalized),
+            "assignee" => Term::Assignee(normalized),
+            "is" => Term::Is(match normalized.as_str() {
+                "done" => Flag::Done,
+                "open" => Flag::Open,
+                "archived" => Flag::Archived,
+                "overdue" => Flag::Overdue,
+                "recurring" => Flag::Recurring,
+                "pinned" => Flag::Pinned,
+                _ => {
+                    return Err(format!(
+                        "Unknown flag 'is:{value}'; expected done, open, archived, overdue, recurring or pinned"
+                    ))
+                }
+            }),
+            _ => {
+                return Err(format!(
+                    "Unknown field '{name}'; quote the term to search for it as text"
+                ))
+            }
+        })
+    }
+
+    fn due_term(&self, comparison: Comparison, value: &str) -> Result<Term, String> {
+        let today = self.calendar.today();
+        let date = match value.trim().to_lowercase().as_str() {
+            "none" if comparison == Comparison::Equal => return Ok(Term::NoDueDate),
+            "today" => today,
+            "tomorrow" => today + Duration::days(1),
+            "yesterday" => today - Duration::days(1),
+            other => NaiveDate::parse_from_str(other, "%Y-%m-%d").map_err(|_| {
+                format!(
+                    "Invalid due date '{value}'; expected YYYY-MM-DD, today, tomorrow or yesterday"
+                )
+            })?,
+        };
+        Ok(Term::Due(comparison, date))
+    }
+}
+
+fn parse_status(value: &str) -> Result<EntryStatus, String> {
+    let compact: String = value
+        .chars()
+        .filter(|c| !matches!(c, '-' | '_' | ' '))
+        .collect();
+    Ok(match compact.as_str() {
+        "backlog" => EntryStatus::Backlog,
+        "upnext" => EntryStatus::UpNext,
+        "inprogress" => EntryStatus::InProgress,
+        "blocked" => EntryStatus::Blocked,
+        "review" => EntryStatus::Review,
+        "done" => EntryStatus::Done,
+        "archived" => EntryStatus::Archived,
+        _ => {
+            return Err(format!(
+                "Unknown status '{value}'; expected backlog, up-next, in-progress, blocked, review, done or archived"
+            ))
+        }
+    })
+}
+
+impl Query {
+    fn eval(&self, term_matches: &dyn Fn(&Term) -> bool) -> bool {
+        match self {
+            Query::All => true,
+            Query::And(clauses) => clauses.iter().all(|clause| clause.eval(term_matches)),
+            Query::Or(branches) => branches.iter().any(|branch| branch.eval(term_matches)),
+            Query::Not(inner) => !inner.eval(term_matches),
+            Query::Term(term) => term_matches(term),
+        }
+    }
+
+    /// Free-text terms in the query; negated ones are left out unless `include_negated`.
+    pub(crate) fn text_terms(&self, include_negated: bool) -> Vec<&str> {
+        match self {
+            Query::All => Vec::new(),
+            Query::And(items) | Query::Or(items) => items
+                .iter()
+                .flat_map(|item| item.text_terms(include_negated))
+                .collect(),
+            Query::Not(inner) if include_negated => inner.text_terms(include_negated),
+            Query::Not(_) => Vec::new(),
+            Query::Term(Term::Text(text)) => vec![text.as_str()],
+            Query::Term(_) => Vec::new(),
+        }
+    }
+
+    /// Whether any term asks for archived entries, which are hidden otherwise.
+    pub(crate) fn mentions_archived(&self) -> bool {
+        match self {
+            Query::All => false,
+            Query::And(items) | Query::Or(items) => items.iter().any(Query::mentions_archived),
+            Query::Not(inner) => inner.mentions_archived(),
+            Query::Term(term) => matches!(
+                term,
+                Term::Status(EntryStatus::Archived) | Term::Is(Flag::Archived)
+            ),
+        }
+    }
+
+    pub(crate) fn matches_entry(
+        &self,
+        entry: &Entry,
+        calendar: &Calendar,
+        text_matches: &dyn Fn(&str) -> bool,
+    ) -> bool {
+        self.eval(&|term| match term {
+            Term::Text(text) => text_matches(text),
+            Term::Status(status) => entry.status == *status,
+            Term::Priority(priority) => entry.priority == *priority,
+            Term::Project(project) => entry
+                .project
+                .as_deref()
+                .is_some_and(|p| p.to_lowercase() == *project),
+            Term::Tag(tag) => entry.tags.iter().any(|t| t.to_lowercase() == *tag),
+            Term::Assignee(assignee) => entry
+                .assignees
+                .iter()
+                .any(|a| a.to_lowercase() == *assignee),
+            Term::Due(comparison, date) => entry
+                .due_ts
+                .and_then(|due| calendar.date_of(due))
+                .is_some_and(|due| match comparison {
+                    Comparison::Less => due < *date,
+                    Comparison::LessOrEqual => due <= *date,
+                    Comparison::Equal => due == *date,
+                    Comparison::GreaterOrEqual => due >= *date,
+                    Comparison::Greater => due > *date,
+                }),
+            Term::NoDueDate => entry.due_ts.is_none(),
+            Term::Is(flag) => match flag {
+                Flag::Done => entry.is_completed,
+                Flag::Open => !entry.is_completed && entry.status != EntryStatus::Archived,
+                Flag::Archived => entry.status == EntryStatus::Archived,
+                Flag::Overdue => entry.timescale == EntryTimescale::Overdue,
+                Flag::Recurring => entry.recurrence.is_some(),
+                Flag::Pinned => false,
+            },
+        })
+    }
+
+    /// Entry-only fields never match a note, so `status:blocked` filters notes out entirely.
+    pub(crate) fn matches_note(&self, note: &Note, text_matches: &dyn Fn(&str) -> bool) -> bool {
+        self.eval(&|term| match term {
+            Term::Text(text) => text_matches(text),
+            Term::Tag(tag) => note.tags.iter().any(|t| t.to_lowercase() == *tag),
+            Term::Is(Flag::Pinned) => note.pinned,
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{test_entry, UserSettings};
+
+    fn utc(y: i32, m: u32, d: u32, h: u32) -> i64 {
+        Utc.with_ymd_and_hms(y, m, d, h, 0, 0)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    /// Noon on Saturday 2026-10-17 in UTC.
+    fn calendar() -> Calendar {
+        Calendar::new(&UserSettings::default(), utc(2026, 10, 17, 12))
+    }
+
+    fn parse(input: &str) -> Query {
+        parse_query(input, &calendar()).unwrap()
+    }
+
+    fn text(word: &str) -> Query {
+        Query::Term(Term::Text(word.to_string()))
+    }
+
+    /// Whether `input` matches `entry`, with free text matched against the title's words.
+    fn matches(input: &str, entry: &Entry) -> bool {
+        let title = entry.title.to_lowercase();
+        parse(input).matches_entry(entry, &calendar(), &|text| {
+            title.split_whitespace().any(|word| word == text) || title.contains(text)
+        })
+    }
+
+    #[test]
+    fn adjacent_terms_and_and_bind_tighter_than_or() {
+        assert_eq!(parse(""), Query::All);
+        assert_eq!(parse(" * "), Query::All);
+        assert_eq!(
+            parse("milk eggs"),
+            Query::And(vec![text("milk"), text("eggs")])
+        );
+        assert_eq!(parse("milk AND eggs"), parse("milk eggs"));
+        assert_eq!(
+            parse("milk eggs OR bread"),
+            Query::Or(vec![
+                Query::And(vec![text("milk"), text("eggs")]),
+                text("bread"),
+            ])
+        );
+        // Lower case operators stay searchable words
+        assert_eq!(
+            parse("salt or pepper"),
+            Query::And(vec![text("salt"), text("or"), text("pepper")])
+        );
+    }
+
+    #[test]
+    fn parentheses_group_and_not_negates() {
+        assert_eq!(
+            parse("milk (eggs OR bread)"),
+            Query::And(vec![
+                text("milk"),
+                Query::Or(vec![text("eggs"), text("bread")]),
+            ])
+        );
+        assert_eq!(
+            parse("NOT (eggs OR bread)"),
+            Query::Not(Box::new(Query::Or(vec![text("eggs"), text("bread")])))
+        );
+        assert_eq!(
+            parse("-done"),
+            Query::Not(Box::new(Query::Term(Term::Is(Flag::Done))))
+        );
+        assert_eq!(parse("-\"done\""), Query::Not(Box::new(text("done"))));
+        // A dash inside a word is just part of it
+        assert_eq!(parse("follow-up"), text("follow-up"));
+    }
+
+    #[test]
+    fn quoted_phrases_and_fields() {
+        assert_eq!(parse("\"Buy Milk\""), text("buy milk"));
+        assert_eq!(
+            parse("status:in-progress priority:HIGH project:\"Home Office\""),
+            Query::And(vec![
+                Query::Term(Term::Status(EntryStatus::InProgress)),
+                Query::Term(Term::Priority(EntryPriority::High)),
+                Query::Term(Term::Project("home office".to_string())),
+            ])
+        );
+        assert_eq!(
+            parse("is:recurring"),
+            Query::Term(Term::Is(Flag::Recurring))
+        );
+    }
+
+    #[test]
+    fn due_comparisons_resolve_relative_dates() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 17).unwrap();
+        assert_eq!(
+            parse("due<today"),
+            Query::Term(Term::Due(Comparison::Less, today))
+        );
+        assert_eq!(
+            parse("due>=tomorrow"),
+            Query::Term(Term::Due(
+                Comparison::GreaterOrEqual,
+                today + Duration::days(1)
+            ))
+        );
+        assert_eq!(
+            parse("due<=2026-12-31"),
+            Query::Term(Term::Due(
+                Comparison::LessOrEqual,
+                NaiveDate::from_ymd_opt(2026, 12, 31).unwrap()
+            ))
+        );
+        assert_eq!(parse("due:none"), Query::Term(Term::NoDueDate));
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_problem() {
+        let calendar = calendar();
+        let error = |input: &str| parse_query(input, &calendar).unwrap_err();
+        assert_eq!(error("(milk"), "Missing ')' for the '(' at column 1");
+        assert_eq!(error("milk)"), "Unexpected ')' at column 5");
+        assert_eq!(error("\"milk"), "Unclosed quote starting at column 1");
+        assert_eq!(
+            error("milk OR"),
+            "Query ends where a search term was expected"
+        );
+        assert_eq!(
+            error("OR milk"),
+            "Expected a search term before OR at column 1"
+        );
+        assert!(error("colour:red").starts_with("Unknown field 'colour'"));
+        assert!(error("status:nope").starts_with("Unknown status 'nope'"));
+        assert!(error("priority>high").starts_with("Only 'due' supports comparisons"));
+        assert!(error("due<someday").starts_with("Invalid due date 'someday'"));
+        assert!(error("due>none").starts_with("Invalid due date 'none'"));
+        assert!(error("tag:").starts_with("Missing value for 'tag'"));
+    }
+
+    #[test]
+    fn evaluates_boolean_structure_against_entries() {
+        let mut milk = test_entry(1, "Buy milk");
+        milk.tags = vec!["Errand".to_string()];
+        milk.priority = EntryPriority::High;
+        let mut report = test_entry(2, "Write report");
+        report.is_completed = true;
+        report.status = EntryStatus::Done;
+
+        assert!(matches("milk", &milk));
+        assert!(!matches("milk", &report));
+        assert!(matches("milk OR report", &report));
+        assert!(!matches("milk report", &report));
+        assert!(matches("tag:errand priority:high", &milk));
+        assert!(!matches("tag:errand -priority:high", &milk));
+        assert!(matches("-done", &milk));
+        assert!(!matches("-done", &report));
+        assert!(matches("done (milk OR report)", &report));
+        assert!(matches("NOT (milk OR tag:errand)", &report));
+        assert!(matches("\"buy milk\"", &milk));
+        assert!(matches("*", &report));
+    }
+
+    #[test]
+    fn due_terms_compare_local_days() {
+        let mut overdue = test_entry(1, "Overdue");
+        overdue.due_ts = Some(utc(2026, 10, 16, 23));
+        let mut today = test_entry(2, "Today");
+        today.due_ts = Some(utc(2026, 10, 17, 0));
+        let undated = test_entry(3, "Undated");
+
+        assert!(matches("due<today", &overdue));
+        assert!(!matches("due<today", &today));
+        assert!(matches("due:today", &today));
+        assert!(matches("due<=2026-10-17", &today));
+        assert!(!matches("due>today", &today));
+        assert!(!matches("due<today", &undated));
+        assert!(matches("due:none", &undated));
+        assert!(!matches("due:none", &overdue));
+    }
+
+    #[test]
+    fn entry_fields_never_match_notes() {
+        let note = Note {
+            id: 1,
+            title: "Milk".to_string(),
+            content: String::new(),
+            pinned: true,
+            tags: vec!["errand".to_string()],
+            linked_entry_ids: Vec::new(),
+            summary: String::new(),
+            accent: String::new(),
+            last_edited_ts: 0,
+            revision: 0,
+        };
+        let matches_note = |input: &str| parse(input).matches_note(&note, &|text| text == "milk");
+        assert!(matches_note("milk tag:errand pinned"));
+        assert!(!matches_note("milk status:backlog"));
+        assert!(matches_note("milk -status:backlog"));
+    }
+
+    #[test]
+    fn archived_entries_only_when_asked_for() {
+        assert!(!parse("milk -done").mentions_archived());
+        assert!(parse("milk OR status:archived").mentions_archived());
+        assert!(parse("-is:archived").mentions_archived());
+        assert_eq!(parse("milk -bread").text_terms(false), vec!["milk"]);
+        assert_eq!(parse("milk -bread").text_terms(true), vec!["milk", "bread"]);
+    }
+}
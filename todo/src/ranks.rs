@@ -0,0 +1,423 @@
+use crate::{Entry, EntryRank, EntryStatus, SmartList, TodoState, TrashedRecord, WsServerMessage};
+
+const RANK_DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+const RANK_BASE: u64 = 36;
+/// Appended ranks step at this width, leaving room for inserts between neighbours.
+const APPEND_WIDTH: usize = 4;
+/// Ranks grow by a digit each time the gap they land in runs out; past this length the
+/// ordering is spread out again instead.
+const MAX_RANK_LEN: usize = 12;
+
+impl TodoState {
+    /// Ranks every entry saved before manual ordering existed, keeping its current place.
+    pub(crate) fn migrate_entry_ranks(&mut self) {
+        let mut last = self.last_entry_rank();
+        let trashed = self
+            .trash
+            .iter_mut()
+            .filter_map(|item| match &mut item.record {
+                TrashedRecord::Entry(entry) => Some(entry),
+                TrashedRecord::Note(_) => None,
+            });
+        for entry in self.entries.iter_mut().chain(trashed) {
+            if entry.rank.is_empty() {
+                last = rank_after(&last);
+                entry.rank = last.clone();
+            }
+        }
+    }
+
+    /// Rank that places a new entry at the bottom of whichever column it lands in.
+    pub(crate) fn next_entry_rank(&self) -> String {
+        rank_after(&self.last_entry_rank())
+    }
+
+    /// Rank that places an entry moving into the `status` column at its bottom.
+    pub(crate) fn column_end_rank(&self, status: &EntryStatus) -> String {
+        let last = self
+            .entries
+            .iter()
+            .filter(|e| e.status == *status)
+            .map(|e| e.rank.as_str())
+            .max()
+            .unwrap_or_default();
+        rank_after(last)
+    }
+
+    fn last_entry_rank(&self) -> String {
+        self.entries
+            .iter()
+            .map(|e| e.rank.as_str())
+            .max()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Moves `entry_id` between `before_id` (the entry that ends up directly above it) and
+    /// `after_id` (directly below), within its status column or, with `list_id`, within
+    /// that smart list. With neither neighbour it moves to the bottom.
+    ///
+    /// Returns the ranks that changed, which are broadcast as `EntriesReranked`.
+    pub(crate) fn move_entry_rank(
+        &mut self,
+        entry_id: u64,
+        list_id: Option<u64>,
+        before_id: Option<u64>,
+        after_id: Option<u64>,
+    ) -> Result<Vec<EntryRank>, String> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|e| e.id == entry_id)
+            .ok_or_else(|| "Entry not found".to_string())?;
+
+        let changed = match list_id {
+            None => {
+                let mut column: Vec<EntryRank> = self
+                    .entries
+                    .iter()
+                    .filter(|e| e.status == entry.status)
+                    .map(|e| EntryRank {
+                        entry_id: e.id,
+                        rank: e.rank.clone(),
+                    })
+                    .collect();
+                column.sort_by(|a, b| (&a.rank, a.entry_id).cmp(&(&b.rank, b.entry_id)));
+                let changed = place_between(&column, entry_id, before_id, after_id, |id| {
+                    format!("Entry {id} is not in the same column.")
+                })?;
+                for change in &changed {
+                    if let Some(entry) = self.entries.iter_mut().find(|e| e.id == change.entry_id) {
+                        entry.rank = change.rank.clone();
+                    }
+                }
+                changed
+            }
+            Some(list_id) => {
+                let list = self
+                    .smart_lists
+                    .iter()
+                    .find(|l| l.id == list_id)
+                    .ok_or_else(|| "Smart list not found".to_string())?;
+                let members = self.smart_list_members(list);
+                if !members.iter().any(|e| e.id == entry_id) {
+                    return Err(format!("Entry {entry_id} is not in this smart list."));
+                }
+                let order: Vec<EntryRank> = members
+                    .iter()
+                    .map(|e| EntryRank {
+                        entry_id: e.id,
+                        rank: list_rank(list, e.id).unwrap_or_default().to_string(),
+                    })
+                    .collect();
+                let changed = place_between(&order, entry_id, before_id, after_id, |id| {
+                    format!("Entry {id} is not in this smart list.")
+                })?;
+                // Ranks of entries that have since left the list are dropped
+                let ranks = order
+                    .into_iter()
+                    .map(|rank| {
+                        changed
+                            .iter()
+                            .find(|c| c.entry_id == rank.entry_id)
+                            .cloned()
+                            .unwrap_or(rank)
+                    })
+                    .filter(|rank| !rank.rank.is_empty())
+                    .collect();
+                if let Some(list) = self.smart_lists.iter_mut().find(|l| l.id == list_id) {
+                    list.entry_ranks = ranks;
+                }
+                changed
+            }
+        };
+
+        if !changed.is_empty() {
+            self.publish_change(|seq| WsServerMessage::EntriesReranked {
+                list_id,
+                ranks: changed.clone(),
+                seq,
+            });
+        }
+        Ok(changed)
+    }
+}
+
+/// Puts the members of `list` in the list's manual order. Members that were never moved
+/// keep their query order after the ranked ones.
+pub(crate) fn order_smart_list(list: &SmartList, entries: &mut [Entry]) {
+    entries.sort_by_key(|entry| {
+        let rank = list_rank(list, entry.id);
+        (rank.is_none(), rank.map(str::to_string))
+    });
+}
+
+fn list_rank(list: &SmartList, entry_id: u64) -> Option<&str> {
+    list.entry_ranks
+        .iter()
+        .find(|r| r.entry_id == entry_id)
+        .map(|r| r.rank.as_str())
+}
+
+/// Works out the ranks that move `entry_id` between its neighbours in `order`, which is
+/// sorted as displayed. Only the moved entry is re-ranked unless the gap has run out (or
+/// ranks are missing or tied), in which case the whole order is spread out again.
+fn place_between(
+    order: &[EntryRank],
+    entry_id: u64,
+    before_id: Option<u64>,
+    after_id: Option<u64>,
+    not_found: impl Fn(u64) -> String,
+) -> Result<Vec<EntryRank>, String> {
+    let others: Vec<&EntryRank> = order.iter().filter(|r| r.entry_id != entry_id).collect();
+    let position = |id: u64| {
+        others
+            .iter()
+            .position(|r| r.entry_id == id)
+            .ok_or_else(|| not_found(id))
+    };
+    let index = match (before_id, after_id) {
+        (Some(before), Some(after)) => {
+            let index = position(before)? + 1;
+            if position(after)? != index {
+                return Err(format!(
+                    "Entries {before} and {after} are not next to each other."
+                ));
+            }
+            index
+        }
+        (Some(before), None) => position(before)? + 1,
+        (None, Some(after)) => position(after)?,
+        (None, None) => others.len(),
+    };
+
+    let lo = index.checked_sub(1).map(|i| others[i].rank.as_str());
+    let hi = others.get(index).map(|r| r.rank.as_str());
+    let rank = match (lo, hi) {
+        (Some(""), _) => None,
+        (lo, Some(hi)) if lo.unwrap_or_default() < hi => {
+            Some(rank_between(lo.unwrap_or_default(), hi))
+        }
+        (lo, None) => Some(rank_after(lo.unwrap_or_default())),
+        _ => None,
+    };
+    if let Some(rank) = rank.filter(|rank| rank.len() <= MAX_RANK_LEN) {
+        return Ok(vec![EntryRank { entry_id, rank }]);
+    }
+
+    let mut ids: Vec<u64> = others.iter().map(|r| r.entry_id).collect();
+    ids.insert(index, entry_id);
+    let ranks = spread_ranks(ids.len());
+    Ok(ids
+        .into_iter()
+        .zip(ranks)
+        .filter(|(id, rank)| {
+            order
+                .iter()
+                .find(|r| r.entry_id == *id)
+                .is_none_or(|r| r.rank != *rank)
+        })
+        .map(|(entry_id, rank)| EntryRank { entry_id, rank })
+        .collect())
+}
+
+fn digit(byte: u8) -> u64 {
+    RANK_DIGITS
+        .iter()
+        .position(|d| *d == byte)
+        .unwrap_or_default() as u64
+}
+
+/// The midpoint of `lo` and `hi`, reading ranks as base-36 fractions. `lo` must sort
+/// before `hi`; the result never ends in `0`, so there's always room below it.
+fn rank_between(lo: &str, hi: &str) -> String {
+    let (lo, hi) = (lo.as_bytes(), hi.as_bytes());
+    let mut rank = Vec::new();
+    let mut bounded = true;
+    for i in 0.. {
+        let low = lo.get(i).map_or(0, |b| digit(*b));
+        let high = match hi.get(i) {
+            Some(b) if bounded => digit(*b),
+            _ => RANK_BASE,
+        };
+        if high.saturating_sub(low) > 1 {
+            rank.push(RANK_DIGITS[((low + high) / 2) as usize]);
+            break;
+        }
+        rank.push(RANK_DIGITS[low as usize]);
+        // Past the first digit below `hi`, any continuation stays below it
+        bounded &= high == low;
+    }
+    String::from_utf8(rank).unwrap_or_default()
+}
+
+/// The next rank after `lo` at `APPEND_WIDTH`, falling back to a longer rank once that
+/// width is used up.
+fn rank_after(lo: &str) -> String {
+    let mut digits: Vec<u64> = lo.bytes().map(digit).collect();
+    digits.resize(APPEND_WIDTH, 0);
+    for i in (0..APPEND_WIDTH).rev() {
+        if digits[i] + 1 < RANK_BASE {
+            digits[i] += 1;
+            return encode(&digits);
+        }
+        digits[i] = 0;
+    }
+    rank_between(lo, "")
+}
+
+/// `count` ranks spaced evenly, wide enough to leave a few digits of room between them.
+fn spread_ranks(count: usize) -> Vec<String> {
+    let mut width = APPEND_WIDTH;
+    while RANK_BASE.pow(width as u32) < (count as u64 + 1) * RANK_BASE * RANK_BASE {
+        width += 1;
+    }
+    let step = RANK_BASE.pow(width as u32) / (count as u64 + 1);
+    (1..=count as u64)
+        .map(|k| {
+            let mut value = k * step;
+            let mut digits = vec![0; width];
+            for digit in digits.iter_mut().rev() {
+                *digit = value % RANK_BASE;
+                value /= RANK_BASE;
+            }
+            encode(&digits)
+        })
+        .collect()
+}
+
+/// Writes out `digits`, dropping trailing zeros so that no rank is a zero-padded copy of
+/// another.
+fn encode(digits: &[u64]) -> String {
+    let end = digits.iter().rposition(|d| *d != 0).map_or(0, |i| i + 1);
+    digits[..end]
+        .iter()
+        .map(|d| RANK_DIGITS[*d as usize] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked(ranks: &[&str]) -> Vec<EntryRank> {
+        ranks
+            .iter()
+            .enumerate()
+            .map(|(i, rank)| EntryRank {
+                entry_id: i as u64 + 1,
+                rank: rank.to_string(),
+            })
+            .collect()
+    }
+
+    /// Applies `changes` to `order` and sorts it the way columns are displayed.
+    fn apply(order: &mut [EntryRank], changes: Vec<EntryRank>) {
+        for change in changes {
+            if let Some(rank) = order.iter_mut().find(|r| r.entry_id == change.entry_id) {
+                rank.rank = change.rank;
+            }
+        }
+        order.sort_by(|a, b| (&a.rank, a.entry_id).cmp(&(&b.rank, b.entry_id)));
+    }
+
+    fn ids(order: &[EntryRank]) -> Vec<u64> {
+        order.iter().map(|r| r.entry_id).collect()
+    }
+
+    #[test]
+    fn rank_between_sorts_between_its_bounds() {
+        for (lo, hi) in [
+            ("", "1"),
+            ("", "0001"),
+            ("1", "2"),
+            ("1", "11"),
+            ("a", "a01"),
+            ("0001", "0002"),
+            ("y", "z"),
+            ("zz", ""),
+        ] {
+            let rank = rank_between(lo, hi);
+            assert!(lo < rank.as_str(), "{lo} < {rank}");
+            assert!(hi.is_empty() || rank.as_str() < hi, "{rank} < {hi}");
+            assert!(!rank.ends_with('0'), "{rank} leaves room below");
+        }
+    }
+
+    #[test]
+    fn rank_after_keeps_appending_in_order() {
+        let mut last = String::new();
+        for _ in 0..100 {
+            let next = rank_after(&last);
+            assert!(next > last);
+            assert!(next.len() <= APPEND_WIDTH);
+            last = next;
+        }
+        let past_width = rank_after("zzzz");
+        assert!(past_width.as_str() > "zzzz");
+    }
+
+    #[test]
+    fn spread_ranks_are_unique_and_ordered() {
+        for count in [1, 2, 36, 5000] {
+            let ranks = spread_ranks(count);
+            assert_eq!(ranks.len(), count);
+            assert!(ranks.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!(ranks.iter().all(|rank| !rank.is_empty()));
+        }
+    }
+
+    #[test]
+    fn moves_only_rerank_the_moved_entry() {
+        let mut order = ranked(&["1", "2", "3", "4"]);
+        let changes = place_between(&order, 4, Some(1), Some(2), |_| String::new()).unwrap();
+        assert_eq!(changes.len(), 1);
+        apply(&mut order, changes);
+        assert_eq!(ids(&order), vec![1, 4, 2, 3]);
+
+        let changes = place_between(&order, 1, None, None, |_| String::new()).unwrap();
+        apply(&mut order, changes);
+        assert_eq!(ids(&order), vec![4, 2, 3, 1]);
+
+        let changes = place_between(&order, 3, None, Some(4), |_| String::new()).unwrap();
+        apply(&mut order, changes);
+        assert_eq!(ids(&order), vec![3, 4, 2, 1]);
+    }
+
+    #[test]
+    fn rejects_neighbours_that_are_apart_or_missing() {
+        let order = ranked(&["1", "2", "3"]);
+        assert!(place_between(&order, 3, Some(1), Some(1), |_| String::new()).is_err());
+        assert!(place_between(&order, 1, Some(2), None, |_| String::new()).is_ok());
+        let err = place_between(&order, 1, Some(9), None, |id| format!("missing {id}"));
+        assert_eq!(err.unwrap_err(), "missing 9");
+    }
+
+    #[test]
+    fn exhausted_gaps_respread_the_order() {
+        let mut order = ranked(&["1", "2", "3"]);
+        let mut respread = false;
+        // Keep squeezing the last entry in directly after the first until the gap runs out
+        for _ in 0..200 {
+            let (moved, after) = (order[2].entry_id, order[1].entry_id);
+            let changes =
+                place_between(&order, moved, Some(1), Some(after), |_| String::new()).unwrap();
+            respread |= changes.len() > 1;
+            apply(&mut order, changes);
+            assert_eq!(ids(&order), vec![1, moved, after]);
+            assert!(order.iter().all(|r| r.rank.len() <= MAX_RANK_LEN));
+        }
+        assert!(respread);
+    }
+
+    #[test]
+    fn unranked_smart_list_members_get_ranks_when_moved() {
+        // Smart list members that were never moved carry no rank
+        let mut order = ranked(&["", "", ""]);
+        let changes = place_between(&order, 3, Some(1), Some(2), |_| String::new()).unwrap();
+        assert_eq!(changes.len(), 3);
+        apply(&mut order, changes);
+        assert_eq!(ids(&order), vec![1, 3, 2]);
+        assert!(order.iter().all(|r| !r.rank.is_empty()));
+    }
+}
@@ -0,0 +1,521 @@
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::{
+    clock::Calendar, last_day_of_month, refresh_entry_timescale, workflows::set_entry_state,
+    DayOfWeek, Entry, EntryStatus, RecurrenceFrequency, RecurrenceRule, TodoState,
+};
+
+/// Largest INTERVAL accepted; beyond it the next occurrence would be centuries out.
+const MAX_INTERVAL: u32 = 1000;
+/// Days checked when looking for the next occurrence. Enough for a yearly 29 February rule
+/// to skip a non-leap century; rules that can never match give up here.
+const MAX_DAYS_SEARCHED: u32 = 366 * 9;
+
+impl TodoState {
+    /// Creates the occurrence that follows `entry_id` in its series, returning the new id.
+    pub(crate) fn spawn_next_occurrence(&mut self, entry_id: u64) -> Option<u64> {
+        let calendar = self.calendar();
+        let current = self.entries.iter().find(|e| e.id == entry_id)?.clone();
+        let rule = current.recurrence.clone()?;
+        let anchor_ts = current
+            .due_ts
+            .or(current.completed_at_ts)
+            .unwrap_or_else(|| calendar.now_ts());
+        let next_due = next_occurrence_ts(&rule, anchor_ts, current.occurrence.max(1), &calendar)?;
+
+        let id = self.next_entry_id();
+        let mut next = Entry {
+            id,
+            rank: self.next_entry_rank(),
+            status: EntryStatus::UpNext,
+            due_ts: Some(next_due),
+            // Keep the same lead time between start and due
+            start_ts: current.start_ts.map(|start| next_due - (anchor_ts - start)),
+            note_ids: Vec::new(),
+            is_completed: false,
+            completed_at_ts: None,
+            progress: 0,
+            series_id: current.series_id.or(Some(current.id)),
+            occurrence: current.occurrence.max(1) + 1,
+            next_occurrence_id: None,
+            // Planning the next occurrence into a sprint is left to the user
+            sprint_id: None,
+            ..current
+        };
+        if let Some(state) = self
+            .entry_workflow(next.project_id)
+            .state_for_status(&EntryStatus::UpNext)
+        {
+            set_entry_state(&mut next, state);
+        }
+        refresh_entry_timescale(&mut next, &calendar);
+        self.entries.push(next);
+
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == entry_id) {
+            entry.next_occurrence_id = Some(id);
+        }
+        Some(id)
+    }
+
+    /// Copies the series-wide fields of `source` onto the other open occurrences.
+    pub(crate) fn apply_to_series(&mut self, source: &Entry) -> Vec<u64> {
+        let Some(series_id) = source.series_id else {
+            return Vec::new();
+        };
+        let mut touched = Vec::new();
+        for entry in &mut self.entries {
+            if entry.id == source.id || entry.series_id != Some(series_id) || entry.is_completed {
+                continue;
+            }
+            entry.title = source.title.clone();
+            entry.summary = source.summary.clone();
+            entry.description = source.description.clone();
+            entry.project = source.project.clone();
+            entry.project_id = source.project_id;
+            entry.priority = source.priority.clone();
+            entry.assignees = source.assignees.clone();
+            entry.tags = source.tags.clone();
+            entry.recurrence = source.recurrence.clone();
+            touched.push(entry.id);
+        }
+        touched
+    }
+}
+
+pub(crate) fn validate_recurrence(rule: &RecurrenceRule) -> Result<(), String> {
+    if rule.interval == 0 {
+        return Err("Recurrence interval must be at least 1.".to_string());
+    }
+    if rule.interval > MAX_INTERVAL {
+        return Err(format!(
+            "Recurrence interval must be at most {MAX_INTERVAL}."
+        ));
+    }
+    if rule.count == Some(0) {
+        return Err("Recurrence count must be at least 1.".to_string());
+    }
+    if rule.count.is_some() && rule.until_ts.is_some() {
+        return Err("Recurrence rules cannot set both an end date and a count.".to_string());
+    }
+    if let Some(day) = rule
+        .by_month_day
+        .iter()
+        .find(|day| **day == 0 || day.abs() > 31)
+    {
+        return Err(format!("Invalid day of month in recurrence: {day}"));
+    }
+    Ok(())
+}
+
+/// Returns the due timestamp of the occurrence after `after_ts`, or `None` once the series ends.
+///
+/// `occurrence` is the 1-based position of the occurrence at `after_ts`, used for `count`.
+pub(crate) fn next_occurrence_ts(
+    rule: &RecurrenceRule,
+    after_ts: i64,
+    occurrence: u32,
+    calendar: &Calendar,
+) -> Option<i64> {
+    if rule.count.is_some_and(|count| occurrence >= count) {
+        return None;
+    }
+    let anchor = calendar.local_datetime(after_ts)?;
+    let anchor_date = anchor.date_naive();
+    let time = anchor.time();
+
+    // Walk the periods the rule is active in, skipping the ones INTERVAL leaves out
+    let first_day = anchor_date.succ_opt()?;
+    let mut budget = MAX_DAYS_SEARCHED;
+    for period in 0.. {
+        let (start, end) = period_bounds(rule, anchor_date, period, calendar)?;
+        let mut date = start.max(first_day);
+        while date <= end {
+            if budget == 0 {
+                return None;
+            }
+            budget -= 1;
+            if matches_rule(rule, anchor_date, date) {
+                return calendar
+                    .timestamp(date, time)
+                    .filter(|ts| rule.until_ts.is_none_or(|until| *ts <= until));
+            }
+            date = date.succ_opt()?;
+        }
+    }
+    None
+}
+
+/// First and last day of the `period`-th active period after the one holding `anchor`,
+/// or `None` once that lies past the dates chrono can represent.
+fn period_bounds(
+    rule: &RecurrenceRule,
+    anchor: NaiveDate,
+    period: u32,
+    calendar: &Calendar,
+) -> Option<(NaiveDate, NaiveDate)> {
+    let step = period.checked_mul(rule.interval.max(1))?;
+    match rule.frequency {
+        RecurrenceFrequency::Daily => {
+            let day = anchor.checked_add_days(Days::new(step.into()))?;
+            Some((day, day))
+        }
+        RecurrenceFrequency::Weekly => {
+            let start = calendar
+                .week_start_of(anchor)
+                .checked_add_days(Days::new(u64::from(step) * 7))?;
+            Some((start, start.checked_add_days(Days::new(6))?))
+        }
+        RecurrenceFrequency::Monthly => {
+            let start = anchor.with_day(1)?.checked_add_months(Months::new(step))?;
+            Some((start, start.checked_add_months(Months::new(1))?.pred_opt()?))
+        }
+        RecurrenceFrequency::Yearly => {
+            let year = anchor.year().checked_add(i32::try_from(step).ok()?)?;
+            Some((
+                NaiveDate::from_ymd_opt(year, 1, 1)?,
+                NaiveDate::from_ymd_opt(year, 12, 31)?,
+            ))
+        }
+    }
+}
+
+/// Whether `date`, inside an active period, matches the rule's day filters.
+fn matches_rule(rule: &RecurrenceRule, anchor: NaiveDate, date: NaiveDate) -> bool {
+    // Without BYDAY/BYMONTHDAY the rule repeats on the anchor's own weekday/day/month
+    let no_filters = rule.by_weekday.is_empty() && rule.by_month_day.is_empty();
+    let weekday_ok = if rule.by_weekday.is_empty() {
+        rule.frequency != RecurrenceFrequency::Weekly || date.weekday() == anchor.weekday()
+    } else {
+        rule.by_weekday
+            .iter()
+            .any(|day| day.to_chrono() == date.weekday())
+    };
+    let month_day_ok = if rule.by_month_day.is_empty() {
+        match rule.frequency {
+            RecurrenceFrequency::Monthly if no_filters => date.day() == anchor.day(),
+            RecurrenceFrequency::Yearly if no_filters => {
+                date.month() == anchor.month() && date.day() == anchor.day()
+            }
+            _ => true,
+        }
+    } else {
+        let last_day = last_day_of_month(date.year(), date.month()).day() as i32;
+        rule.by_month_day.iter().any(|day| {
+            let resolved = if *day < 0 { last_day + day + 1 } else { *day };
+            resolved == date.day() as i32
+        })
+    };
+    weekday_ok && month_day_ok
+}
+
+/// Parses an RFC 5545 RRULE value such as `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;COUNT=10`.
+pub(crate) fn parse_rrule(input: &str, calendar: &Calendar) -> Result<RecurrenceRule, String> {
+    let trimmed = input.trim();
+    let body = match trimmed.get(..6) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("RRULE:") => &trimmed[6..],
+        _ => trimmed,
+    };
+
+    let mut frequency = None;
+    let mut rule = RecurrenceRule {
+        frequency: RecurrenceFrequency::Daily,
+        interval: 1,
+        by_weekday: Vec::new(),
+        by_month_day: Vec::new(),
+        until_ts: None,
+        count: None,
+    };
+
+    for part in body.split(';').filter(|part| !part.trim().is_empty()) {
+        let (key, value) = part
+            .split_once('=')
+            .ok_or_else(|| format!("Malformed RRULE part '{part}'"))?;
+        let value = value.trim();
+        match key.trim().to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                frequency = Some(match value.to_ascii_uppercase().as_str() {
+                    "DAILY" => RecurrenceFrequency::Daily,
+                    "WEEKLY" => RecurrenceFrequency::Weekly,
+                    "MONTHLY" => RecurrenceFrequency::Monthly,
+                    "YEARLY" => RecurrenceFrequency::Yearly,
+                    other => return Err(format!("Unsupported FREQ '{other}'")),
+                });
+            }
+            "INTERVAL" => {
+                rule.interval = value
+                    .parse()
+                    .map_err(|_| format!("Invalid INTERVAL '{value}'"))?;
+            }
+            "COUNT" => {
+                rule.count = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid COUNT '{value}'"))?,
+                );
+            }
+            "UNTIL" => rule.until_ts = Some(parse_until(value, calendar)?),
+            "BYDAY" => {
+                rule.by_weekday = value
+                    .split(',')
+                    .map(parse_weekday_code)
+                    .collect::<Result<_, _>>()?;
+            }
+            "BYMONTHDAY" => {
+                rule.by_month_day = value
+                    .split(',')
+                    .map(|day| {
+                        day.trim()
+                            .parse()
+                            .map_err(|_| format!("Invalid BYMONTHDAY '{day}'"))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            other => return Err(format!("Unsupported RRULE part '{other}'")),
+        }
+    }
+
+    rule.frequency = frequency.ok_or_else(|| "RRULE is missing FREQ".to_string())?;
+    validate_recurrence(&rule)?;
+    Ok(rule)
+}
+
+fn parse_weekday_code(code: &str) -> Result<DayOfWeek, String> {
+    let code = code.trim().to_ascii_uppercase();
+    Ok(match code.as_str() {
+        "MO" => DayOfWeek::Monday,
+        "TU" => DayOfWeek::Tuesday,
+        "WE" => DayOfWeek::Wednesday,
+        "TH" => DayOfWeek::Thursday,
+        "FR" => DayOfWeek::Friday,
+        "SA" => DayOfWeek::Saturday,
+        "SU" => DayOfWeek::Sunday,
+        _ if code.len() > 2 => {
+            return Err(format!(
+                "Ordinal BYDAY values such as '{code}' are not supported"
+            ))
+        }
+        _ => return Err(format!("Invalid BYDAY value '{code}'")),
+    })
+}
+
+/// Accepts `YYYYMMDD` (inclusive of that whole day), `YYYYMMDDTHHMMSS` (both in the user's
+/// zone) and `YYYYMMDDTHHMMSSZ` (UTC).
+fn parse_until(value: &str, calendar: &Calendar) -> Result<i64, String> {
+    let invalid = || format!("Invalid UNTIL '{value}'");
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+        return Ok(naive.and_utc().timestamp_millis());
+    }
+    if value.contains('T') {
+        let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+        return calendar
+            .timestamp(naive.date(), naive.time())
+            .ok_or_else(invalid);
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y%m%d").map_err(|_| invalid())?;
+    let end_of_day = NaiveTime::from_hms_milli_opt(23, 59, 59, 999).ok_or_else(invalid)?;
+    calendar.timestamp(date, end_of_day).ok_or_else(invalid)
+}
+
+impl DayOfWeek {
+    pub(crate) fn to_chrono(self) -> Weekday {
+        match self {
+            DayOfWeek::Monday => Weekday::Mon,
+            DayOfWeek::Tuesday => Weekday::Tue,
+            DayOfWeek::Wednesday => Weekday::Wed,
+            DayOfWeek::Thursday => Weekday::Thu,
+            DayOfWeek::Friday => Weekday::Fri,
+            DayOfWeek::Saturday => Weekday::Sat,
+            DayOfWeek::Sunday => Weekday::Sun,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::UserSettings;
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> i64 {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    fn calendar() -> Calendar {
+        Calendar::new(&UserSettings::default(), utc(2026, 1, 1, 0, 0))
+    }
+
+    fn rule(input: &str) -> RecurrenceRule {
+        parse_rrule(input, &calendar()).unwrap()
+    }
+
+    /// The next `n` due timestamps after `start_ts`, which is occurrence 1.
+    fn next_n(rule: &RecurrenceRule, start_ts: i64, n: usize) -> Vec<i64> {
+        let calendar = calendar();
+        let mut due = Vec::new();
+        let mut ts = start_ts;
+        for occurrence in 1..=n as u32 {
+            match next_occurrence_ts(rule, ts, occurrence, &calendar) {
+                Some(next) => {
+                    due.push(next);
+                    ts = next;
+                }
+                None => break,
+            }
+        }
+        due
+    }
+
+    #[test]
+    fn parses_every_supported_part() {
+        assert_eq!(
+            rule("RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,th;COUNT=10"),
+            RecurrenceRule {
+                frequency: RecurrenceFrequency::Weekly,
+                interval: 2,
+                by_weekday: vec![DayOfWeek::Monday, DayOfWeek::Thursday],
+                by_month_day: Vec::new(),
+                until_ts: None,
+                count: Some(10),
+            }
+        );
+        let monthly = rule("freq=monthly;bymonthday=1,-1");
+        assert_eq!(monthly.frequency, RecurrenceFrequency::Monthly);
+        assert_eq!(monthly.by_month_day, vec![1, -1]);
+        assert_eq!(monthly.interval, 1);
+    }
+
+    #[test]
+    fn parses_until_forms() {
+        let end_of_day = Utc
+            .with_ymd_and_hms(2026, 12, 31, 23, 59, 59)
+            .unwrap()
+            .timestamp_millis()
+            + 999;
+        assert_eq!(rule("FREQ=DAILY;UNTIL=20261231").until_ts, Some(end_of_day));
+        assert_eq!(
+            rule("FREQ=DAILY;UNTIL=20261231T120000Z").until_ts,
+            Some(utc(2026, 12, 31, 12, 0))
+        );
+        let new_york = Calendar::new(
+            &UserSettings {
+                time_zone: "America/New_York".to_string(),
+                ..UserSettings::default()
+            },
+            utc(2026, 1, 1, 0, 0),
+        );
+        let local = parse_rrule("FREQ=DAILY;UNTIL=20261231T120000", &new_york).unwrap();
+        assert_eq!(local.until_ts, Some(utc(2026, 12, 31, 17, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let calendar = calendar();
+        for input in [
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;INTERVAL=1001",
+            "FREQ=DAILY;COUNT=0",
+            "FREQ=DAILY;COUNT=3;UNTIL=20261231",
+            "FREQ=WEEKLY;BYDAY=1MO",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=MONTHLY;BYMONTHDAY=0",
+            "FREQ=MONTHLY;BYMONTHDAY=-32",
+            "FREQ=DAILY;UNTIL=2026-12-31",
+            "FREQ=DAILY;BYSETPOS=1",
+        ] {
+            assert!(parse_rrule(input, &calendar).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn daily_interval_keeps_the_time_of_day() {
+        let start = utc(2026, 10, 15, 9, 30);
+        assert_eq!(
+            next_n(&rule("FREQ=DAILY;INTERVAL=3"), start, 2),
+            vec![utc(2026, 10, 18, 9, 30), utc(2026, 10, 21, 9, 30)]
+        );
+    }
+
+    #[test]
+    fn weekly_byday_walks_the_listed_days() {
+        // Thursday 2026-10-15
+        let start = utc(2026, 10, 15, 9, 0);
+        assert_eq!(
+            next_n(&rule("FREQ=WEEKLY;BYDAY=TU,TH"), start, 3),
+            vec![
+                utc(2026, 10, 20, 9, 0),
+                utc(2026, 10, 22, 9, 0),
+                utc(2026, 10, 27, 9, 0),
+            ]
+        );
+        // Every other week: the rest of this week is empty, next week is skipped
+        assert_eq!(
+            next_n(&rule("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU,TH"), start, 2),
+            vec![utc(2026, 10, 27, 9, 0), utc(2026, 10, 29, 9, 0)]
+        );
+    }
+
+    #[test]
+    fn bymonthday_counts_back_from_the_month_end() {
+        let last_day = rule("FREQ=MONTHLY;BYMONTHDAY=-1");
+        assert_eq!(
+            next_n(&last_day, utc(2028, 1, 31, 8, 0), 3),
+            vec![
+                utc(2028, 2, 29, 8, 0),
+                utc(2028, 3, 31, 8, 0),
+                utc(2028, 4, 30, 8, 0),
+            ]
+        );
+        let mid_and_end = rule("FREQ=MONTHLY;BYMONTHDAY=15,-1");
+        assert_eq!(
+            next_n(&mid_and_end, utc(2026, 2, 15, 8, 0), 2),
+            vec![utc(2026, 2, 28, 8, 0), utc(2026, 3, 15, 8, 0)]
+        );
+    }
+
+    #[test]
+    fn month_end_anchors_skip_shorter_months() {
+        // As in RFC 5545, a day that a month lacks is skipped rather than clamped
+        assert_eq!(
+            next_n(&rule("FREQ=MONTHLY"), utc(2026, 1, 31, 8, 0), 2),
+            vec![utc(2026, 3, 31, 8, 0), utc(2026, 5, 31, 8, 0)]
+        );
+        assert_eq!(
+            next_n(
+                &rule("FREQ=MONTHLY;BYMONTHDAY=30"),
+                utc(2026, 1, 30, 8, 0),
+                2
+            ),
+            vec![utc(2026, 3, 30, 8, 0), utc(2026, 4, 30, 8, 0)]
+        );
+        assert_eq!(
+            next_n(&rule("FREQ=YEARLY"), utc(2028, 2, 29, 8, 0), 1),
+            vec![utc(2032, 2, 29, 8, 0)]
+        );
+    }
+
+    #[test]
+    fn count_and_until_end_the_series() {
+        let start = utc(2026, 10, 15, 9, 0);
+        assert_eq!(next_n(&rule("FREQ=DAILY;COUNT=3"), start, 10).len(), 2);
+        assert!(next_n(&rule("FREQ=DAILY;COUNT=1"), start, 10).is_empty());
+
+        // UNTIL is inclusive of its whole day
+        let until = rule("FREQ=DAILY;UNTIL=20261017");
+        assert_eq!(
+            next_n(&until, start, 10),
+            vec![utc(2026, 10, 16, 9, 0), utc(2026, 10, 17, 9, 0)]
+        );
+        let until_exact = rule("FREQ=DAILY;UNTIL=20261016T090000Z");
+        assert_eq!(
+            next_n(&until_exact, start, 10),
+            vec![utc(2026, 10, 16, 9, 0)]
+        );
+    }
+}
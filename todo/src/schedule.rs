@@ -0,0 +1,373 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Entry, EntryStatus, InfeasibleEntry, ProjectSchedule, ScheduleReport, ScheduledEntry};
+
+const MINUTE_MS: i64 = 60_000;
+
+/// Forward pass result for a single entry.
+struct Timing {
+    duration_ms: i64,
+    earliest_start_ts: i64,
+    earliest_finish_ts: i64,
+}
+
+/// Runs a critical path analysis over every non-archived entry and reports it per project.
+///
+/// Open work never starts before `now_ts`; completed entries finish at their completion time.
+pub(crate) fn compute_schedule(
+    entries: &[Entry],
+    now_ts: i64,
+    project: Option<&str>,
+) -> Result<ScheduleReport, String> {
+    let active: Vec<&Entry> = entries
+        .iter()
+        .filter(|e| e.status != EntryStatus::Archived)
+        .collect();
+    let order = topological_order(&active)?;
+    let by_id: HashMap<u64, &Entry> = active.iter().map(|e| (e.id, *e)).collect();
+
+    let mut timings: HashMap<u64, Timing> = HashMap::new();
+    for id in &order {
+        let entry = by_id[id];
+        let duration_ms = entry.estimate_minutes.unwrap_or(0) as i64 * MINUTE_MS;
+        let timing = if entry.is_completed {
+            let finish = entry.completed_at_ts.unwrap_or(now_ts);
+            Timing {
+                duration_ms,
+                earliest_start_ts: finish - duration_ms,
+                earliest_finish_ts: finish,
+            }
+        } else {
+            let release = entry.start_ts.unwrap_or(now_ts).max(now_ts);
+            let start = entry
+                .dependencies
+                .iter()
+                .filter_map(|dep| timings.get(dep))
+                .map(|t| t.earliest_finish_ts)
+                .fold(release, i64::max);
+            Timing {
+                duration_ms,
+                earliest_start_ts: start,
+                earliest_finish_ts: start + duration_ms,
+            }
+        };
+        timings.insert(*id, timing);
+    }
+
+    let mut groups: BTreeMap<Option<String>, Vec<u64>> = BTreeMap::new();
+    for id in &order {
+        let entry_project = by_id[id].project.clone();
+        if project.is_some_and(|wanted| entry_project.as_deref() != Some(wanted)) {
+            continue;
+        }
+        groups.entry(entry_project).or_default().push(*id);
+    }
+
+    let mut projects: Vec<ProjectSchedule> = groups
+        .into_iter()
+        .map(|(name, ids)| schedule_project(name, &ids, &by_id, &timings))
+        .collect();
+    // BTreeMap sorts `None` first; unassigned work reads better at the end
+    projects.sort_by_key(|p| p.project.is_none());
+
+    Ok(ScheduleReport {
+        generated_at_ts: now_ts,
+        projects,
+    })
+}
+
+/// Kahn's algorithm over dependency edges; dependencies outside `entries` are ignored.
+fn topological_order(entries: &[&Entry]) -> Result<Vec<u64>, String> {
+    let ids: Vec<u64> = entries.iter().map(|e| e.id).collect();
+    let mut indegree: HashMap<u64, usize> = HashMap::new();
+    let mut dependents: HashMap<u64, Vec<u64>> = HashMap::new();
+    for entry in entries {
+        let deps: Vec<u64> = entry
+            .dependencies
+            .iter()
+            .copied()
+            .filter(|dep| ids.contains(dep))
+            .collect();
+        indegree.insert(entry.id, deps.len());
+        for dep in deps {
+            dependents.entry(dep).or_default().push(entry.id);
+        }
+    }
+
+    let mut ready: Vec<u64> = ids.iter().copied().filter(|id| indegree[id] == 0).collect();
+    let mut order = Vec::with_capacity(ids.len());
+    while let Some(id) = ready.pop() {
+        order.push(id);
+        for dependent in dependents.get(&id).into_iter().flatten() {
+            let remaining = indegree.get_mut(dependent).expect("dependent is indexed");
+            *remaining -= 1;
+            if *remaining == 0 {
+                ready.push(*dependent);
+            }
+        }
+    }
+
+    if order.len() != ids.len() {
+        let stuck: Vec<String> = ids
+            .iter()
+            .filter(|id| !order.contains(id))
+            .map(|id| id.to_string())
+            .collect();
+        return Err(format!(
+            "Dependency cycle prevents scheduling entries: {}",
+            stuck.join(", ")
+        ));
+    }
+    Ok(order)
+}
+
+fn schedule_project(
+    project: Option<String>,
+    ids: &[u64],
+    by_id: &HashMap<u64, &Entry>,
+    timings: &HashMap<u64, Timing>,
+) -> ProjectSchedule {
+    let start_ts = ids
+        .iter()
+        .map(|id| timings[id].earliest_start_ts)
+        .min()
+        .unwrap_or_default();
+    let finish_ts = ids
+        .iter()
+        .map(|id| timings[id].earliest_finish_ts)
+        .max()
+        .unwrap_or_default();
+
+    // Backward pass in reverse topological order, only following edges inside the project
+    let mut latest_start: HashMap<u64, i64> = HashMap::new();
+    let mut scheduled: Vec<ScheduledEntry> = Vec::with_capacity(ids.len());
+    for id in ids.iter().rev() {
+        let timing = &timings[id];
+        let (latest_start_ts, latest_finish_ts) = if by_id[id].is_completed {
+            (timing.earliest_start_ts, timing.earliest_finish_ts)
+        } else {
+            // A due date before the successors need the entry pulls its latest finish in
+            let latest_finish_ts = ids
+                .iter()
+                .filter(|other| by_id[*other].dependencies.contains(id))
+                .filter_map(|other| latest_start.get(other))
+                .copied()
+                .chain(by_id[id].due_ts)
+                .fold(finish_ts, i64::min);
+            (latest_finish_ts - timing.duration_ms, latest_finish_ts)
+        };
+        latest_start.insert(*id, latest_start_ts);
+
+        let slack_ms = latest_start_ts - timing.earliest_start_ts;
+        scheduled.push(ScheduledEntry {
+            entry_id: *id,
+            duration_minutes: by_id[id].estimate_minutes.unwrap_or(0),
+            earliest_start_ts: timing.earliest_start_ts,
+            earliest_finish_ts: timing.earliest_finish_ts,
+            latest_start_ts,
+            latest_finish_ts,
+            slack_ms,
+            is_critical: slack_ms <= 0 && !by_id[id].is_completed,
+        });
+    }
+    scheduled.reverse();
+
+    let infeasible = scheduled
+        .iter()
+        .filter_map(|item| {
+            let entry = by_id[&item.entry_id];
+            let due_ts = entry.due_ts?;
+            (!entry.is_completed && item.earliest_finish_ts > due_ts).then(|| InfeasibleEntry {
+                entry_id: entry.id,
+                due_ts,
+                earliest_finish_ts: item.earliest_finish_ts,
+                overrun_ms: item.earliest_finish_ts - due_ts,
+            })
+        })
+        .collect();
+
+    ProjectSchedule {
+        project,
+        start_ts,
+        finish_ts,
+        critical_path: critical_path(&scheduled, by_id),
+        entries: scheduled,
+        infeasible,
+    }
+}
+
+/// Walks back from the last critical entry through critical predecessors that feed it directly.
+fn critical_path(scheduled: &[ScheduledEntry], by_id: &HashMap<u64, &Entry>) -> Vec<u64> {
+    let critical: HashMap<u64, &ScheduledEntry> = scheduled
+        .iter()
+        .filter(|item| item.is_critical)
+        .map(|item| (item.entry_id, item))
+        .collect();
+    let Some(mut current) = critical
+        .values()
+        .max_by_key(|item| (item.earliest_finish_ts, std::cmp::Reverse(item.entry_id)))
+        .copied()
+    else {
+        return Vec::new();
+    };
+
+    let mut path = vec![current.entry_id];
+    while let Some(previous) = by_id[&current.entry_id]
+        .dependencies
+        .iter()
+        .filter_map(|dep| critical.get(dep))
+        .filter(|item| item.earliest_finish_ts == current.earliest_start_ts)
+        .min_by_key(|item| item.entry_id)
+    {
+        path.push(previous.entry_id);
+        current = previous;
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_entry;
+
+    const NOW: i64 = 1_000 * MINUTE_MS;
+
+    fn at(minutes: i64) -> i64 {
+        NOW + minutes * MINUTE_MS
+    }
+
+    fn task(id: u64, minutes: u32, dependencies: &[u64]) -> Entry {
+        let mut entry = test_entry(id, &format!("Task {id}"));
+        entry.project = Some("Launch".to_string());
+        entry.estimate_minutes = Some(minutes);
+        entry.dependencies = dependencies.to_vec();
+        entry
+    }
+
+    /// Design feeds build and docs, which both feed the release.
+    fn launch() -> Vec<Entry> {
+        vec![
+            task(1, 60, &[]),
+            task(2, 120, &[1]),
+            task(3, 30, &[1]),
+            task(4, 30, &[2, 3]),
+        ]
+    }
+
+    fn scheduled(project: &ProjectSchedule, id: u64) -> &ScheduledEntry {
+        project
+            .entries
+            .iter()
+            .find(|item| item.entry_id == id)
+            .unwrap()
+    }
+
+    /// (earliest start, latest start, slack) in minutes from `NOW`.
+    fn window(project: &ProjectSchedule, id: u64) -> (i64, i64, i64) {
+        let item = scheduled(project, id);
+        (
+            (item.earliest_start_ts - NOW) / MINUTE_MS,
+            (item.latest_start_ts - NOW) / MINUTE_MS,
+            item.slack_ms / MINUTE_MS,
+        )
+    }
+
+    #[test]
+    fn forward_and_backward_passes_find_the_critical_path() {
+        let report = compute_schedule(&launch(), NOW, None).unwrap();
+        let project = &report.projects[0];
+
+        assert_eq!(project.project.as_deref(), Some("Launch"));
+        assert_eq!((project.start_ts, project.finish_ts), (at(0), at(210)));
+        assert_eq!(window(project, 1), (0, 0, 0));
+        assert_eq!(window(project, 2), (60, 60, 0));
+        assert_eq!(window(project, 3), (60, 150, 90));
+        assert_eq!(window(project, 4), (180, 180, 0));
+        assert_eq!(scheduled(project, 4).earliest_finish_ts, at(210));
+        assert!(!scheduled(project, 3).is_critical);
+        assert_eq!(project.critical_path, vec![1, 2, 4]);
+        assert!(project.infeasible.is_empty());
+
+        // Entries come back in topological order
+        let position = |id| project.entries.iter().position(|e| e.entry_id == id);
+        assert!(position(1) < position(2) && position(1) < position(3));
+        assert!(position(2) < position(4) && position(3) < position(4));
+    }
+
+    #[test]
+    fn due_dates_too_early_are_infeasible() {
+        let mut entries = launch();
+        entries[3].due_ts = Some(at(200));
+        let report = compute_schedule(&entries, NOW, None).unwrap();
+        let project = &report.projects[0];
+
+        assert_eq!(project.infeasible.len(), 1);
+        let late = &project.infeasible[0];
+        assert_eq!(late.entry_id, 4);
+        assert_eq!(late.due_ts, at(200));
+        assert_eq!(late.earliest_finish_ts, at(210));
+        assert_eq!(late.overrun_ms, 10 * MINUTE_MS);
+
+        // The due date pulls the latest finish in along the whole chain
+        assert_eq!(scheduled(project, 4).latest_finish_ts, at(200));
+        assert_eq!(window(project, 4), (180, 170, -10));
+        assert_eq!(window(project, 2), (60, 50, -10));
+        assert_eq!(window(project, 1), (0, -10, -10));
+        assert_eq!(window(project, 3), (60, 140, 80));
+        assert_eq!(project.critical_path, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn completed_work_and_start_dates_anchor_the_schedule() {
+        let mut entries = launch();
+        entries[0].is_completed = true;
+        entries[0].status = EntryStatus::Done;
+        entries[0].completed_at_ts = Some(at(-30));
+        entries[2].start_ts = Some(at(100));
+        let report = compute_schedule(&entries, NOW, None).unwrap();
+        let project = &report.projects[0];
+
+        let design = scheduled(project, 1);
+        assert_eq!(design.earliest_start_ts, at(-90));
+        assert_eq!(design.earliest_finish_ts, at(-30));
+        assert!(!design.is_critical);
+        // Open work never starts in the past, nor before its own start date
+        assert_eq!(window(project, 2), (0, 10, 10));
+        assert_eq!(window(project, 3), (100, 100, 0));
+        // The late docs now hold up the release instead of the build
+        assert_eq!(window(project, 4), (130, 130, 0));
+        assert_eq!(project.critical_path, vec![3, 4]);
+    }
+
+    #[test]
+    fn projects_are_grouped_and_filtered() {
+        let mut entries = launch();
+        entries.push(test_entry(5, "Loose end"));
+        let mut archived = task(6, 600, &[4]);
+        archived.status = EntryStatus::Archived;
+        entries.push(archived);
+
+        let report = compute_schedule(&entries, NOW, None).unwrap();
+        let names: Vec<Option<&str>> = report
+            .projects
+            .iter()
+            .map(|p| p.project.as_deref())
+            .collect();
+        assert_eq!(names, vec![Some("Launch"), None]);
+        assert_eq!(report.projects[0].entries.len(), 4);
+        assert_eq!(report.projects[0].finish_ts, at(210));
+
+        let report = compute_schedule(&entries, NOW, Some("Launch")).unwrap();
+        assert_eq!(report.projects.len(), 1);
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let entries = vec![task(1, 10, &[2]), task(2, 10, &[1]), task(3, 10, &[])];
+        assert_eq!(
+            compute_schedule(&entries, NOW, None).unwrap_err(),
+            "Dependency cycle prevents scheduling entries: 1, 2"
+        );
+    }
+}
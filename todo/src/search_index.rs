@@ -0,0 +1,675 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{Entry, Note, SearchField, SearchHit, SearchHitKind, SnippetSegment, TodoState};
+
+/// Indexed field groups and how much a match in each one counts towards the score.
+const FIELD_COUNT: usize = 3;
+const TITLE: usize = 0;
+const LABELS: usize = 1;
+const BODY: usize = 2;
+const FIELD_BOOSTS: [f64; FIELD_COUNT] = [3.0, 2.0, 1.0];
+
+/// BM25 saturation and length normalisation.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+/// A prefix match ("launc" for "launch") counts for less than a whole-word match.
+const PREFIX_WEIGHT: f64 = 0.5;
+const MIN_PREFIX_LEN: usize = 2;
+
+/// Words of context kept on either side of the first match in a snippet.
+const SNIPPET_WORDS_BEFORE: usize = 6;
+const SNIPPET_WORDS_AFTER: usize = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum DocKey {
+    Entry(u64),
+    Note(u64),
+}
+
+/// In-memory inverted index over entry and note text; rebuilt on boot, never persisted.
+#[derive(Debug, Default)]
+pub(crate) struct SearchIndex {
+    docs: HashMap<DocKey, IndexedDoc>,
+    /// Stemmed term to the documents containing it. Ordered so prefixes are a range scan.
+    postings: BTreeMap<String, HashSet<DocKey>>,
+    total_lengths: [u64; FIELD_COUNT],
+}
+
+#[derive(Debug)]
+struct IndexedDoc {
+    term_freqs: HashMap<String, [u32; FIELD_COUNT]>,
+    lengths: [u32; FIELD_COUNT],
+}
+
+/// A query word in both forms: `raw` for prefix matching, `stem` for whole-word matching.
+#[derive(Debug, Clone)]
+struct QueryToken {
+    raw: String,
+    stem: String,
+}
+
+impl SearchIndex {
+    fn upsert(&mut self, key: DocKey, fields: [String; FIELD_COUNT]) {
+        self.remove(key);
+        let mut doc = IndexedDoc {
+            term_freqs: HashMap::new(),
+            lengths: [0; FIELD_COUNT],
+        };
+        for (field, text) in fields.iter().enumerate() {
+            for word in words(text) {
+                doc.term_freqs
+                    .entry(stem(&word))
+                    .or_insert([0; FIELD_COUNT])[field] += 1;
+                doc.lengths[field] += 1;
+            }
+            self.total_lengths[field] += doc.lengths[field] as u64;
+        }
+        for term in doc.term_freqs.keys() {
+            self.postings.entry(term.clone()).or_default().insert(key);
+        }
+        self.docs.insert(key, doc);
+    }
+
+    fn remove(&mut self, key: DocKey) {
+        let Some(doc) = self.docs.remove(&key) else {
+            return;
+        };
+        for field in 0..FIELD_COUNT {
+            self.total_lengths[field] -= doc.lengths[field] as u64;
+        }
+        for term in doc.term_freqs.keys() {
+            if let Some(keys) = self.postings.get_mut(term) {
+                keys.remove(&key);
+                if keys.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+    }
+
+    /// Index terms matching `token`, with the weight each match carries.
+    fn expand(&self, token: &QueryToken) -> Vec<(&str, f64)> {
+        let mut terms: Vec<(&str, f64)> = self
+            .postings
+            .get_key_value(&token.stem)
+            .map(|(term, _)| (term.as_str(), 1.0))
+            .into_iter()
+            .collect();
+        if token.raw.chars().count() >= MIN_PREFIX_LEN {
+            terms.extend(
+                self.postings
+                    .range(token.raw.clone()..)
+                    .map(|(term, _)| term.as_str())
+                    .take_while(|term| term.starts_with(&token.raw))
+                    .filter(|term| *term != token.stem)
+                    .map(|term| (term, PREFIX_WEIGHT)),
+            );
+        }
+        terms
+    }
+
+    fn matching_docs(&self, token: &QueryToken) -> HashSet<DocKey> {
+        self.expand(token)
+            .into_iter()
+            .flat_map(|(term, _)| self.postings[term].iter().copied())
+            .collect()
+    }
+
+    /// BM25F: field frequencies are boosted and length-normalised before saturation.
+    fn score(&self, key: DocKey, tokens: &[QueryToken]) -> f64 {
+        let Some(doc) = self.docs.get(&key) else {
+            return 0.0;
+        };
+        let doc_count = self.docs.len() as f64;
+        let mut score = 0.0;
+        for token in tokens {
+            for (term, weight) in self.expand(token) {
+                let Some(freqs) = doc.term_freqs.get(term) else {
+                    continue;
+                };
+                let mut weighted_tf = 0.0;
+                for field in 0..FIELD_COUNT {
+                    let average = self.total_lengths[field] as f64 / doc_count;
+                    let norm = if average > 0.0 {
+                        1.0 - B + B * doc.lengths[field] as f64 / average
+                    } else {
+                        1.0
+                    };
+                    weighted_tf += FIELD_BOOSTS[field] * freqs[field] as f64 / norm;
+                }
+                let doc_freq = self.postings[term].len() as f64;
+                let idf = (1.0 + (doc_count - doc_freq + 0.5) / (doc_freq + 0.5)).ln();
+                score += weight * idf * weighted_tf * (K1 + 1.0) / (weighted_tf + K1);
+            }
+        }
+        score
+    }
+}
+
+impl TodoState {
+    pub(crate) fn rebuild_search_index(&mut self) {
+        self.search_index = SearchIndex::default();
+        let entry_ids: Vec<u64> = self.entries.iter().map(|e| e.id).collect();
+        for id in entry_ids {
+            self.index_entry(id);
+        }
+        let note_ids: Vec<u64> = self.notes.iter().map(|n| n.id).collect();
+        for id in note_ids {
+            self.index_note(id);
+        }
+    }
+
+    /// Re-indexes `entry_id`, or drops it from the index if the entry no longer exists.
+    pub(crate) fn index_entry(&mut self, entry_id: u64) {
+        let key = DocKey::Entry(entry_id);
+        match self.entries.iter().find(|e| e.id == entry_id) {
+            Some(entry) => {
+                let fields = entry_fields(entry);
+                self.search_index.upsert(key, fields);
+            }
+            None => self.search_index.remove(key),
+        }
+    }
+
+    /// Re-indexes `note_id`, or drops it from the index if the note no longer exists.
+    pub(crate) fn index_note(&mut self, note_id: u64) {
+        let key = DocKey::Note(note_id);
+        match self.notes.iter().find(|n| n.id == note_id) {
+            Some(note) => {
+                let fields = note_fields(note);
+                self.search_index.upsert(key, fields);
+            }
+            None => self.search_index.remove(key),
+        }
+    }
+
+    /// Documents containing every word of `text`; multi-word text must also appear verbatim.
+    pub(crate) fn text_matches(&self, text: &str) -> HashSet<DocKey> {
+        let tokens = query_tokens(text);
+        let Some((first, rest)) = tokens.split_first() else {
+            return HashSet::new();
+        };
+        let mut matches = self.search_index.matching_docs(first);
+        for token in rest {
+            let next = self.search_index.matching_docs(token);
+            matches.retain(|key| next.contains(key));
+        }
+        if tokens.len() > 1 {
+            let phrase = text.to_lowercase();
+            matches.retain(|key| self.doc_text(*key).to_lowercase().contains(&phrase));
+        }
+        matches
+    }
+
+    /// Orders `entries` and `notes` by relevance to `texts` and builds a highlighted hit for
+    /// each scoring result, best first. Ties keep storage order.
+    pub(crate) fn rank_results(
+        &self,
+        entries: &mut [Entry],
+        notes: &mut [Note],
+        texts: &[&str],
+    ) -> Vec<SearchHit> {
+        let tokens: Vec<QueryToken> = texts.iter().flat_map(|text| query_tokens(text)).collect();
+        if tokens.is_empty() {
+            return Vec::new();
+        }
+        let score = |key| self.search_index.score(key, &tokens);
+        let entry_scores: HashMap<u64, f64> = entries
+            .iter()
+            .map(|e| (e.id, score(DocKey::Entry(e.id))))
+            .collect();
+        let note_scores: HashMap<u64, f64> = notes
+            .iter()
+            .map(|n| (n.id, score(DocKey::Note(n.id))))
+            .collect();
+        entries.sort_by(|a, b| entry_scores[&b.id].total_cmp(&entry_scores[&a.id]));
+        notes.sort_by(|a, b| note_scores[&b.id].total_cmp(&note_scores[&a.id]));
+
+        let mut hits: Vec<SearchHit> = entries
+            .iter()
+            .map(|entry| {
+                let (field, snippet) = snippet(
+                    &[
+                        (SearchField::Description, &entry.description),
+                        (SearchField::Summary, &entry.summary),
+                        (SearchField::Title, &entry.title),
+                    ],
+                    &tokens,
+                );
+                SearchHit {
+                    kind: SearchHitKind::Entry,
+                    id: entry.id,
+                    score: entry_scores[&entry.id],
+                    field,
+                    snippet,
+                }
+            })
+            .chain(notes.iter().map(|note| {
+                let (field, snippet) = snippet(
+                    &[
+                        (SearchField::Content, &note.content),
+                        (SearchField::Summary, &note.summary),
+                        (SearchField::Title, &note.title),
+                    ],
+                    &tokens,
+                );
+                SearchHit {
+                    kind: SearchHitKind::Note,
+                    id: note.id,
+                    score: note_scores[&note.id],
+                    field,
+                    snippet,
+                }
+            }))
+            .filter(|hit| hit.score > 0.0)
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits
+    }
+
+    fn doc_text(&self, key: DocKey) -> String {
+        let fields = match key {
+            DocKey::Entry(id) => self.entries.iter().find(|e| e.id == id).map(entry_fields),
+            DocKey::Note(id) => self.notes.iter().find(|n| n.id == id).map(note_fields),
+        };
+        fields.map(|fields| fields.join("\n")).unwrap_or_default()
+    }
+}
+
+fn entry_fields(entry: &Entry) -> [String; FIELD_COUNT] {
+    let mut labels: Vec<&str> = entry.tags.iter().map(String::as_str).collect();
+    labels.extend(entry.project.as_deref());
+    labels.extend(entry.assignees.iter().map(String::as_str));
+
+    let mut fields: [String; FIELD_COUNT] = Default::default();
+    fields[TITLE] = entry.title.clone();
+    fields[LABELS] = labels.join("\n");
+    fields[BODY] = format!("{}\n{}", entry.summary, entry.description);
+    fields
+}
+
+fn note_fields(note: &Note) -> [String; FIELD_COUNT] {
+    let mut fields: [String; FIELD_COUNT] = Default::default();
+    fields[TITLE] = note.title.clone();
+    fields[LABELS] = note.tags.join("\n");
+    fields[BODY] = format!("{}\n{}", note.summary, note.content);
+    fields
+}
+
+/// Lowercased runs of letters and digits.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+fn query_tokens(text: &str) -> Vec<QueryToken> {
+    words(text)
+        .map(|raw| QueryToken {
+            stem: stem(&raw),
+            raw,
+        })
+        .collect()
+}
+
+/// A light English stemmer covering plurals, `-ed`/`-ing`, `-ly` and a trailing `e`, so
+/// "plans", "planned" and "planning" all index as "plan".
+fn stem(word: &str) -> String {
+    if word.len() <= 3 || !word.chars().all(|c| c.is_ascii_lowercase()) {
+        return word.to_string();
+    }
+    let mut stem = word.to_string();
+
+    if ["sses", "ches", "shes", "xes", "zes"]
+        .iter()
+        .any(|suffix| stem.ends_with(suffix))
+    {
+        stem.truncate(stem.len() - 2);
+    } else if stem.ends_with("ies") {
+        stem.truncate(stem.len() - 3);
+        stem.push('y');
+    } else if stem.ends_with('s') && !stem.ends_with("ss") && !stem.ends_with("us") {
+        stem.pop();
+    }
+
+    for suffix in ["ing", "ed", "ly"] {
+        if let Some(base) = stem.strip_suffix(suffix) {
+            if base.len() >= 3 && base.chars().any(is_vowel) {
+                stem.truncate(base.len());
+                if suffix != "ly" {
+                    undouble(&mut stem);
+                }
+                break;
+            }
+        }
+    }
+
+    if stem.len() > 3 && stem.ends_with('e') {
+        stem.pop();
+    }
+    stem
+}
+
+/// "plann" -> "plan", but "fall" and "pass" keep their double letter.
+fn undouble(stem: &mut String) {
+    let mut tail = stem.chars().rev();
+    if let (Some(last), Some(previous)) = (tail.next(), tail.next()) {
+        if last == previous && !is_vowel(last) && !matches!(last, 'l' | 's' | 'z') {
+            stem.pop();
+        }
+    }
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y')
+}
+
+fn token_matches(word: &str, tokens: &[QueryToken]) -> bool {
+    let lower = word.to_lowercase();
+    let stemmed = stem(&lower);
+    tokens.iter().any(|token| {
+        stemmed == token.stem
+            || (token.raw.chars().count() >= MIN_PREFIX_LEN && lower.starts_with(&token.raw))
+    })
+}
+
+/// Picks the first field with a match and cuts a window of words around it, splitting the
+/// text into plain and highlighted segments.
+fn snippet(
+    fields: &[(SearchField, &String)],
+    tokens: &[QueryToken],
+) -> (SearchField, Vec<SnippetSegment>) {
+    for (field, text) in fields {
+        // Byte ranges of each word in the original text
+        let mut spans = Vec::new();
+        let mut start = None;
+        for (idx, c) in text.char_indices() {
+            match (c.is_alphanumeric(), start) {
+                (true, None) => start = Some(idx),
+                (false, Some(from)) => {
+                    spans.push((from, idx));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(from) = start {
+            spans.push((from, text.len()));
+        }
+
+        let Some(first) = spans
+            .iter()
+            .position(|(from, to)| token_matches(&text[*from..*to], tokens))
+        else {
+            continue;
+        };
+        let window_start = first.saturating_sub(SNIPPET_WORDS_BEFORE);
+        let window_end = (first + SNIPPET_WORDS_AFTER).min(spans.len() - 1);
+
+        let mut segments: Vec<SnippetSegment> = Vec::new();
+        let mut push = |text: &str, highlighted: bool| match segments.last_mut() {
+            Some(last) if last.highlighted == highlighted => last.text.push_str(text),
+            _ if text.is_empty() => {}
+            _ => segments.push(SnippetSegment {
+                text: text.to_string(),
+                highlighted,
+            }),
+        };
+        if window_start > 0 {
+            push("…", false);
+        }
+        let mut cursor = spans[window_start].0;
+        for (from, to) in &spans[window_start..=window_end] {
+            push(&text[cursor..*from], false);
+            push(&text[*from..*to], token_matches(&text[*from..*to], tokens));
+            cursor = *to;
+        }
+        if window_end + 1 < spans.len() {
+            push("…", false);
+        }
+        return (*field, segments);
+    }
+
+    let (field, text) = fields[fields.len() - 1];
+    let segments = vec![SnippetSegment {
+        text: text.clone(),
+        highlighted: false,
+    }];
+    (field, segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_entry;
+
+    fn fields(title: &str, labels: &str, body: &str) -> [String; FIELD_COUNT] {
+        [title.to_string(), labels.to_string(), body.to_string()]
+    }
+
+    fn test_note(id: u64, title: &str, content: &str) -> Note {
+        Note {
+            id,
+            title: title.to_string(),
+            content: content.to_string(),
+            pinned: false,
+            tags: Vec::new(),
+            linked_entry_ids: Vec::new(),
+            summary: String::new(),
+            accent: String::new(),
+            last_edited_ts: 0,
+            revision: 0,
+        }
+    }
+
+    /// Documents in `index` ordered by their score for `query`, best first.
+    fn ranked(index: &SearchIndex, query: &str) -> Vec<DocKey> {
+        let tokens = query_tokens(query);
+        let mut keys: Vec<(DocKey, f64)> = index
+            .docs
+            .keys()
+            .map(|key| (*key, index.score(*key, &tokens)))
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        keys.sort_by(|a, b| b.1.total_cmp(&a.1));
+        keys.into_iter().map(|(key, _)| key).collect()
+    }
+
+    /// Snippet segments joined into one string, with highlighted words in brackets.
+    fn render(segments: &[SnippetSegment]) -> String {
+        segments
+            .iter()
+            .map(|segment| match segment.highlighted {
+                true => format!("[{}]", segment.text),
+                false => segment.text.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn words_are_lowercased_alphanumeric_runs() {
+        let split: Vec<String> = words("Ship v2.0 -- the Launch-plan!").collect();
+        assert_eq!(split, vec!["ship", "v2", "0", "the", "launch", "plan"]);
+        let split: Vec<String> = words("Café  Über").collect();
+        assert_eq!(split, vec!["café", "über"]);
+
+        let tokens = query_tokens("Planning, Releases");
+        assert_eq!(tokens[0].raw, "planning");
+        assert_eq!(tokens[0].stem, "plan");
+        assert_eq!(tokens[1].raw, "releases");
+        assert_eq!(tokens[1].stem, "releas");
+    }
+
+    #[test]
+    fn stemming_folds_common_suffixes() {
+        for word in ["plan", "plans", "planned", "planning"] {
+            assert_eq!(stem(word), "plan", "{word}");
+        }
+        assert_eq!(stem("stories"), "story");
+        assert_eq!(stem("boxes"), "box");
+        assert_eq!(stem("quickly"), "quick");
+        assert_eq!(stem("release"), stem("releases"));
+        // Double letters that belong to the word stay put
+        assert_eq!(stem("falling"), "fall");
+        assert_eq!(stem("passing"), "pass");
+        // Short words, non-plural "s" endings and non-ASCII words are left alone
+        assert_eq!(stem("need"), "need");
+        assert_eq!(stem("bus"), "bus");
+        assert_eq!(stem("status"), "status");
+        assert_eq!(stem("class"), "class");
+        assert_eq!(stem("cafés"), "cafés");
+    }
+
+    #[test]
+    fn prefixes_match_at_a_lower_weight() {
+        let mut index = SearchIndex::default();
+        index.upsert(DocKey::Entry(1), fields("plan", "", ""));
+        index.upsert(DocKey::Entry(2), fields("planet", "", ""));
+        index.upsert(DocKey::Entry(3), fields("launch", "", ""));
+
+        let launch = DocKey::Entry(3);
+        assert_eq!(
+            index.matching_docs(&query_tokens("laun")[0]),
+            HashSet::from([launch])
+        );
+        assert!(index.matching_docs(&query_tokens("l")[0]).is_empty());
+        // A whole-word match on "plan" outranks the prefix match on "planet"
+        assert_eq!(
+            ranked(&index, "plan"),
+            vec![DocKey::Entry(1), DocKey::Entry(2)]
+        );
+        let exact = index.score(DocKey::Entry(1), &query_tokens("plan"));
+        let prefix = index.score(DocKey::Entry(2), &query_tokens("plan"));
+        assert!((prefix - exact * PREFIX_WEIGHT).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bm25_favours_frequent_terms_in_short_documents() {
+        let mut index = SearchIndex::default();
+        index.upsert(DocKey::Entry(1), fields("", "", "deploy the service"));
+        index.upsert(DocKey::Entry(2), fields("", "", "deploy deploy service"));
+        index.upsert(
+            DocKey::Entry(3),
+            fields("", "", "deploy the service after the long review"),
+        );
+        index.upsert(DocKey::Entry(4), fields("", "", "unrelated"));
+
+        assert_eq!(
+            ranked(&index, "deploy"),
+            vec![DocKey::Entry(2), DocKey::Entry(1), DocKey::Entry(3)]
+        );
+        // A term found in fewer documents carries more weight
+        let common = index.score(DocKey::Entry(3), &query_tokens("service"));
+        let rare = index.score(DocKey::Entry(3), &query_tokens("review"));
+        assert!(rare > common);
+    }
+
+    #[test]
+    fn title_matches_outweigh_labels_and_body() {
+        let mut index = SearchIndex::default();
+        index.upsert(DocKey::Entry(1), fields("misc", "other", "deploy"));
+        index.upsert(DocKey::Entry(2), fields("deploy", "misc", "other"));
+        index.upsert(DocKey::Note(3), fields("misc", "deploy", "other"));
+
+        assert_eq!(
+            ranked(&index, "deploy"),
+            vec![DocKey::Entry(2), DocKey::Note(3), DocKey::Entry(1)]
+        );
+    }
+
+    #[test]
+    fn snippets_highlight_matches_in_the_first_matching_field() {
+        let description = String::new();
+        let summary = "We should plan the launch carefully".to_string();
+        let title = "Launch".to_string();
+        let texts = [
+            (SearchField::Description, &description),
+            (SearchField::Summary, &summary),
+            (SearchField::Title, &title),
+        ];
+
+        let (field, segments) = snippet(&texts, &query_tokens("planning launch"));
+        assert_eq!(field, SearchField::Summary);
+        assert_eq!(render(&segments), "We should [plan] the [launch] carefully");
+
+        let (field, segments) = snippet(&texts, &query_tokens("missing"));
+        assert_eq!(field, SearchField::Title);
+        assert_eq!(render(&segments), "Launch");
+    }
+
+    #[test]
+    fn long_snippets_are_cut_around_the_first_match() {
+        let mut text: Vec<String> = (0..40).map(|i| format!("w{i}")).collect();
+        text[20] = "launch".to_string();
+        let text = text.join(" ");
+
+        let (_, segments) = snippet(&[(SearchField::Content, &text)], &query_tokens("launch"));
+        let window: Vec<String> = (14..=34)
+            .map(|i| match i {
+                20 => "[launch]".to_string(),
+                i => format!("w{i}"),
+            })
+            .collect();
+        assert_eq!(render(&segments), format!("…{}…", window.join(" ")));
+    }
+
+    /// The index contents and the scores a few queries give each document.
+    fn index_summary(state: &TodoState) -> (Vec<String>, [u64; FIELD_COUNT], Vec<String>) {
+        let index = &state.search_index;
+        let mut postings: Vec<String> = index
+            .postings
+            .iter()
+            .map(|(term, keys)| {
+                let mut keys: Vec<String> = keys.iter().map(|key| format!("{key:?}")).collect();
+                keys.sort();
+                format!("{term}: {}", keys.join(", "))
+            })
+            .collect();
+        postings.sort();
+        let mut scores = Vec::new();
+        for query in ["launch", "plan", "release party", "lo", "notes"] {
+            let tokens = query_tokens(query);
+            let mut keys: Vec<&DocKey> = index.docs.keys().collect();
+            keys.sort_by_key(|key| format!("{key:?}"));
+            for key in keys {
+                scores.push(format!("{query} {key:?} {}", index.score(*key, &tokens)));
+            }
+        }
+        (postings, index.total_lengths, scores)
+    }
+
+    #[test]
+    fn incremental_updates_match_a_rebuild() {
+        let mut state = TodoState::default();
+        let mut plan = test_entry(1, "Plan the launch");
+        plan.tags = vec!["release".to_string()];
+        let mut login = test_entry(2, "Fix login bug");
+        login.description = "Crashes when planning".to_string();
+        state.entries = vec![plan, login];
+        state.notes = vec![test_note(1, "Launch notes", "Checklist for launch day")];
+        state.rebuild_search_index();
+
+        state.entries[0].title = "Plan the release party".to_string();
+        state.index_entry(1);
+        state.entries.remove(1);
+        state.index_entry(2);
+        state
+            .entries
+            .push(test_entry(3, "Write the launch blog post"));
+        state.index_entry(3);
+        state.notes[0].content = "Nothing left to do".to_string();
+        state.index_note(1);
+        state
+            .notes
+            .push(test_note(2, "Party notes", "Logistics for the release"));
+        state.index_note(2);
+
+        let incremental = index_summary(&state);
+        let matches = state.text_matches("release party");
+        state.rebuild_search_index();
+        assert_eq!(index_summary(&state), incremental);
+        assert_eq!(state.text_matches("release party"), matches);
+        assert_eq!(matches, HashSet::from([DocKey::Entry(1)]));
+        assert!(state.text_matches("login").is_empty());
+    }
+}
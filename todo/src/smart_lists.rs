@@ -0,0 +1,204 @@
+use std::collections::HashSet;
+
+use crate::{
+    query::parse_query, ranks::order_smart_list, search_index::DocKey, Entry, EntryStatus,
+    SmartList, TodoState, WsServerMessage,
+};
+
+/// WebSocket channels following a smart list, with the members they were last sent.
+#[derive(Debug)]
+pub(crate) struct SmartListSubscription {
+    list_id: u64,
+    channels: HashSet<u32>,
+    members: Vec<Entry>,
+    /// The query `members` were worked out with.
+    query: String,
+    /// The change `members` are up to date with; `None` re-runs the query in full.
+    seen_seq: Option<u64>,
+}
+
+impl TodoState {
+    pub(crate) fn validate_smart_list(&self, name: &str, query: &str) -> Result<(), String> {
+        if name.trim().is_empty() {
+            return Err("Smart lists require a name.".to_string());
+        }
+        parse_query(query, &self.calendar())
+            .map(|_| ())
+            .map_err(|err| format!("Invalid smart list query: {err}"))
+    }
+
+    /// Current members of `list`, in the list's order. A query that no longer parses
+    /// yields an empty list.
+    pub(crate) fn smart_list_members(&self, list: &SmartList) -> Vec<Entry> {
+        let calendar = self.calendar();
+        let mut members = parse_query(&list.query, &calendar)
+            .map(|query| self.run_query(&query, &calendar).entries)
+            .unwrap_or_default();
+        order_smart_list(list, &mut members);
+        members
+    }
+
+    /// Follows `list_id` on `channel_id`, replying with the list's current members.
+    ///
+    /// Unknown lists are answered with `SmartListRemoved`.
+    pub(crate) fn subscribe_to_smart_list(&mut self, channel_id: u32, list_id: u64) {
+        let Some(list) = self.smart_lists.iter().find(|l| l.id == list_id) else {
+            self.send_ws_message(channel_id, &WsServerMessage::SmartListRemoved { list_id });
+            return;
+        };
+        let members = self.smart_list_members(list);
+        self.send_ws_message(
+            channel_id,
+            &WsServerMessage::SmartListSnapshot {
+                list_id,
+                entries: members.clone(),
+            },
+        );
+
+        match self
+            .smart_list_subscriptions
+            .iter_mut()
+            .find(|s| s.list_id == list_id)
+        {
+            Some(subscription) => {
+                subscription.channels.insert(channel_id);
+            }
+            None => self.smart_list_subscriptions.push(SmartListSubscription {
+                list_id,
+                channels: HashSet::from([channel_id]),
+                members,
+                query: list.query.clone(),
+                seen_seq: Some(self.change_seq),
+            }),
+        }
+    }
+
+    /// Stops following `list_id` on `channel_id`, or every list when `list_id` is `None`.
+    pub(crate) fn unsubscribe_from_smart_lists(&mut self, channel_id: u32, list_id: Option<u64>) {
+        for subscription in &mut self.smart_list_subscriptions {
+            if list_id.is_none_or(|id| id == subscription.list_id) {
+                subscription.channels.remove(&channel_id);
+            }
+        }
+        self.smart_list_subscriptions
+            .retain(|s| !s.channels.is_empty());
+    }
+
+    /// Makes the next refresh re-run every followed list in full, for changes that can move
+    /// entries in or out without touching them, such as the day rolling over.
+    pub(crate) fn invalidate_smart_lists(&mut self) {
+        for subscription in &mut self.smart_list_subscriptions {
+            subscription.seen_seq = None;
+        }
+    }
+
+    /// Re-evaluates the followed lists against the entries changed since their last refresh
+    /// and pushes membership changes to their channels. A list whose query changed, or
+    /// whose last refresh fell out of the change log, is re-run in full.
+    ///
+    /// Entries that stay in a list but changed are sent as `SmartListEntryUpdated`.
+    pub(crate) fn refresh_smart_lists(&mut self) {
+        if self.change_log.is_batching() {
+            // `commit_batch` refreshes once the whole batch is in
+            return;
+        }
+        let mut subscriptions = std::mem::take(&mut self.smart_list_subscriptions);
+        for subscription in &mut subscriptions {
+            let list_id = subscription.list_id;
+            let Some(list) = self.smart_lists.iter().find(|l| l.id == list_id) else {
+                for channel_id in &subscription.channels {
+                    self.send_ws_message(
+                        *channel_id,
+                        &WsServerMessage::SmartListRemoved { list_id },
+                    );
+                }
+                subscription.channels.clear();
+                continue;
+            };
+            let changed = subscription
+                .seen_seq
+                .filter(|_| subscription.query == list.query)
+                .and_then(|seq| self.entries_changed_since(seq));
+            let members = match changed {
+                Some(entry_ids) => self.updated_members(list, &subscription.members, &entry_ids),
+                None => self.smart_list_members(list),
+            };
+            subscription.query = list.query.clone();
+            subscription.seen_seq = Some(self.change_seq);
+
+            let mut messages = Vec::new();
+            for previous in &subscription.members {
+                if !members.iter().any(|e| e.id == previous.id) {
+                    messages.push(WsServerMessage::SmartListLeft {
+                        list_id,
+                        entry_id: previous.id,
+                    });
+                }
+            }
+            for entry in &members {
+                match subscription.members.iter().find(|e| e.id == entry.id) {
+                    None => messages.push(WsServerMessage::SmartListEntered {
+                        list_id,
+                        entry: Box::new(entry.clone()),
+                    }),
+                    Some(previous) if !same_entry(previous, entry) => {
+                        messages.push(WsServerMessage::SmartListEntryUpdated {
+                            list_id,
+                            entry: Box::new(entry.clone()),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            for message in &messages {
+                for channel_id in &subscription.channels {
+                    self.send_ws_message(*channel_id, message);
+                }
+            }
+            subscription.members = members;
+        }
+        subscriptions.retain(|s| !s.channels.is_empty());
+        self.smart_list_subscriptions = subscriptions;
+    }
+
+    /// `members` of `list` after re-checking only `entry_ids` against its query.
+    fn updated_members(
+        &self,
+        list: &SmartList,
+        members: &[Entry],
+        entry_ids: &[u64],
+    ) -> Vec<Entry> {
+        let calendar = self.calendar();
+        let Ok(query) = parse_query(&list.query, &calendar) else {
+            return Vec::new();
+        };
+        let include_archived = query.mentions_archived();
+        let mut updated: Vec<Entry> = members
+            .iter()
+            .filter(|e| !entry_ids.contains(&e.id))
+            .cloned()
+            .collect();
+        for entry_id in entry_ids {
+            let Some(entry) = self.entries.iter().find(|e| e.id == *entry_id) else {
+                continue;
+            };
+            let matches = (include_archived || entry.status != EntryStatus::Archived)
+                && query.matches_entry(entry, &calendar, &|text| {
+                    self.text_matches(text).contains(&DocKey::Entry(entry.id))
+                });
+            if matches {
+                updated.push(entry.clone());
+            }
+        }
+        updated
+    }
+}
+
+/// Rank changes reach clients as `EntriesReranked`, so they don't count.
+fn same_entry(a: &Entry, b: &Entry) -> bool {
+    Entry {
+        rank: b.rank.clone(),
+        ..a.clone()
+    } == *b
+}
@@ -0,0 +1,321 @@
+use crate::{
+    Sprint, SprintDraft, SprintKind, SprintReport, SprintScope, SprintStatus, TodoState,
+    WsServerMessage,
+};
+
+impl TodoState {
+    fn next_sprint_id(&mut self) -> u64 {
+        // State saved before sprints existed deserializes the counter as 0
+        let id = self.next_sprint_id.max(1);
+        self.next_sprint_id = id + 1;
+        id
+    }
+
+    /// The active sprint, if any. Milestones never count.
+    pub(crate) fn running_sprint(&self) -> Option<&Sprint> {
+        self.sprints
+            .iter()
+            .find(|s| s.kind == SprintKind::Sprint && s.status == SprintStatus::Active)
+    }
+
+    fn find_sprint(&self, sprint_id: u64) -> Result<&Sprint, String> {
+        self.sprints
+            .iter()
+            .find(|s| s.id == sprint_id)
+            .ok_or_else(|| "Sprint not found".to_string())
+    }
+
+    /// Checks that `entry_id` may be saved into `sprint_id`, returning its current sprint.
+    /// Closed sprints take no new entries.
+    pub(crate) fn check_sprint_assignment(
+        &self,
+        entry_id: Option<u64>,
+        sprint_id: Option<u64>,
+    ) -> Result<Option<u64>, String> {
+        let current = entry_id
+            .and_then(|id| self.entries.iter().find(|e| e.id == id))
+            .and_then(|e| e.sprint_id);
+        if let Some(sprint_id) = sprint_id.filter(|id| current != Some(*id)) {
+            let sprint = self.find_sprint(sprint_id)?;
+            if sprint.status == SprintStatus::Closed {
+                return Err(format!("Sprint '{}' is closed.", sprint.name));
+            }
+        }
+        Ok(current)
+    }
+
+    /// Records `entry_id` moving from sprint `before` to sprint `after` in the scope
+    /// changes of whichever of them is running.
+    pub(crate) fn track_sprint_scope(
+        &mut self,
+        entry_id: u64,
+        before: Option<u64>,
+        after: Option<u64>,
+    ) {
+        if before == after {
+            return;
+        }
+        let mut changed = Vec::new();
+        for sprint in &mut self.sprints {
+            if sprint.status != SprintStatus::Active {
+                continue;
+            }
+            if Some(sprint.id) == before {
+                if sprint.added_entry_ids.contains(&entry_id) {
+                    sprint.added_entry_ids.retain(|id| *id != entry_id);
+                } else if sprint.committed_entry_ids.contains(&entry_id) {
+                    sprint.removed_entry_ids.push(entry_id);
+                }
+                changed.push(sprint.clone());
+            } else if Some(sprint.id) == after {
+                if sprint.removed_entry_ids.contains(&entry_id) {
+                    sprint.removed_entry_ids.retain(|id| *id != entry_id);
+                } else if !sprint.committed_entry_ids.contains(&entry_id) {
+                    sprint.added_entry_ids.push(entry_id);
+                }
+                changed.push(sprint.clone());
+            }
+        }
+        for sprint in changed {
+            self.broadcast_sprint(sprint);
+        }
+    }
+
+    pub(crate) fn save_sprint_draft(&mut self, draft: SprintDraft) -> Result<Sprint, String> {
+        if draft.name.trim().is_empty() {
+            return Err("Sprints require a name.".to_string());
+        }
+        if draft.end_ts < draft.start_ts {
+            return Err("A sprint cannot end before it starts.".to_string());
+        }
+
+        let sprint = if let Some(id) = draft.id {
+            let sprint = self
+                .sprints
+                .iter_mut()
+                .find(|s| s.id == id)
+                .ok_or_else(|| "Sprint not found".to_string())?;
+            if sprint.status == SprintStatus::Closed {
+                return Err(format!("Sprint '{}' is closed.", sprint.name));
+            }
+            if sprint.status == SprintStatus::Active && sprint.kind != draft.kind {
+                return Err("A running sprint cannot change kind.".to_string());
+            }
+            sprint.name = draft.name;
+            sprint.goal = draft.goal;
+            sprint.kind = draft.kind;
+            sprint.start_ts = draft.start_ts;
+            sprint.end_ts = draft.end_ts;
+            sprint.clone()
+        } else {
+            let sprint = Sprint {
+                id: self.next_sprint_id(),
+                name: draft.name,
+                goal: draft.goal,
+                kind: draft.kind,
+                start_ts: draft.start_ts,
+                end_ts: draft.end_ts,
+                status: SprintStatus::Planned,
+                committed_entry_ids: Vec::new(),
+                added_entry_ids: Vec::new(),
+                removed_entry_ids: Vec::new(),
+                completed_entry_ids: Vec::new(),
+                carried_over_entry_ids: Vec::new(),
+                carried_over_to: None,
+                closed_ts: None,
+            };
+            self.sprints.push(sprint.clone());
+            sprint
+        };
+
+        self.broadcast_sprint(sprint.clone());
+        if sprint.status == SprintStatus::Active {
+            // A new end date moves the sprint boundary
+            self.refresh_timescales_now();
+        }
+        Ok(sprint)
+    }
+
+    pub(crate) fn begin_sprint(&mut self, sprint_id: u64) -> Result<Sprint, String> {
+        let sprint = self.find_sprint(sprint_id)?;
+        if sprint.status != SprintStatus::Planned {
+            return Err(format!("Sprint '{}' has already started.", sprint.name));
+        }
+        if sprint.kind == SprintKind::Sprint {
+            if let Some(running) = self.running_sprint() {
+                return Err(format!(
+                    "Sprint '{}' is still running; close it first.",
+                    running.name
+                ));
+            }
+        }
+
+        let committed: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|e| e.sprint_id == Some(sprint_id) && !e.is_completed)
+            .map(|e| e.id)
+            .collect();
+        let sprint = self
+            .sprints
+            .iter_mut()
+            .find(|s| s.id == sprint_id)
+            .ok_or_else(|| "Sprint not found".to_string())?;
+        sprint.status = SprintStatus::Active;
+        sprint.committed_entry_ids = committed;
+        let sprint = sprint.clone();
+
+        self.broadcast_sprint(sprint.clone());
+        self.refresh_timescales_now();
+        Ok(sprint)
+    }
+
+    /// Closes a running sprint, freezing what was completed and carrying unfinished
+    /// entries over to `carry_over_to`.
+    pub(crate) fn finish_sprint(
+        &mut self,
+        sprint_id: u64,
+        carry_over_to: Option<u64>,
+    ) -> Result<(), String> {
+        let sprint = self.find_sprint(sprint_id)?;
+        if sprint.status != SprintStatus::Active {
+            return Err(format!("Sprint '{}' is not running.", sprint.name));
+        }
+        if let Some(target_id) = carry_over_to {
+            let target = self.find_sprint(target_id)?;
+            if target_id == sprint_id || target.status == SprintStatus::Closed {
+                return Err(format!(
+                    "Cannot carry entries over to sprint '{}'.",
+                    target.name
+                ));
+            }
+        }
+
+        let members: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|e| e.sprint_id == Some(sprint_id))
+            .map(|e| (e.id, e.is_completed))
+            .collect();
+        let completed: Vec<u64> = members.iter().filter(|m| m.1).map(|m| m.0).collect();
+        let unfinished: Vec<u64> = members.iter().filter(|m| !m.1).map(|m| m.0).collect();
+
+        let calendar = self.calendar();
+        let sprint = self
+            .sprints
+            .iter_mut()
+            .find(|s| s.id == sprint_id)
+            .ok_or_else(|| "Sprint not found".to_string())?;
+        sprint.status = SprintStatus::Closed;
+        sprint.completed_entry_ids = completed;
+        sprint.carried_over_entry_ids = unfinished.clone();
+        sprint.carried_over_to = carry_over_to;
+        sprint.closed_ts = Some(calendar.now_ts());
+        let sprint = sprint.clone();
+        self.broadcast_sprint(sprint);
+
+        for &entry_id in &unfinished {
+            if let Some(entry) = self.entries.iter_mut().find(|e| e.id == entry_id) {
+                entry.sprint_id = carry_over_to;
+            }
+            self.track_sprint_scope(entry_id, Some(sprint_id), carry_over_to);
+        }
+        self.broadcast_moved_entries(&unfinished);
+        Ok(())
+    }
+
+    pub(crate) fn remove_sprint(&mut self, sprint_id: u64) -> Result<(), String> {
+        self.find_sprint(sprint_id)?;
+        let members: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|e| e.sprint_id == Some(sprint_id))
+            .map(|e| e.id)
+            .collect();
+        for entry in &mut self.entries {
+            if entry.sprint_id == Some(sprint_id) {
+                entry.sprint_id = None;
+            }
+        }
+        self.sprints.retain(|s| s.id != sprint_id);
+        self.publish_change(|seq| WsServerMessage::SprintRemoved { sprint_id, seq });
+
+        self.broadcast_moved_entries(&members);
+        Ok(())
+    }
+
+    pub(crate) fn sprint_report(&self, sprint_id: u64) -> Result<SprintReport, String> {
+        let sprint = self.find_sprint(sprint_id)?.clone();
+        let members: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|e| e.sprint_id == Some(sprint_id))
+            .map(|e| e.id)
+            .collect();
+        let (committed, added) = match sprint.status {
+            SprintStatus::Planned => (members, Vec::new()),
+            _ => (
+                sprint.committed_entry_ids.clone(),
+                sprint.added_entry_ids.clone(),
+            ),
+        };
+        Ok(SprintReport {
+            committed: self.sprint_scope(&sprint, committed),
+            added: self.sprint_scope(&sprint, added),
+            removed_entry_ids: sprint.removed_entry_ids.clone(),
+            carried_over_entry_ids: sprint.carried_over_entry_ids.clone(),
+            sprint,
+        })
+    }
+
+    /// Scope of `entry_ids` within `sprint`, leaving out entries it gave up along the way.
+    /// Completion is read live until the sprint closes and frozen afterwards.
+    fn sprint_scope(&self, sprint: &Sprint, entry_ids: Vec<u64>) -> SprintScope {
+        let entry_ids: Vec<u64> = entry_ids
+            .into_iter()
+            .filter(|id| !sprint.removed_entry_ids.contains(id))
+            .collect();
+        let completed = |id: &u64| match sprint.status {
+            SprintStatus::Closed => sprint.completed_entry_ids.contains(id),
+            _ => self
+                .entries
+                .iter()
+                .any(|e| e.id == *id && e.sprint_id == Some(sprint.id) && e.is_completed),
+        };
+        let estimate = |ids: &[u64]| -> u32 {
+            ids.iter()
+                .filter_map(|id| self.entries.iter().find(|e| e.id == *id))
+                .filter_map(|e| e.estimate_minutes)
+                .fold(0u32, u32::saturating_add)
+        };
+        let completed_entry_ids: Vec<u64> = entry_ids.iter().copied().filter(completed).collect();
+        SprintScope {
+            estimate_minutes: estimate(&entry_ids),
+            completed_estimate_minutes: estimate(&completed_entry_ids),
+            entry_ids,
+            completed_entry_ids,
+        }
+    }
+
+    /// Broadcasts entries whose sprint changed together with every entry whose timescale
+    /// moved as a result, each once.
+    fn broadcast_moved_entries(&mut self, moved: &[u64]) {
+        let rebucketed = self.refresh_all_timescales();
+        let extra: Vec<u64> = rebucketed
+            .into_iter()
+            .filter(|id| !moved.contains(id))
+            .collect();
+        for &entry_id in moved {
+            self.index_entry(entry_id);
+            self.broadcast_entry(entry_id);
+        }
+        for entry_id in extra {
+            self.broadcast_entry(entry_id);
+        }
+        self.refresh_live_views();
+    }
+
+    fn broadcast_sprint(&mut self, sprint: Sprint) {
+        self.publish_change(|seq| WsServerMessage::SprintUpdated { sprint, seq });
+    }
+}
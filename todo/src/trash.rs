@@ -0,0 +1,239 @@
+use crate::{
+    refresh_entry_timescale, Entry, Note, SprintStatus, TodoState, TrashItem, TrashKey,
+    TrashedRecord,
+};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+impl TrashItem {
+    fn key(&self) -> TrashKey {
+        match &self.record {
+            TrashedRecord::Entry(entry) => TrashKey::Entry(entry.id),
+            TrashedRecord::Note(note) => TrashKey::Note(note.id),
+        }
+    }
+}
+
+impl TodoState {
+    /// Moves a removed entry to the trash. `deleted_with` names the entry whose cascading
+    /// delete took it along, so restoring that entry brings it back too. Entries that still
+    /// depend on it are remembered before their dependency on it is dropped.
+    pub(crate) fn trash_entry(&mut self, entry: Entry, deleted_with: Option<u64>) {
+        let dependents = self
+            .entries
+            .iter()
+            .filter(|e| e.dependencies.contains(&entry.id))
+            .map(|e| e.id)
+            .collect();
+        self.trash.push(TrashItem {
+            record: TrashedRecord::Entry(entry),
+            deleted_at_ts: self.calendar().now_ts(),
+            deleted_with,
+            dependents,
+        });
+    }
+
+    pub(crate) fn trash_note(&mut self, note: Note) {
+        self.trash.push(TrashItem {
+            record: TrashedRecord::Note(note),
+            deleted_at_ts: self.calendar().now_ts(),
+            deleted_with: None,
+            dependents: Vec::new(),
+        });
+    }
+
+    /// Drops `key` from the trash without restoring it, e.g. when undo recreated the record.
+    pub(crate) fn forget_trashed(&mut self, key: TrashKey) {
+        self.trash.retain(|item| item.key() != key);
+    }
+
+    /// Puts `entry_id` back, along with the subtasks its cascading delete removed, and
+    /// re-links the notes and the dependent entries that still exist. Nothing is restored
+    /// if that would break an enforced WIP limit.
+    pub(crate) fn restore_entry_from_trash(&mut self, entry_id: u64) -> Result<Entry, String> {
+        if !self
+            .trash
+            .iter()
+            .any(|i| i.key() == TrashKey::Entry(entry_id))
+        {
+            return Err("Entry not found in trash".to_string());
+        }
+        let belongs = |item: &TrashItem| {
+            matches!(item.record, TrashedRecord::Entry(_))
+                && (item.key() == TrashKey::Entry(entry_id) || item.deleted_with == Some(entry_id))
+        };
+        // Trash order keeps parents ahead of their subtasks
+        let mut restored = Vec::new();
+        let mut dependents = Vec::new();
+        for item in self.trash.iter().filter(|item| belongs(item)) {
+            if let TrashedRecord::Entry(entry) = &item.record {
+                dependents.extend(
+                    item.dependents
+                        .iter()
+                        .map(|dependent| (*dependent, entry.id)),
+                );
+                restored.push(entry.clone());
+            }
+        }
+
+        let calendar = self.calendar();
+        let ids: Vec<u64> = restored.iter().map(|e| e.id).collect();
+        let existing = self.entries.len();
+        for mut entry in restored {
+            if entry
+                .parent_id
+                .is_some_and(|parent_id| !self.entries.iter().any(|e| e.id == parent_id))
+            {
+                entry.parent_id = None;
+            }
+            entry
+                .dependencies
+                .retain(|dep| ids.contains(dep) || self.entries.iter().any(|e| e.id == *dep));
+            entry
+                .note_ids
+                .retain(|note_id| self.notes.iter().any(|n| n.id == *note_id));
+            if entry.sprint_id.is_some_and(|id| {
+                !self
+                    .sprints
+                    .iter()
+                    .any(|s| s.id == id && s.status != SprintStatus::Closed)
+            }) {
+                entry.sprint_id = None;
+            }
+            // The project may have been merged away or deleted while the entry was in the trash
+            if entry
+                .project_id
+                .is_some_and(|id| !self.projects.iter().any(|p| p.id == id))
+            {
+                entry.project_id = None;
+                entry.project = None;
+            }
+            if let Err(err) = self.check_wip_limits(
+                Some(entry.id),
+                &entry.status,
+                &entry.assignees,
+                entry.is_completed,
+            ) {
+                self.entries.truncate(existing);
+                return Err(err.into_message());
+            }
+            refresh_entry_timescale(&mut entry, &calendar);
+            self.entries.push(entry);
+        }
+        self.trash.retain(|item| !belongs(item));
+
+        for &id in &ids {
+            self.index_entry(id);
+            let note_ids = self
+                .entries
+                .iter()
+                .find(|e| e.id == id)
+                .map(|e| e.note_ids.clone())
+                .unwrap_or_default();
+            for note in self.sync_entry_note_links(id, note_ids) {
+                self.broadcast_note(note.id);
+            }
+            self.refresh_blocked_status(id);
+            let sprint_id = self
+                .entries
+                .iter()
+                .find(|e| e.id == id)
+                .and_then(|e| e.sprint_id);
+            self.track_sprint_scope(id, None, sprint_id);
+            self.sync_entry_state(id);
+        }
+        for &id in ids.iter().rev() {
+            self.refresh_progress_upwards(id);
+        }
+        for &id in &ids {
+            self.broadcast_entry(id);
+        }
+
+        // Dependencies that would now close a cycle stay dropped
+        for (dependent_id, dependency) in dependents {
+            if ids.contains(&dependent_id)
+                || self
+                    .validate_dependencies(Some(dependent_id), &[dependency])
+                    .is_err()
+            {
+                continue;
+            }
+            let Some(dependent) = self
+                .entries
+                .iter_mut()
+                .find(|e| e.id == dependent_id && !e.dependencies.contains(&dependency))
+            else {
+                continue;
+            };
+            dependent.dependencies.push(dependency);
+            self.refresh_blocked_status(dependent_id);
+            self.broadcast_entry(dependent_id);
+        }
+        if let Some(parent_id) = self
+            .entries
+            .iter()
+            .find(|e| e.id == entry_id)
+            .and_then(|e| e.parent_id)
+        {
+            self.broadcast_entry(parent_id);
+        }
+        self.refresh_live_views();
+        self.entries
+            .iter()
+            .find(|e| e.id == entry_id)
+            .cloned()
+            .ok_or_else(|| "Entry not found".to_string())
+    }
+
+    /// Puts `note_id` back and re-links the entries that still exist.
+    pub(crate) fn restore_note_from_trash(&mut self, note_id: u64) -> Result<Note, String> {
+        let idx = self
+            .trash
+            .iter()
+            .position(|i| i.key() == TrashKey::Note(note_id))
+            .ok_or_else(|| "Note not found in trash".to_string())?;
+        let TrashedRecord::Note(mut note) = self.trash.remove(idx).record else {
+            return Err("Note not found in trash".to_string());
+        };
+        note.linked_entry_ids
+            .retain(|entry_id| self.entries.iter().any(|e| e.id == *entry_id));
+        let linked_entry_ids = note.linked_entry_ids.clone();
+        self.notes.push(note);
+
+        self.index_note(note_id);
+        for entry in self.sync_note_entry_links(note_id, linked_entry_ids) {
+            self.broadcast_entry(entry.id);
+        }
+        self.refresh_live_views();
+        self.broadcast_note(note_id)
+            .ok_or_else(|| "Note not found".to_string())
+    }
+
+    /// Deletes `keys` from the trash for good, or everything when `keys` is `None`.
+    pub(crate) fn purge_trash_items(&mut self, keys: Option<Vec<TrashKey>>) -> u32 {
+        self.purge_trash_where(|item| keys.as_ref().is_none_or(|keys| keys.contains(&item.key())))
+    }
+
+    /// Purges items that have been in the trash longer than the retention period.
+    pub(crate) fn purge_expired_trash(&mut self) -> u32 {
+        let cutoff = self.calendar().now_ts() - self.settings.trash_retention_days as i64 * DAY_MS;
+        self.purge_trash_where(|item| item.deleted_at_ts <= cutoff)
+    }
+
+    /// Drops the matching items along with the version history of purged notes.
+    fn purge_trash_where(&mut self, purge: impl Fn(&TrashItem) -> bool) -> u32 {
+        let (purged, kept): (Vec<TrashItem>, Vec<TrashItem>) = std::mem::take(&mut self.trash)
+            .into_iter()
+            .partition(|item| purge(item));
+        self.trash = kept;
+        let note_ids: Vec<u64> = purged
+            .iter()
+            .filter_map(|item| match &item.record {
+                TrashedRecord::Note(note) => Some(note.id),
+                TrashedRecord::Entry(_) => None,
+            })
+            .collect();
+        self.drop_note_versions(&note_ids);
+        purged.len() as u32
+    }
+}
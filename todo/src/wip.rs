@@ -0,0 +1,158 @@
+use crate::{EntrySaveError, EntryStatus, TodoState, WipLimit, WipUsage, WsServerMessage};
+
+impl TodoState {
+    /// Checks the limits an entry moving into `status` with `assignees` would break. Only
+    /// columns the entry is not already counted in are checked, so editing an entry that
+    /// already sits in a full column never fails. Enforced limits turn into an error; the
+    /// rest are returned as warnings.
+    ///
+    /// Saves, reopening, restoring from the trash and unarchiving a project are checked.
+    /// Moves the process makes on its own are exempt and only show up through
+    /// `wip_over_limit`: a dependency hold lifting, and workflow or project changes
+    /// re-syncing entry states.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn check_wip_limits(
+        &self,
+        entry_id: Option<u64>,
+        status: &EntryStatus,
+        assignees: &[String],
+        completed: bool,
+    ) -> Result<Vec<WipUsage>, EntrySaveError> {
+        if completed {
+            return Ok(Vec::new());
+        }
+        let current = entry_id.and_then(|id| self.entries.iter().find(|e| e.id == id));
+        let mut warnings = Vec::new();
+        for limit in self
+            .settings
+            .wip_limits
+            .iter()
+            .filter(|l| l.status == *status)
+        {
+            let scopes: Vec<Option<&str>> = if limit.per_assignee {
+                assignees.iter().map(|a| Some(a.as_str())).collect()
+            } else {
+                vec![None]
+            };
+            for assignee in scopes {
+                let already_counted = current.is_some_and(|entry| {
+                    !entry.is_completed
+                        && entry.status == *status
+                        && assignee.is_none_or(|a| entry.assignees.iter().any(|x| x == a))
+                });
+                if already_counted {
+                    continue;
+                }
+                let usage = self.wip_usage(limit, assignee, entry_id);
+                // Room left for the entry itself
+                if usage.count < usage.max_entries {
+                    continue;
+                }
+                let usage = WipUsage {
+                    count: usage.count + 1,
+                    ..usage
+                };
+                if limit.enforced {
+                    return Err(EntrySaveError::WipLimit(usage));
+                }
+                warnings.push(usage);
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Every limited column currently over its limit.
+    pub(crate) fn wip_over_limit(&self) -> Vec<WipUsage> {
+        let mut over_limit = Vec::new();
+        for limit in &self.settings.wip_limits {
+            let scopes: Vec<Option<&str>> = if limit.per_assignee {
+                let mut assignees: Vec<&str> = self
+                    .entries
+                    .iter()
+                    .filter(|e| !e.is_completed && e.status == limit.status)
+                    .flat_map(|e| e.assignees.iter().map(String::as_str))
+                    .collect();
+                assignees.sort_unstable();
+                assignees.dedup();
+                assignees.into_iter().map(Some).collect()
+            } else {
+                vec![None]
+            };
+            over_limit.extend(
+                scopes
+                    .into_iter()
+                    .map(|assignee| self.wip_usage(limit, assignee, None))
+                    .filter(|usage| usage.count > usage.max_entries),
+            );
+        }
+        over_limit
+    }
+
+    /// Broadcasts `WipStatus` when the set of over-limit columns changed.
+    pub(crate) fn refresh_wip_status(&mut self) {
+        if self.change_log.is_batching() {
+            return;
+        }
+        let over_limit = self.wip_over_limit();
+        if over_limit != self.wip_over_limit {
+            self.wip_over_limit = over_limit.clone();
+            self.broadcast(&WsServerMessage::WipStatus { over_limit });
+        }
+    }
+
+    /// Open entries counted against `limit`, leaving out `excluded`.
+    fn wip_usage(
+        &self,
+        limit: &WipLimit,
+        assignee: Option<&str>,
+        excluded: Option<u64>,
+    ) -> WipUsage {
+        let count = self
+            .entries
+            .iter()
+            .filter(|e| {
+                Some(e.id) != excluded
+                    && !e.is_completed
+                    && e.status == limit.status
+                    && assignee.is_none_or(|a| e.assignees.iter().any(|x| x == a))
+            })
+            .count();
+        WipUsage {
+            status: limit.status.clone(),
+            assignee: assignee.map(str::to_string),
+            count: count as u32,
+            max_entries: limit.max_entries,
+            enforced: limit.enforced,
+        }
+    }
+}
+
+impl WipUsage {
+    pub(crate) fn describe(&self) -> String {
+        let scope = match &self.assignee {
+            Some(assignee) => format!(" for {assignee}"),
+            None => String::new(),
+        };
+        format!(
+            "WIP limit of {} {:?} entries{scope} exceeded ({} in the column).",
+            self.max_entries, self.status, self.count
+        )
+    }
+}
+
+pub(crate) fn validate_wip_limits(limits: &[WipLimit]) -> Result<(), String> {
+    for (idx, limit) in limits.iter().enumerate() {
+        if limit.max_entries == 0 {
+            return Err("WIP limits must allow at least one entry.".to_string());
+        }
+        if limits[..idx]
+            .iter()
+            .any(|l| l.status == limit.status && l.per_assignee == limit.per_assignee)
+        {
+            return Err(
+                "Each status takes at most one column and one per-assignee limit.".to_string(),
+            );
+        }
+    }
+    Ok(())
+}
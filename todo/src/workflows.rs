@@ -0,0 +1,440 @@
+use std::collections::HashSet;
+
+use crate::{
+    Entry, EntryStatus, TodoState, Workflow, WorkflowCategory, WorkflowDraft, WorkflowState,
+    WsServerMessage,
+};
+
+pub(crate) const DEFAULT_WORKFLOW_ID: u64 = 1;
+
+pub(crate) fn default_workflows() -> Vec<Workflow> {
+    let states = [
+        (EntryStatus::Backlog, "Backlog"),
+        (EntryStatus::UpNext, "Up next"),
+        (EntryStatus::InProgress, "In progress"),
+        (EntryStatus::Blocked, "Blocked"),
+        (EntryStatus::Review, "Review"),
+        (EntryStatus::Done, "Done"),
+        (EntryStatus::Archived, "Archived"),
+    ]
+    .into_iter()
+    .map(|(status, name)| WorkflowState {
+        key: status_key(&status).to_string(),
+        name: name.to_string(),
+        category: status_category(&status),
+    })
+    .collect();
+    vec![Workflow {
+        id: DEFAULT_WORKFLOW_ID,
+        name: "Default".to_string(),
+        states,
+        transitions: Vec::new(),
+    }]
+}
+
+fn status_key(status: &EntryStatus) -> &'static str {
+    match status {
+        EntryStatus::Backlog => "Backlog",
+        EntryStatus::UpNext => "UpNext",
+        EntryStatus::InProgress => "InProgress",
+        EntryStatus::Blocked => "Blocked",
+        EntryStatus::Review => "Review",
+        EntryStatus::Done => "Done",
+        EntryStatus::Archived => "Archived",
+    }
+}
+
+fn status_category(status: &EntryStatus) -> WorkflowCategory {
+    match status {
+        EntryStatus::Backlog | EntryStatus::UpNext => WorkflowCategory::Todo,
+        EntryStatus::InProgress | EntryStatus::Blocked | EntryStatus::Review => {
+            WorkflowCategory::Active
+        }
+        EntryStatus::Done => WorkflowCategory::Done,
+        EntryStatus::Archived => WorkflowCategory::Archived,
+    }
+}
+
+/// The built-in status an entry in `state` reports as: the status of the same key when the
+/// categories agree, otherwise the plainest status of its category.
+pub(crate) fn state_status(state: &WorkflowState) -> EntryStatus {
+    let statuses = [
+        EntryStatus::Backlog,
+        EntryStatus::UpNext,
+        EntryStatus::InProgress,
+        EntryStatus::Blocked,
+        EntryStatus::Review,
+        EntryStatus::Done,
+        EntryStatus::Archived,
+    ];
+    statuses
+        .into_iter()
+        .find(|status| status_key(status) == state.key && status_category(status) == state.category)
+        .unwrap_or(match state.category {
+            WorkflowCategory::Todo => EntryStatus::Backlog,
+            WorkflowCategory::Active => EntryStatus::InProgress,
+            WorkflowCategory::Done => EntryStatus::Done,
+            WorkflowCategory::Archived => EntryStatus::Archived,
+        })
+}
+
+/// Puts `entry` in `state`, dropping any dependency hold; callers re-check dependencies.
+/// Leaving the state also forgets where archiving the project took the entry from.
+pub(crate) fn set_entry_state(entry: &mut Entry, state: &WorkflowState) {
+    if entry.state != state.key {
+        entry.archived_from = None;
+    }
+    entry.state = state.key.clone();
+    entry.status = state_status(state);
+    entry.blocked_from = None;
+}
+
+impl Workflow {
+    pub(crate) fn state(&self, key: &str) -> Option<&WorkflowState> {
+        self.states.iter().find(|s| s.key == key)
+    }
+
+    fn allows(&self, from: &str, to: &str) -> bool {
+        from == to
+            || self.transitions.is_empty()
+            || self
+                .transitions
+                .iter()
+                .any(|t| t.from == from && t.to == to)
+    }
+
+    fn first_in(&self, category: WorkflowCategory) -> Option<&WorkflowState> {
+        self.states.iter().find(|s| s.category == category)
+    }
+
+    /// The state a built-in `status` maps to: the state keyed by it, else the first state of
+    /// its category. Every workflow has to-do and done states and active work falls back to
+    /// to-do, so only `Archived` can come up empty.
+    pub(crate) fn state_for_status(&self, status: &EntryStatus) -> Option<&WorkflowState> {
+        let category = status_category(status);
+        self.states
+            .iter()
+            .find(|s| s.key == status_key(status) && s.category == category)
+            .or_else(|| self.first_in(category))
+            .or_else(|| {
+                (category == WorkflowCategory::Active)
+                    .then(|| self.first_in(WorkflowCategory::Todo))
+                    .flatten()
+            })
+    }
+}
+
+impl TodoState {
+    fn next_workflow_id(&mut self) -> u64 {
+        // State saved before workflows existed deserializes the counter as 0
+        let id = self.next_workflow_id.max(DEFAULT_WORKFLOW_ID + 1);
+        self.next_workflow_id = id + 1;
+        id
+    }
+
+    pub(crate) fn workflow_for(&self, workflow_id: Option<u64>) -> &Workflow {
+        let id = workflow_id.unwrap_or(DEFAULT_WORKFLOW_ID);
+        self.workflows
+            .iter()
+            .find(|w| w.id == id)
+            .or_else(|| self.workflows.iter().find(|w| w.id == DEFAULT_WORKFLOW_ID))
+            .expect("the default workflow is always present")
+    }
+
+    /// The workflow of entries in `project_id`.
+    pub(crate) fn entry_workflow(&self, project_id: Option<u64>) -> &Workflow {
+        let workflow_id = project_id
+            .and_then(|id| self.projects.iter().find(|p| p.id == id))
+            .and_then(|p| p.workflow_id);
+        self.workflow_for(workflow_id)
+    }
+
+    /// Gives every entry saved before workflows existed the default state of its status.
+    pub(crate) fn migrate_entry_states(&mut self) {
+        if !self.workflows.iter().any(|w| w.id == DEFAULT_WORKFLOW_ID) {
+            self.workflows.splice(0..0, default_workflows());
+        }
+        let ids: Vec<u64> = self.entries.iter().map(|e| e.id).collect();
+        for id in ids {
+            self.sync_entry_state(id);
+        }
+    }
+
+    /// Works out the state a saved entry moves to. A requested `state` wins when it differs
+    /// from the current one; otherwise a changed `status` picks its matching state. Moves
+    /// within one workflow must be allowed transitions.
+    pub(crate) fn resolve_entry_state(
+        &self,
+        entry_id: Option<u64>,
+        workflow_id: Option<u64>,
+        state: Option<&str>,
+        status: &EntryStatus,
+    ) -> Result<WorkflowState, String> {
+        let workflow = self.workflow_for(workflow_id);
+        let current = entry_id.and_then(|id| self.entries.iter().find(|e| e.id == id));
+        let requested = state.filter(|key| current.is_none_or(|entry| entry.state != *key));
+        let target = if let Some(key) = requested {
+            workflow
+                .state(key)
+                .ok_or_else(|| format!("Workflow '{}' has no state '{key}'.", workflow.name))?
+        } else if let Some(entry) = current.filter(|entry| entry.status == *status) {
+            // A dependency hold shows as `Blocked` without being the entry's state
+            let status = entry.blocked_from.as_ref().unwrap_or(status);
+            match workflow.state(&entry.state) {
+                Some(state) => state,
+                None => workflow.state_for_status(status).ok_or_else(|| {
+                    format!("Workflow '{}' has no archived state.", workflow.name)
+                })?,
+            }
+        } else {
+            workflow
+                .state_for_status(status)
+                .ok_or_else(|| format!("Workflow '{}' has no archived state.", workflow.name))?
+        };
+
+        if let Some(entry) = current {
+            let same_workflow = self.entry_workflow(entry.project_id).id == workflow.id;
+            if same_workflow && !workflow.allows(&entry.state, &target.key) {
+                return Err(format!(
+                    "Workflow '{}' does not allow moving from '{}' to '{}'.",
+                    workflow.name,
+                    workflow
+                        .state(&entry.state)
+                        .map_or(entry.state.as_str(), |s| s.name.as_str()),
+                    target.name
+                ));
+            }
+        }
+        Ok(target.clone())
+    }
+
+    /// The state toggling completion moves `entry_id` to, or `None` when it already sits in
+    /// a state of the right kind. Only done entries move when reopened.
+    pub(crate) fn completion_state(
+        &self,
+        entry_id: u64,
+        completed: bool,
+    ) -> Result<Option<WorkflowState>, String> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|e| e.id == entry_id)
+            .ok_or_else(|| "Entry not found".to_string())?;
+        let workflow = self.entry_workflow(entry.project_id);
+        let is_done = workflow
+            .state(&entry.state)
+            .is_some_and(|s| s.category == WorkflowCategory::Done);
+        if is_done == completed {
+            return Ok(None);
+        }
+
+        let (preferred, categories) = if completed {
+            (EntryStatus::Done, vec![WorkflowCategory::Done])
+        } else {
+            (
+                EntryStatus::UpNext,
+                vec![WorkflowCategory::Todo, WorkflowCategory::Active],
+            )
+        };
+        workflow
+            .state_for_status(&preferred)
+            .into_iter()
+            .chain(
+                categories.iter().flat_map(|category| {
+                    workflow.states.iter().filter(|s| s.category == *category)
+                }),
+            )
+            .find(|s| workflow.allows(&entry.state, &s.key))
+            .map(|s| Some(s.clone()))
+            .ok_or_else(|| {
+                format!(
+                    "Workflow '{}' has no allowed move to {} state.",
+                    workflow.name,
+                    if completed { "a done" } else { "an open" }
+                )
+            })
+    }
+
+    /// Brings `entry_id` back in line with its workflow after the workflow or the entry's
+    /// project changed: a state the workflow lacks is replaced by the closest match, the
+    /// status follows the state, and completion follows a done state. Returns whether
+    /// anything changed.
+    pub(crate) fn sync_entry_state(&mut self, entry_id: u64) -> bool {
+        let Some(entry) = self.entries.iter().find(|e| e.id == entry_id) else {
+            return false;
+        };
+        let workflow = self.entry_workflow(entry.project_id);
+        let state = match workflow.state(&entry.state) {
+            Some(state) => state,
+            None => {
+                let status = match (entry.is_completed, entry.blocked_from.as_ref()) {
+                    (true, _) => &EntryStatus::Done,
+                    (false, Some(previous)) => previous,
+                    // Reopening used to leave entries marked done
+                    (false, None) if entry.status == EntryStatus::Done => &EntryStatus::UpNext,
+                    (false, None) => &entry.status,
+                };
+                match workflow.state_for_status(status) {
+                    Some(state) => state,
+                    None => workflow
+                        .state_for_status(&EntryStatus::Backlog)
+                        .expect("workflows always have a to-do state"),
+                }
+            }
+        };
+        let base_status = entry.blocked_from.as_ref().unwrap_or(&entry.status);
+        if state.key == entry.state && state_status(state) == *base_status {
+            let done = state.category == WorkflowCategory::Done;
+            if done == entry.is_completed {
+                return false;
+            }
+        }
+
+        let state = state.clone();
+        let completed = state.category == WorkflowCategory::Done;
+        let Some(entry) = self.entries.iter_mut().find(|e| e.id == entry_id) else {
+            return false;
+        };
+        set_entry_state(entry, &state);
+        if entry.is_completed != completed {
+            self.apply_completion(entry_id, completed);
+        } else {
+            self.refresh_blocked_status(entry_id);
+        }
+        true
+    }
+
+    pub(crate) fn save_workflow_draft(&mut self, draft: WorkflowDraft) -> Result<Workflow, String> {
+        if draft.id == Some(DEFAULT_WORKFLOW_ID) {
+            return Err("The default workflow cannot be changed.".to_string());
+        }
+        validate_workflow(&draft)?;
+        let mut transitions = Vec::new();
+        for transition in draft.transitions {
+            if !transitions.contains(&transition) {
+                transitions.push(transition);
+            }
+        }
+
+        let workflow = if let Some(id) = draft.id {
+            let project_ids: Vec<u64> = self
+                .projects
+                .iter()
+                .filter(|p| p.workflow_id == Some(id))
+                .map(|p| p.id)
+                .collect();
+            let in_use = self.entries.iter().find(|e| {
+                e.project_id.is_some_and(|p| project_ids.contains(&p))
+                    && !draft.states.iter().any(|s| s.key == e.state)
+            });
+            if let Some(entry) = in_use {
+                return Err(format!(
+                    "State '{}' is still used by entry {}.",
+                    entry.state, entry.id
+                ));
+            }
+            let workflow = self
+                .workflows
+                .iter_mut()
+                .find(|w| w.id == id)
+                .ok_or_else(|| "Workflow not found".to_string())?;
+            workflow.name = draft.name;
+            workflow.states = draft.states;
+            workflow.transitions = transitions;
+            workflow.clone()
+        } else {
+            let workflow = Workflow {
+                id: self.next_workflow_id(),
+                name: draft.name,
+                states: draft.states,
+                transitions,
+            };
+            self.workflows.push(workflow.clone());
+            workflow
+        };
+
+        self.publish_change(|seq| WsServerMessage::WorkflowUpdated {
+            workflow: workflow.clone(),
+            seq,
+        });
+        // States may have changed category
+        let member_ids: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|e| self.entry_workflow(e.project_id).id == workflow.id)
+            .map(|e| e.id)
+            .collect();
+        self.sync_entry_states(&member_ids);
+        Ok(workflow)
+    }
+
+    pub(crate) fn remove_workflow(&mut self, workflow_id: u64) -> Result<(), String> {
+        if workflow_id == DEFAULT_WORKFLOW_ID {
+            return Err("The default workflow cannot be deleted.".to_string());
+        }
+        if !self.workflows.iter().any(|w| w.id == workflow_id) {
+            return Err("Workflow not found".to_string());
+        }
+        if let Some(project) = self
+            .projects
+            .iter()
+            .find(|p| p.workflow_id == Some(workflow_id))
+        {
+            return Err(format!(
+                "Workflow is still used by project '{}'.",
+                project.name
+            ));
+        }
+        self.workflows.retain(|w| w.id != workflow_id);
+        self.publish_change(|seq| WsServerMessage::WorkflowRemoved { workflow_id, seq });
+        Ok(())
+    }
+
+    /// Syncs and broadcasts each of `entry_ids` whose state had to change.
+    pub(crate) fn sync_entry_states(&mut self, entry_ids: &[u64]) {
+        let changed: Vec<u64> = entry_ids
+            .iter()
+            .copied()
+            .filter(|id| self.sync_entry_state(*id))
+            .collect();
+        for entry_id in &changed {
+            self.refresh_progress_upwards(*entry_id);
+            self.index_entry(*entry_id);
+            self.broadcast_entry(*entry_id);
+        }
+        if !changed.is_empty() {
+            self.refresh_live_views();
+        }
+    }
+}
+
+fn validate_workflow(draft: &WorkflowDraft) -> Result<(), String> {
+    if draft.name.trim().is_empty() {
+        return Err("Workflows require a name.".to_string());
+    }
+    let mut keys = HashSet::new();
+    for state in &draft.states {
+        if state.key.trim().is_empty() || state.name.trim().is_empty() {
+            return Err("Workflow states require a key and a name.".to_string());
+        }
+        if !keys.insert(state.key.as_str()) {
+            return Err(format!("State '{}' appears twice.", state.key));
+        }
+    }
+    for category in [WorkflowCategory::Todo, WorkflowCategory::Done] {
+        if !draft.states.iter().any(|s| s.category == category) {
+            return Err("Workflows need at least one to-do and one done state.".to_string());
+        }
+    }
+    if let Some(transition) = draft
+        .transitions
+        .iter()
+        .find(|t| !keys.contains(t.from.as_str()) || !keys.contains(t.to.as_str()))
+    {
+        return Err(format!(
+            "Transition '{}' -> '{}' names an unknown state.",
+            transition.from, transition.to
+        ));
+    }
+    Ok(())
+}
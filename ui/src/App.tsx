@@ -46,6 +46,7 @@ function App() {
     entries,
     notes,
     error,
+    wipWarning,
     activeView,
     selectedEntryId,
     selectedNoteId,
@@ -69,6 +70,7 @@ function App() {
     setEntryEditMode,
     setNoteEditorTab,
     setError,
+    setWipWarning,
   } = useTodoStore();
   const [chatResetToken, setChatResetToken] = useState(0);
   const [showArchiveModal, setShowArchiveModal] = useState(false);
@@ -118,6 +120,12 @@ function App() {
           <button onClick={() => setError(null)}>Dismiss</button>
         </div>
       )}
+      {wipWarning && (
+        <div className="alert warning-alert">
+          <span>{wipWarning}</span>
+          <button onClick={() => setWipWarning(null)}>Dismiss</button>
+        </div>
+      )}
 
       <main className={`app-main ${activeView === 'chat' ? 'chat-mode' : ''}`}>
         <div style={{ display: activeView === 'chat' ? 'contents' : 'none' }}>
@@ -1175,8 +1183,18 @@ function entryToDraft(entry: Entry): BackendTodo.EntryDraft {
     due_ts: entry.due_ts,
     start_ts: entry.start_ts,
     dependencies: entry.dependencies,
+    parent_id: entry.parent_id,
     note_ids: entry.note_ids,
     assignees: entry.assignees,
+    recurrence: entry.recurrence,
+    series_scope: null,
+    estimate_minutes: entry.estimate_minutes,
+    tags: entry.tags,
+    expected_revision: entry.revision,
+    // Let the server resolve `project` by name so edits to the field take effect
+    project_id: null,
+    sprint_id: entry.sprint_id,
+    state: null,
   };
 }
 
@@ -35,6 +35,7 @@ type ServerMessage =
   | { type: 'snapshot'; entries: Entry[]; notes: Note[] }
   | { type: 'entryUpdated'; entry: Entry }
   | { type: 'entryRemoved'; entryId: number }
+  | { type: 'entriesReranked'; listId: number | null; ranks: Todo.EntryRank[] }
   | { type: 'noteUpdated'; note: Note }
   | { type: 'noteRemoved'; noteId: number };
 
@@ -54,6 +55,8 @@ interface TodoStore {
   notes: Note[];
   isLoading: boolean;
   error: string | null;
+  // Set by the last entry save that went over a WIP limit the server does not enforce
+  wipWarning: string | null;
   isPublicMode: boolean;
   activeView: ViewName;
   selectedEntryId: number | null;
@@ -80,9 +83,12 @@ interface TodoStore {
   deleteEntry: (entryId: number) => Promise<void>;
   archiveEntry: (entryId: number) => Promise<void>;
   setError: (error: string | null) => void;
+  setWipWarning: (warning: string | null) => void;
 }
 
 let wsClient: HyperwareClientApi | null = null;
+// Scopes server-side undo/redo history to this tab
+const SESSION_ID = crypto.randomUUID();
 
 export const useTodoStore = create<TodoStore>((set, get) => ({
   nodeId: null,
@@ -92,6 +98,7 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
   notes: [],
   isLoading: false,
   error: null,
+  wipWarning: null,
   isPublicMode: false,
   activeView: 'chat',
   selectedEntryId: null,
@@ -191,7 +198,7 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
   toggleEntryCompletion: async (entryId, completed) => {
     set({ isLoading: true });
     try {
-      const updated = await Todo.toggle_entry_completion(entryId, completed);
+      const updated = await Todo.toggle_entry_completion(entryId, completed, SESSION_ID);
       set((state) => ({
         entries: upsertEntry(state.entries, updated),
         isLoading: false,
@@ -204,10 +211,11 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
   saveEntry: async (draft) => {
     set({ isLoading: true });
     try {
-      const updated = await Todo.save_entry(draft);
+      const saved = await Todo.save_entry(draft, SESSION_ID);
       set((state) => ({
-        entries: upsertEntry(state.entries, updated),
+        entries: upsertEntry(state.entries, saved.entry),
         isLoading: false,
+        wipWarning: describeWipUsages(saved.wip_warnings),
       }));
     } catch (error) {
       set({ error: extractErrorMessage(error), isLoading: false });
@@ -230,10 +238,19 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
         due_ts: null,
         start_ts: null,
         dependencies: [],
+        parent_id: null,
         note_ids: [],
         assignees: [],
+        recurrence: null,
+        series_scope: null,
+        estimate_minutes: null,
+        tags: [],
+        expected_revision: null,
+        project_id: null,
+        sprint_id: null,
+        state: null,
       };
-      const created = await Todo.save_entry(draft);
+      const { entry: created } = await Todo.save_entry(draft, SESSION_ID);
       set((state) => ({
         entries: upsertEntry(state.entries, created),
         selectedEntryId: created.id,
@@ -255,7 +272,8 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
         tags: [],
         linked_entry_ids: [],
         accent: null,
-      });
+        expected_revision: null,
+      }, SESSION_ID);
       set((state) => ({
         notes: upsertNote(state.notes, newNote),
         selectedNoteId: newNote.id,
@@ -282,7 +300,8 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
         tags: note.tags,
         linked_entry_ids: note.linked_entry_ids,
         accent: note.accent,
-      });
+        expected_revision: note.revision,
+      }, SESSION_ID);
       set((state) => ({
         notes: upsertNote(state.notes, updated),
         entries: syncEntriesWithNote(state.entries, updated),
@@ -307,7 +326,8 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
         tags: meta.tags,
         linked_entry_ids: meta.linkedEntryIds,
         accent: meta.accent ?? note.accent,
-      });
+        expected_revision: note.revision,
+      }, SESSION_ID);
       set((state) => ({
         notes: upsertNote(state.notes, updated),
         entries: syncEntriesWithNote(state.entries, updated),
@@ -319,7 +339,7 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
 
   deleteNote: async (noteId) => {
     try {
-      await Todo.delete_note(noteId);
+      await Todo.delete_note(noteId, SESSION_ID);
       set((state) => ({
         notes: state.notes.filter((n) => n.id !== noteId),
         selectedNoteId: state.selectedNoteId === noteId ? null : state.selectedNoteId,
@@ -332,7 +352,7 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
 
   deleteEntry: async (entryId) => {
     try {
-      await Todo.delete_entry(entryId);
+      await Todo.delete_entry(entryId, null, SESSION_ID);
       set((state) => ({
         entries: state.entries.filter((e) => e.id !== entryId),
         selectedEntryId: state.selectedEntryId === entryId ? null : state.selectedEntryId,
@@ -366,10 +386,19 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
         due_ts: entry.due_ts,
         start_ts: entry.start_ts,
         dependencies: entry.dependencies,
+        parent_id: entry.parent_id,
         note_ids: entry.note_ids,
         assignees: entry.assignees,
+        recurrence: entry.recurrence,
+        series_scope: null,
+        estimate_minutes: entry.estimate_minutes,
+        tags: entry.tags,
+        expected_revision: entry.revision,
+        project_id: null,
+        sprint_id: entry.sprint_id,
+        state: null,
       };
-      await Todo.save_entry(draft);
+      await Todo.save_entry(draft, SESSION_ID);
     } catch (error) {
       // Revert on error
       set((state) => ({
@@ -382,6 +411,7 @@ export const useTodoStore = create<TodoStore>((set, get) => ({
   },
 
   setError: (error) => set({ error }),
+  setWipWarning: (warning) => set({ wipWarning: warning }),
 }));
 
 function extractErrorMessage(error: unknown): string {
@@ -447,25 +477,45 @@ const timescaleRank: Record<Todo.EntryTimescale, number> = {
   [Todo.EntryTimescale.Overdue]: 0,
   [Todo.EntryTimescale.Today]: 1,
   [Todo.EntryTimescale.ThisWeek]: 2,
-  [Todo.EntryTimescale.ThisMonth]: 3,
-  [Todo.EntryTimescale.Later]: 4,
-  [Todo.EntryTimescale.Someday]: 5,
-  [Todo.EntryTimescale.Completed]: 6,
+  [Todo.EntryTimescale.ThisSprint]: 3,
+  [Todo.EntryTimescale.ThisMonth]: 4,
+  [Todo.EntryTimescale.Later]: 5,
+  [Todo.EntryTimescale.Someday]: 6,
+  [Todo.EntryTimescale.Completed]: 7,
 };
 
+// Within a timescale, entries keep the manual order set through `move_entry`
 function sortEntries(entries: Entry[]): Entry[] {
   return [...entries].sort((a, b) => {
     const rankDelta = timescaleRank[a.timescale] - timescaleRank[b.timescale];
     if (rankDelta !== 0) return rankDelta;
-    if (a.due_ts && b.due_ts) {
-      return a.due_ts - b.due_ts;
+    if (a.rank !== b.rank) {
+      return a.rank < b.rank ? -1 : 1;
     }
-    if (a.due_ts && !b.due_ts) return -1;
-    if (!a.due_ts && b.due_ts) return 1;
     return a.id - b.id;
   });
 }
 
+function applyRanks(entries: Entry[], ranks: Todo.EntryRank[]): Entry[] {
+  const byId = new Map(ranks.map((item) => [item.entry_id, item.rank]));
+  return sortEntries(
+    entries.map((entry) => {
+      const rank = byId.get(entry.id);
+      return rank === undefined ? entry : { ...entry, rank };
+    }),
+  );
+}
+
+function describeWipUsages(usages: Todo.WipUsage[]): string | null {
+  if (usages.length === 0) return null;
+  return usages
+    .map((usage) => {
+      const who = usage.assignee ? ` for ${usage.assignee}` : '';
+      return `WIP limit of ${usage.max_entries} ${usage.status} entries${who} exceeded (${usage.count} in the column).`;
+    })
+    .join(' ');
+}
+
 function sortNotes(notes: Note[]): Note[] {
   return [...notes].sort((a, b) => {
     if (a.pinned !== b.pinned) {
@@ -488,6 +538,13 @@ function applyRealtime(message: ServerMessage, set: (fn: (state: TodoStore) => P
         entries: upsertEntry(state.entries, message.entry),
       }));
       break;
+    case 'entriesReranked':
+      // Smart list orders live with the lists, which this store does not hold
+      if (message.listId !== null) break;
+      set((state) => ({
+        entries: applyRanks(state.entries, message.ranks),
+      }));
+      break;
     case 'entryRemoved':
       set((state) => ({
         entries: state.entries.filter((entry) => entry.id !== message.entryId),
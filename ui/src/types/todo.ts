@@ -30,9 +30,15 @@ export const TIMESCALES: TimescaleDefinition[] = [
   {
     key: BackendTodo.EntryTimescale.ThisWeek,
     label: 'This week',
-    blurb: 'Still in the sprint window',
+    blurb: 'Due before the week is out',
     accent: 'var(--amber-500)',
   },
+  {
+    key: BackendTodo.EntryTimescale.ThisSprint,
+    label: 'This sprint',
+    blurb: 'Planned into the current sprint',
+    accent: 'var(--teal-500)',
+  },
   {
     key: BackendTodo.EntryTimescale.ThisMonth,
     label: 'This month',